@@ -1,5 +1,13 @@
 GRANT ALL ON SCHEMA paradedb TO PUBLIC;
 
+-- Synonym sets that can be referenced by group from the synonyms option of a text field.
+CREATE TABLE IF NOT EXISTS paradedb.synonyms (
+    group_name TEXT NOT NULL,
+    terms TEXT[] NOT NULL
+);
+SELECT pg_catalog.pg_extension_config_dump('paradedb.synonyms', '');
+GRANT SELECT ON paradedb.synonyms TO PUBLIC;
+
 CREATE OR REPLACE PROCEDURE paradedb.create_bm25_test_table(table_name VARCHAR DEFAULT 'bm25_test_table', schema_name VARCHAR DEFAULT 'paradedb')
 LANGUAGE plpgsql
 AS $$
@@ -99,6 +107,7 @@ END $$;
 --   numeric_fields: JSON object representing the numeric fields for the index.
 --   boolean_fields: JSON object representing the boolean fields for the index.
 --   json_fields: JSON object representing the json fields for the index.
+--   merge_policy: JSON object representing how the segments of the index are merged.
 CREATE OR REPLACE PROCEDURE paradedb.create_bm25(
     index_name text DEFAULT '',
     table_name text DEFAULT '',
@@ -107,11 +116,11 @@ CREATE OR REPLACE PROCEDURE paradedb.create_bm25(
     text_fields text DEFAULT '{}',
     numeric_fields text DEFAULT '{}',
     boolean_fields text DEFAULT '{}',
-    json_fields text DEFAULT '{}'
+    json_fields text DEFAULT '{}',
+    merge_policy text DEFAULT ''
 )
 LANGUAGE plpgsql AS $$
 DECLARE
-    index_json JSONB;
     original_client_min_messages TEXT;
 BEGIN
     SELECT INTO original_client_min_messages current_setting('client_min_messages');
@@ -133,13 +142,6 @@ BEGIN
         RAISE EXCEPTION 'no text_fields, numeric_fields, boolean_fields, or json_fields were specified for index %', index_name;
     END IF;
 
-    index_json := jsonb_build_object(
-        'index_name', format('%s_bm25_index', index_name),
-        'table_name', table_name,
-        'key_field', key_field,
-        'schema_name', schema_name
-    );
-
     -- Drop any existing index and function with the same name to avoid conflicts.
     CALL paradedb.drop_bm25(index_name, schema_name => schema_name);
 
@@ -148,8 +150,33 @@ BEGIN
 
     -- Create a new BM25 index on the specified table.
     -- The index is created dynamically based on the function parameters.
-    EXECUTE format('CREATE INDEX %s_bm25_index ON %I.%I USING bm25 ((%I.*)) WITH (key_field=%L, text_fields=%L, numeric_fields=%L, boolean_fields=%L, json_fields=%L);',
-                   index_name, schema_name, table_name, table_name, key_field, text_fields, numeric_fields, boolean_fields, json_fields);
+    EXECUTE format('CREATE INDEX %s_bm25_index ON %I.%I USING bm25 ((%I.*)) WITH (key_field=%L, text_fields=%L, numeric_fields=%L, boolean_fields=%L, json_fields=%L, merge_policy=%L);',
+                   index_name, schema_name, table_name, table_name, key_field, text_fields, numeric_fields, boolean_fields, json_fields, merge_policy);
+
+    CALL paradedb.create_bm25_functions(index_name, table_name, key_field, schema_name);
+
+    EXECUTE 'SET client_min_messages TO ' || quote_literal(original_client_min_messages);
+   END;
+$$;
+
+-- This procedure creates the search functions of a BM25 index in the schema named after it.
+-- It's called again with the new names when the index, or the table it indexes, is renamed.
+CREATE OR REPLACE PROCEDURE paradedb.create_bm25_functions(
+    index_name text,
+    table_name text,
+    key_field text,
+    schema_name text
+)
+LANGUAGE plpgsql AS $$
+DECLARE
+    index_json JSONB;
+BEGIN
+    index_json := jsonb_build_object(
+        'index_name', format('%s_bm25_index', index_name),
+        'table_name', table_name,
+        'key_field', key_field,
+        'schema_name', schema_name
+    );
 
     -- Dynamically create a new function for performing searches on the indexed table.
     -- The variable '__paradedb_search_config__' is available to the function_body parameter.
@@ -165,55 +192,37 @@ BEGIN
 
     EXECUTE paradedb.format_bm25_function(
         function_name => format('%I.highlight', index_name),
-        return_type => format('TABLE(%s bigint, highlight_bm25 text)', key_field),
+        return_type => format('TABLE(%s bigint, highlight_bm25 text, highlight_field text)', key_field),
         function_body => 'RETURN QUERY SELECT * FROM paradedb.highlight_bm25(__paradedb_search_config__);',
         index_json => index_json
     );
 
+    EXECUTE paradedb.format_bm25_function(
+        function_name => format('%I.highlight_offsets', index_name),
+        return_type => format('TABLE(%s bigint, highlight_field text, fragment text, offsets int4range[])', key_field),
+        function_body => 'RETURN QUERY SELECT * FROM paradedb.highlight_offsets_bm25(__paradedb_search_config__);',
+        index_json => index_json
+    );
+
     EXECUTE paradedb.format_bm25_function(
         function_name => format('%I.rank', index_name),
-        return_type => format('TABLE(%s bigint, rank_bm25 real)', key_field),
+        return_type => format('TABLE(%s bigint, rank_bm25 real, cursor text)', key_field),
         function_body => 'RETURN QUERY SELECT * FROM paradedb.rank_bm25(__paradedb_search_config__);',
         index_json => index_json
     );
 
     EXECUTE paradedb.format_empty_function(
         function_name => format('%I.schema', index_name),
-        return_type => 'TABLE(name text, field_type text, stored bool, indexed bool, fast bool, fieldnorms bool, expand_dots bool, tokenizer text, record text, normalizer text)',
+        return_type => 'TABLE(name text, field_type text, stored bool, indexed bool, fast bool, fieldnorms bool, expand_dots bool, tokenizer text, record text, normalizer text, filters text[])',
         function_body => format('RETURN QUERY SELECT * FROM paradedb.schema_bm25(''%s'');', index_name)
     );
 
     EXECUTE paradedb.format_hybrid_function(
         function_name => format('%I.rank_hybrid', index_name),
         return_type => format('TABLE(%s bigint, rank_hybrid real)', key_field),
-        function_body => '
-            WITH similarity AS (
-                SELECT
-                    __key_field__ as key_field,
-                    1 - ((__similarity_query__) - MIN(__similarity_query__) OVER ()) / 
-                    (MAX(__similarity_query__) OVER () - MIN(__similarity_query__) OVER ()) AS score
-                FROM %I
-                ORDER BY __similarity_query__
-                LIMIT $2
-            ),
-            bm25 AS (
-                SELECT 
-                    __key_field__ as key_field, 
-                    rank_bm25 as score 
-                FROM paradedb.minmax_bm25($1)
-            )
-            SELECT
-                COALESCE(similarity.key_field, bm25.key_field) AS __key_field__,
-                (COALESCE(similarity.score, 0.0) * $3 + COALESCE(bm25.score, 0.0) * $4)::real AS score_hybrid
-            FROM similarity
-            FULL OUTER JOIN bm25 ON similarity.key_field = bm25.key_field
-            ORDER BY score_hybrid DESC;
-        ',
         index_json => index_json
     );
-
-    EXECUTE 'SET client_min_messages TO ' || quote_literal(original_client_min_messages);
-   END;
+END;
 $$;
 
 -- A helper function to format a search query. The "template" below is used by several
@@ -239,7 +248,15 @@ BEGIN
             prefix boolean DEFAULT NULL, -- Prefix parameter for searches
             regex_fields text DEFAULT NULL, -- Fields where regex search is applied
             max_num_chars integer DEFAULT NULL, -- Maximum character limit for searches
-            highlight_field text DEFAULT NULL -- Field name to highlight (highlight func only)
+            highlight_field text DEFAULT NULL, -- Fields to highlight (highlight funcs only)
+            highlight_pre_tag text DEFAULT NULL, -- Tag inserted before each highlighted match
+            highlight_post_tag text DEFAULT NULL, -- Tag inserted after each highlighted match
+            highlight_format text DEFAULT NULL, -- Whether fragments are 'html' or 'plain' text
+            phrase_fields text DEFAULT NULL, -- Fields where the query is searched as a phrase
+            slop integer DEFAULT NULL, -- Number of positions phrase terms may be apart
+            phrase_prefix boolean DEFAULT NULL, -- Match the last phrase term as a prefix
+            sort_by text DEFAULT NULL, -- Fast fields to sort results by, instead of score
+            search_after text DEFAULT NULL -- Score and key of the result to continue after
         ) RETURNS %s AS $func$
         DECLARE
             __paradedb_search_config__ JSONB;
@@ -249,14 +266,26 @@ BEGIN
         		'%s'::jsonb || jsonb_build_object(
             		'query', query,
                 	'offset_rows', offset_rows,
-                	'limit_rows', limit_rows,
+                	-- The default limit only applies to these functions, and never to the @@@ operator.
+                	'limit_rows', coalesce(
+                        limit_rows,
+                        nullif(current_setting('paradedb.pg_bm25.default_limit_rows', true)::integer, 0)
+                    ),
                 	'fuzzy_fields', fuzzy_fields,
                 	'distance', distance,
                 	'transpose_cost_one', transpose_cost_one,
                 	'prefix', prefix,
                 	'regex_fields', regex_fields,
                 	'max_num_chars', max_num_chars,
-                    'highlight_field', highlight_field
+                    'highlight_field', highlight_field,
+                    'highlight_pre_tag', highlight_pre_tag,
+                    'highlight_post_tag', highlight_post_tag,
+                    'highlight_format', highlight_format,
+                    'phrase_fields', phrase_fields,
+                    'slop', slop,
+                    'phrase_prefix', phrase_prefix,
+                    'sort_by', sort_by,
+                    'search_after', search_after
             	)
         	);
             %s
@@ -266,56 +295,64 @@ BEGIN
 END;
 $outerfunc$ LANGUAGE plpgsql;
 
--- A helper function to format a hybrid search query
+-- A helper function to format a hybrid search query. The similarity query is an expression
+-- over the table, like a pgvector distance, so it's interpolated into the query that collects
+-- the similarity ranking. The rankings are fused by paradedb.rank_hybrid.
 CREATE OR REPLACE FUNCTION paradedb.format_hybrid_function(
     function_name text,
     return_type text,
-    function_body text,
     index_json jsonb
 ) RETURNS text AS $outerfunc$
 BEGIN
-    DECLARE
-        __table_name__ text;
-        __function_body__ text;
-    BEGIN
-        __table_name__ := index_json->>'table_name';
-        __function_body__ := format(
-            function_body,
-            __table_name__
-        );
-
-        RETURN format($f$
-            -- If you add parameters to the function here, you must also add them to the `drop_bm25`
-            -- function, or you'll get a runtime "function does not exist" error when you try to drop.
-            CREATE OR REPLACE FUNCTION %s(
-                bm25_query text,
-                similarity_query text,
-                similarity_limit_n integer DEFAULT 100,
-                bm25_limit_n integer DEFAULT 100,
-                similarity_weight real DEFAULT 0.5,
-                bm25_weight real DEFAULT 0.5
-            ) RETURNS %s AS $func$
-            DECLARE
-                __paradedb_search_config__ JSONB;
-                query text;
-            BEGIN
+    RETURN format($f$
+        -- If you add parameters to the function here, you must also add them to the `drop_bm25`
+        -- function, or you'll get a runtime "function does not exist" error when you try to drop.
+        CREATE OR REPLACE FUNCTION %s(
+            bm25_query text,
+            similarity_query text,
+            similarity_limit_n integer DEFAULT 100,
+            bm25_limit_n integer DEFAULT 100,
+            similarity_weight real DEFAULT 0.5,
+            bm25_weight real DEFAULT 0.5,
+            strategy text DEFAULT 'minmax',
+            rrf_k integer DEFAULT 60
+        ) RETURNS %s AS $func$
+        DECLARE
+            __paradedb_search_config__ JSONB;
+            similarity_keys bigint[];
+            similarity_scores real[];
+        BEGIN
             -- Merge the outer 'index_json' object into the parameters passed to the dynamic function.
-                __paradedb_search_config__ := jsonb_strip_nulls(
-                    '%s'::jsonb || jsonb_build_object(
-                        'query', bm25_query,
-                        'limit_rows', bm25_limit_n
-                    )
-                );
+            __paradedb_search_config__ := jsonb_strip_nulls(
+                '%s'::jsonb || jsonb_build_object(
+                    'query', bm25_query,
+                    'limit_rows', bm25_limit_n
+                )
+            );
 
-                query := replace(%L, '__similarity_query__', similarity_query);
-                query := replace(query, '__key_field__', __paradedb_search_config__ ->>'key_field');
+            EXECUTE format(
+                'SELECT array_agg(key ORDER BY score), array_agg(score ORDER BY score)
+                 FROM (SELECT %%I::bigint AS key, (%%s)::real AS score FROM %%I.%%I ORDER BY 2 LIMIT $1) AS similarity',
+                __paradedb_search_config__ ->> 'key_field',
+                similarity_query,
+                __paradedb_search_config__ ->> 'schema_name',
+                __paradedb_search_config__ ->> 'table_name'
+            )
+            INTO similarity_keys, similarity_scores
+            USING similarity_limit_n;
 
-                RETURN QUERY EXECUTE query
-                USING __paradedb_search_config__, similarity_limit_n, similarity_weight, bm25_weight;
-            END;
-            $func$ LANGUAGE plpgsql;
-        $f$, function_name, return_type, index_json, __function_body__);
-    END;
+            RETURN QUERY SELECT * FROM paradedb.rank_hybrid(
+                __paradedb_search_config__,
+                COALESCE(similarity_keys, '{}'),
+                COALESCE(similarity_scores, '{}'),
+                strategy => strategy,
+                rrf_k => rrf_k,
+                bm25_weight => bm25_weight,
+                similarity_weight => similarity_weight
+            );
+        END;
+        $func$ LANGUAGE plpgsql;
+    $f$, function_name, return_type, index_json);
 END;
 $outerfunc$ LANGUAGE plpgsql;
 
@@ -350,7 +387,6 @@ BEGIN
 
     EXECUTE format('DROP INDEX IF EXISTS %s.%s_bm25_index', schema_name, index_name); 
     EXECUTE format('DROP SCHEMA IF EXISTS %s CASCADE', index_name);
-    PERFORM paradedb.drop_bm25_internal(format('%s_bm25_index', index_name));
 
     EXECUTE 'SET client_min_messages TO ' || quote_literal(original_client_min_messages);
   END;
@@ -0,0 +1,360 @@
+\echo Use "ALTER EXTENSION pg_bm25 UPDATE TO '0.5.4'" to load this file. \quit
+
+-- Synonym sets that can be referenced by group from the synonyms option of a text field.
+CREATE TABLE IF NOT EXISTS paradedb.synonyms (
+    group_name TEXT NOT NULL,
+    terms TEXT[] NOT NULL
+);
+SELECT pg_catalog.pg_extension_config_dump('paradedb.synonyms', '');
+GRANT SELECT ON paradedb.synonyms TO PUBLIC;
+
+CREATE OR REPLACE PROCEDURE paradedb.create_bm25(
+    index_name text DEFAULT '',
+    table_name text DEFAULT '',
+    key_field text DEFAULT '',
+    schema_name text DEFAULT CURRENT_SCHEMA,
+    text_fields text DEFAULT '{}',
+    numeric_fields text DEFAULT '{}',
+    boolean_fields text DEFAULT '{}',
+    json_fields text DEFAULT '{}',
+    merge_policy text DEFAULT ''
+)
+LANGUAGE plpgsql AS $$
+DECLARE
+    original_client_min_messages TEXT;
+BEGIN
+    SELECT INTO original_client_min_messages current_setting('client_min_messages');
+    SET client_min_messages TO WARNING;
+    
+    IF index_name IS NULL OR index_name = '' THEN
+        RAISE EXCEPTION 'no index_name parameter given for bm25 index';
+    END IF;
+
+    IF table_name IS NULL OR table_name = '' THEN
+        RAISE EXCEPTION 'no table_name parameter given for bm25 index "%"', index_name;
+    END IF;
+
+    IF key_field IS NULL OR key_field = '' THEN
+        RAISE EXCEPTION 'no key_field parameter given for bm25 index "%"', index_name;
+    END IF;
+
+    IF text_fields = '{}' AND numeric_fields = '{}' AND boolean_fields = '{}' AND json_fields = '{}' THEN
+        RAISE EXCEPTION 'no text_fields, numeric_fields, boolean_fields, or json_fields were specified for index %', index_name;
+    END IF;
+
+    -- Drop any existing index and function with the same name to avoid conflicts.
+    CALL paradedb.drop_bm25(index_name, schema_name => schema_name);
+
+    -- Create the new, empty schema.
+    EXECUTE format('CREATE SCHEMA %s', index_name);
+
+    -- Create a new BM25 index on the specified table.
+    -- The index is created dynamically based on the function parameters.
+    EXECUTE format('CREATE INDEX %s_bm25_index ON %I.%I USING bm25 ((%I.*)) WITH (key_field=%L, text_fields=%L, numeric_fields=%L, boolean_fields=%L, json_fields=%L, merge_policy=%L);',
+                   index_name, schema_name, table_name, table_name, key_field, text_fields, numeric_fields, boolean_fields, json_fields, merge_policy);
+
+    CALL paradedb.create_bm25_functions(index_name, table_name, key_field, schema_name);
+
+    EXECUTE 'SET client_min_messages TO ' || quote_literal(original_client_min_messages);
+   END;
+$$;
+
+-- This procedure creates the search functions of a BM25 index in the schema named after it.
+-- It's called again with the new names when the index, or the table it indexes, is renamed.
+CREATE OR REPLACE PROCEDURE paradedb.create_bm25_functions(
+    index_name text,
+    table_name text,
+    key_field text,
+    schema_name text
+)
+LANGUAGE plpgsql AS $$
+DECLARE
+    index_json JSONB;
+BEGIN
+    index_json := jsonb_build_object(
+        'index_name', format('%s_bm25_index', index_name),
+        'table_name', table_name,
+        'key_field', key_field,
+        'schema_name', schema_name
+    );
+
+    -- Dynamically create a new function for performing searches on the indexed table.
+    -- The variable '__paradedb_search_config__' is available to the function_body parameter.
+    -- Note that due to how the SQL query is parsed, this variable cannot share a name with
+    -- any existing table or column. The possibility of a naming collision is inevitable, but
+    -- we choose '__paradedb_search_config__' in hopes of avoiding a collision.
+    EXECUTE paradedb.format_bm25_function(
+        function_name => format('%I.search', index_name),        	
+        return_type => format('SETOF %I.%I', schema_name, table_name),
+        function_body => format('RETURN QUERY SELECT * FROM %I.%I WHERE %I @@@ __paradedb_search_config__;', schema_name, table_name, table_name),
+        index_json => index_json
+    );
+
+    EXECUTE paradedb.format_bm25_function(
+        function_name => format('%I.highlight', index_name),
+        return_type => format('TABLE(%s bigint, highlight_bm25 text, highlight_field text)', key_field),
+        function_body => 'RETURN QUERY SELECT * FROM paradedb.highlight_bm25(__paradedb_search_config__);',
+        index_json => index_json
+    );
+
+    EXECUTE paradedb.format_bm25_function(
+        function_name => format('%I.highlight_offsets', index_name),
+        return_type => format('TABLE(%s bigint, highlight_field text, fragment text, offsets int4range[])', key_field),
+        function_body => 'RETURN QUERY SELECT * FROM paradedb.highlight_offsets_bm25(__paradedb_search_config__);',
+        index_json => index_json
+    );
+
+    EXECUTE paradedb.format_bm25_function(
+        function_name => format('%I.rank', index_name),
+        return_type => format('TABLE(%s bigint, rank_bm25 real, cursor text)', key_field),
+        function_body => 'RETURN QUERY SELECT * FROM paradedb.rank_bm25(__paradedb_search_config__);',
+        index_json => index_json
+    );
+
+    EXECUTE paradedb.format_empty_function(
+        function_name => format('%I.schema', index_name),
+        return_type => 'TABLE(name text, field_type text, stored bool, indexed bool, fast bool, fieldnorms bool, expand_dots bool, tokenizer text, record text, normalizer text, filters text[])',
+        function_body => format('RETURN QUERY SELECT * FROM paradedb.schema_bm25(''%s'');', index_name)
+    );
+
+    EXECUTE paradedb.format_hybrid_function(
+        function_name => format('%I.rank_hybrid', index_name),
+        return_type => format('TABLE(%s bigint, rank_hybrid real)', key_field),
+        index_json => index_json
+    );
+END;
+$$;
+
+-- A helper function to format a search query. The "template" below is used by several
+-- search functions, like "search", "rank", and "highlight", so we've extracted the code
+-- into a common function.
+CREATE OR REPLACE FUNCTION paradedb.format_bm25_function(
+    function_name text,
+    return_type text,
+    function_body text,
+    index_json jsonb
+) RETURNS text AS $outerfunc$
+BEGIN
+     RETURN format($f$
+        -- If you add parameters to the function here, you must also add them to the `drop_bm25`
+        -- function, or you'll get a runtime "function does not exist" error when you try to drop.
+        CREATE OR REPLACE FUNCTION %s(
+            query text, -- The search query
+            offset_rows integer DEFAULT NULL, -- Offset for paginated results
+            limit_rows integer DEFAULT NULL, -- Limit for paginated results
+            fuzzy_fields text DEFAULT NULL, -- Fields where fuzzy search is applied
+            distance integer DEFAULT NULL, -- Distance parameter for fuzzy search
+            transpose_cost_one boolean DEFAULT NULL, -- Transpose cost parameter for fuzzy search
+            prefix boolean DEFAULT NULL, -- Prefix parameter for searches
+            regex_fields text DEFAULT NULL, -- Fields where regex search is applied
+            max_num_chars integer DEFAULT NULL, -- Maximum character limit for searches
+            highlight_field text DEFAULT NULL, -- Fields to highlight (highlight funcs only)
+            highlight_pre_tag text DEFAULT NULL, -- Tag inserted before each highlighted match
+            highlight_post_tag text DEFAULT NULL, -- Tag inserted after each highlighted match
+            highlight_format text DEFAULT NULL, -- Whether fragments are 'html' or 'plain' text
+            phrase_fields text DEFAULT NULL, -- Fields where the query is searched as a phrase
+            slop integer DEFAULT NULL, -- Number of positions phrase terms may be apart
+            phrase_prefix boolean DEFAULT NULL, -- Match the last phrase term as a prefix
+            sort_by text DEFAULT NULL, -- Fast fields to sort results by, instead of score
+            search_after text DEFAULT NULL -- Score and key of the result to continue after
+        ) RETURNS %s AS $func$
+        DECLARE
+            __paradedb_search_config__ JSONB;
+        BEGIN
+           -- Merge the outer 'index_json' object into the parameters passed to the dynamic function.
+           __paradedb_search_config__ := jsonb_strip_nulls(
+        		'%s'::jsonb || jsonb_build_object(
+            		'query', query,
+                	'offset_rows', offset_rows,
+                	-- The default limit only applies to these functions, and never to the @@@ operator.
+                	'limit_rows', coalesce(
+                        limit_rows,
+                        nullif(current_setting('paradedb.pg_bm25.default_limit_rows', true)::integer, 0)
+                    ),
+                	'fuzzy_fields', fuzzy_fields,
+                	'distance', distance,
+                	'transpose_cost_one', transpose_cost_one,
+                	'prefix', prefix,
+                	'regex_fields', regex_fields,
+                	'max_num_chars', max_num_chars,
+                    'highlight_field', highlight_field,
+                    'highlight_pre_tag', highlight_pre_tag,
+                    'highlight_post_tag', highlight_post_tag,
+                    'highlight_format', highlight_format,
+                    'phrase_fields', phrase_fields,
+                    'slop', slop,
+                    'phrase_prefix', phrase_prefix,
+                    'sort_by', sort_by,
+                    'search_after', search_after
+            	)
+        	);
+            %s
+        END;
+        $func$ LANGUAGE plpgsql;
+    $f$, function_name, return_type, index_json, function_body);
+END;
+$outerfunc$ LANGUAGE plpgsql;
+
+-- A helper function to format a hybrid search query. The similarity query is an expression
+-- over the table, like a pgvector distance, so it's interpolated into the query that collects
+-- the similarity ranking. The rankings are fused by paradedb.rank_hybrid.
+CREATE OR REPLACE FUNCTION paradedb.format_hybrid_function(
+    function_name text,
+    return_type text,
+    index_json jsonb
+) RETURNS text AS $outerfunc$
+BEGIN
+    RETURN format($f$
+        -- If you add parameters to the function here, you must also add them to the `drop_bm25`
+        -- function, or you'll get a runtime "function does not exist" error when you try to drop.
+        CREATE OR REPLACE FUNCTION %s(
+            bm25_query text,
+            similarity_query text,
+            similarity_limit_n integer DEFAULT 100,
+            bm25_limit_n integer DEFAULT 100,
+            similarity_weight real DEFAULT 0.5,
+            bm25_weight real DEFAULT 0.5,
+            strategy text DEFAULT 'minmax',
+            rrf_k integer DEFAULT 60
+        ) RETURNS %s AS $func$
+        DECLARE
+            __paradedb_search_config__ JSONB;
+            similarity_keys bigint[];
+            similarity_scores real[];
+        BEGIN
+            -- Merge the outer 'index_json' object into the parameters passed to the dynamic function.
+            __paradedb_search_config__ := jsonb_strip_nulls(
+                '%s'::jsonb || jsonb_build_object(
+                    'query', bm25_query,
+                    'limit_rows', bm25_limit_n
+                )
+            );
+
+            EXECUTE format(
+                'SELECT array_agg(key ORDER BY score), array_agg(score ORDER BY score)
+                 FROM (SELECT %%I::bigint AS key, (%%s)::real AS score FROM %%I.%%I ORDER BY 2 LIMIT $1) AS similarity',
+                __paradedb_search_config__ ->> 'key_field',
+                similarity_query,
+                __paradedb_search_config__ ->> 'schema_name',
+                __paradedb_search_config__ ->> 'table_name'
+            )
+            INTO similarity_keys, similarity_scores
+            USING similarity_limit_n;
+
+            RETURN QUERY SELECT * FROM paradedb.rank_hybrid(
+                __paradedb_search_config__,
+                COALESCE(similarity_keys, '{}'),
+                COALESCE(similarity_scores, '{}'),
+                strategy => strategy,
+                rrf_k => rrf_k,
+                bm25_weight => bm25_weight,
+                similarity_weight => similarity_weight
+            );
+        END;
+        $func$ LANGUAGE plpgsql;
+    $f$, function_name, return_type, index_json);
+END;
+$outerfunc$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION paradedb.format_empty_function(
+    function_name text,
+    return_type text,
+    function_body text
+) RETURNS text AS $outerfunc$
+BEGIN
+     RETURN format($f$
+        -- If you add parameters to the function here, you must also add them to the `drop_bm25`
+        -- function, or you'll get a runtime "function does not exist" error when you try to drop.
+        CREATE OR REPLACE FUNCTION %s() RETURNS %s AS $func$
+        BEGIN
+            %s
+        END;
+        $func$ LANGUAGE plpgsql;
+    $f$, function_name, return_type, function_body);
+END;
+$outerfunc$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE PROCEDURE paradedb.drop_bm25(
+    index_name text,
+    schema_name text DEFAULT CURRENT_SCHEMA
+)
+LANGUAGE plpgsql AS $$
+DECLARE 
+    original_client_min_messages TEXT;
+BEGIN
+    SELECT INTO original_client_min_messages current_setting('client_min_messages');
+    SET client_min_messages TO WARNING;
+
+    EXECUTE format('DROP INDEX IF EXISTS %s.%s_bm25_index', schema_name, index_name); 
+    EXECUTE format('DROP SCHEMA IF EXISTS %s CASCADE', index_name);
+
+    EXECUTE 'SET client_min_messages TO ' || quote_literal(original_client_min_messages);
+  END;
+$$;
+
+-- highlight_bm25 now returns the highlighted field as a third column, and rank_bm25 returns a
+-- search_after cursor. Postgres can't change the return type of a function in place, so they're
+-- recreated, and so are the search functions of every index, which are dropped first because
+-- their signatures changed too.
+DROP FUNCTION IF EXISTS paradedb.highlight_bm25(jsonb);
+CREATE FUNCTION paradedb."highlight_bm25"(
+	"config_json" jsonb
+) RETURNS TABLE (
+	"id" bigint,
+	"highlight_bm25" TEXT,
+	"highlight_field" TEXT
+)
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'highlight_bm25_wrapper';
+
+DROP FUNCTION IF EXISTS paradedb.rank_bm25(jsonb);
+CREATE FUNCTION paradedb."rank_bm25"(
+	"config_json" jsonb
+) RETURNS TABLE (
+	"id" bigint,
+	"rank_bm25" real,
+	"cursor" TEXT
+)
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'rank_bm25_wrapper';
+
+DO $$
+DECLARE
+    index_row record;
+    function_oid oid;
+BEGIN
+    FOR index_row IN
+        SELECT
+            left(index_class.relname, -length('_bm25_index')) AS index_name,
+            table_class.relname AS table_name,
+            table_namespace.nspname AS schema_name,
+            (
+                SELECT option_value FROM pg_options_to_table(index_class.reloptions)
+                WHERE option_name = 'key_field'
+            ) AS key_field
+        FROM pg_index
+        JOIN pg_class index_class ON index_class.oid = pg_index.indexrelid
+        JOIN pg_am ON pg_am.oid = index_class.relam
+        JOIN pg_class table_class ON table_class.oid = pg_index.indrelid
+        JOIN pg_namespace table_namespace ON table_namespace.oid = table_class.relnamespace
+        WHERE pg_am.amname = 'bm25' AND index_class.relname LIKE '%\_bm25\_index'
+    LOOP
+        FOR function_oid IN
+            SELECT pg_proc.oid FROM pg_proc
+            JOIN pg_namespace ON pg_namespace.oid = pg_proc.pronamespace
+            WHERE pg_namespace.nspname = index_row.index_name
+        LOOP
+            EXECUTE format('DROP FUNCTION %s', function_oid::regprocedure);
+        END LOOP;
+
+        CALL paradedb.create_bm25_functions(
+            index_row.index_name,
+            index_row.table_name,
+            index_row.key_field,
+            index_row.schema_name
+        );
+    END LOOP;
+END;
+$$;
@@ -0,0 +1,143 @@
+use pgrx::{iter::TableIterator, *};
+
+use crate::index_access::utils::get_index_oid;
+use crate::parade_index::fields::{ParadeOption, ParadeTokenFilter};
+use crate::parade_index::index::ParadeIndex;
+use crate::security::HeapAccess;
+
+#[pg_extern]
+pub fn autocomplete_bm25(
+    index_name: &str,
+    field_name: &str,
+    prefix: &str,
+    limit: default!(i32, 10),
+) -> TableIterator<'static, (name!(term, String), name!(doc_freq, i64))> {
+    let bm25_index_name = format!("{}_bm25_index", index_name);
+    let index_oid = get_index_oid(None, &bm25_index_name);
+    let parade_index = ParadeIndex::from_index_oid(index_oid);
+
+    // Terms are aggregated over every row in the index, so we can't recheck them against
+    // row level security policies.
+    HeapAccess::from_index(index_oid, parade_index).deny_row_security("autocomplete_bm25");
+
+    let field = parade_index
+        .schema()
+        .get_field(field_name)
+        .unwrap_or_else(|err| panic!("error autocompleting field {field_name}: {err:?}"));
+
+    let ParadeOption::Text(text_options) = parade_index
+        .field_configs
+        .get(field_name)
+        .unwrap_or_else(|| panic!("no field config found for field {field_name}"))
+    else {
+        panic!("can only autocomplete text fields, {field_name} is not a text field")
+    };
+
+    // If the field's tokens are lowercased, the prefix must be lowercased too if it's going
+    // to match anything in the term dictionary. The rest of the filter chain isn't applied,
+    // because filters like stemmers would change a partial word into something that isn't
+    // a prefix of the terms it was typed for.
+    let prefix = if text_options
+        .filter_chain()
+        .contains(&ParadeTokenFilter::Lowercase)
+    {
+        prefix.to_lowercase()
+    } else {
+        prefix.to_string()
+    };
+
+    let mut completions: Vec<(String, u64)> = parade_index
+        .prefix_terms(field, &prefix)
+        .unwrap_or_else(|err| panic!("error reading terms for field {field_name}: {err:?}"))
+        .into_iter()
+        .collect();
+
+    // Most frequent terms first, alphabetically in case of a tie.
+    completions.sort_by(|(term_a, freq_a), (term_b, freq_b)| {
+        freq_b.cmp(freq_a).then_with(|| term_a.cmp(term_b))
+    });
+    completions.truncate(limit.max(0) as usize);
+
+    TableIterator::new(
+        completions
+            .into_iter()
+            .map(|(term, doc_freq)| (term, doc_freq as i64)),
+    )
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::autocomplete_bm25;
+    use crate::index_access::utils::get_index_oid;
+    use crate::parade_index::index::ParadeIndex;
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+
+    #[pg_test]
+    fn test_autocomplete_bm25() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let completions =
+            autocomplete_bm25("one_republic_songs", "album", "Dr", 10).collect::<Vec<_>>();
+        assert_eq!(completions, vec![("dreaming".to_string(), 2)]);
+
+        let completions =
+            autocomplete_bm25("one_republic_songs", "title", "s", 2).collect::<Vec<_>>();
+        assert_eq!(completions.len(), 2);
+        assert_eq!(completions[0], ("secrets".to_string(), 1));
+    }
+
+    #[pg_test]
+    fn test_autocomplete_bm25_skips_deleted_documents() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        // Delete "Stop and Stare" from the index, like VACUUM would after the row is deleted.
+        let (block, offset) = Spi::get_two::<i64, i32>(
+            "SELECT (ctid::text::point)[0]::bigint, (ctid::text::point)[1]::int
+            FROM one_republic_songs WHERE title = 'Stop and Stare'",
+        )
+        .expect("failed to read ctid");
+        let (block, offset) = (block.unwrap() as u32, offset.unwrap() as u16);
+        let index_oid = get_index_oid(None, "one_republic_songs_bm25_index");
+        ParadeIndex::from_index_oid(index_oid)
+            .delete(|ctid| unsafe { item_pointer_get_both(*ctid) } == (block, offset))
+            .expect("failed to delete document");
+
+        let completions =
+            autocomplete_bm25("one_republic_songs", "album", "Dr", 10).collect::<Vec<_>>();
+        assert_eq!(completions, vec![("dreaming".to_string(), 1)]);
+
+        let completions =
+            autocomplete_bm25("one_republic_songs", "title", "stop", 10).collect::<Vec<_>>();
+        assert!(completions.is_empty());
+    }
+
+    #[pg_test]
+    fn test_autocomplete_bm25_whitespace_field() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CALL paradedb.create_bm25(
+                index_name => 'whitespace_songs',
+                table_name => 'one_republic_songs',
+                key_field => 'song_id',
+                text_fields => '{album: {tokenizer: {type: "whitespace"}}}'
+            );
+            "#,
+        )
+        .expect("failed to create index");
+
+        // The whitespace tokenizer has no filters, so terms keep their case.
+        let completions =
+            autocomplete_bm25("whitespace_songs", "album", "Dr", 10).collect::<Vec<_>>();
+        assert_eq!(completions, vec![("Dreaming".to_string(), 2)]);
+
+        let completions =
+            autocomplete_bm25("whitespace_songs", "album", "dr", 10).collect::<Vec<_>>();
+        assert!(completions.is_empty());
+    }
+}
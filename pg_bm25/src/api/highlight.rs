@@ -0,0 +1,628 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use pgrx::{prelude::TableIterator, *};
+use tantivy::query::Query;
+use tantivy::schema::{Field, FieldType, Value};
+use tantivy::tokenizer::{TextAnalyzer, TokenStream};
+use tantivy::{Document, Score, Searcher, Snippet, SnippetGenerator};
+
+use crate::{
+    index_access::utils::{HighlightFormat, SearchConfig},
+    parade_index::index::ParadeIndex,
+    security::HeapAccess,
+};
+
+// Tantivy's default, used when max_num_chars isn't set.
+const DEFAULT_MAX_NUM_CHARS: usize = 150;
+// Separates the segments of the json path stored in terms of json fields.
+const JSON_PATH_SEGMENT_SEP: char = '\u{1}';
+
+#[pg_extern]
+pub fn highlight_bm25(
+    config_json: JsonB,
+) -> TableIterator<
+    'static,
+    (
+        name!(id, i64),
+        name!(highlight_bm25, String),
+        name!(highlight_field, String),
+    ),
+> {
+    let JsonB(search_config_json) = config_json;
+    let search_config: SearchConfig =
+        serde_json::from_value(search_config_json).expect("could not parse search config");
+    let parade_index = search_config.parade_index();
+
+    let (pre_tag, post_tag, escape) = match search_config.highlight_format.unwrap_or_default() {
+        HighlightFormat::Html => ("<b>", "</b>", true),
+        HighlightFormat::Plain => ("", "", false),
+    };
+    let pre_tag = search_config
+        .highlight_pre_tag
+        .as_deref()
+        .unwrap_or(pre_tag);
+    let post_tag = search_config
+        .highlight_post_tag
+        .as_deref()
+        .unwrap_or(post_tag);
+
+    let field_rows = highlights(&search_config, parade_index)
+        .into_iter()
+        .map(|(key, field_name, highlight)| {
+            (
+                key,
+                highlight.to_tagged(pre_tag, post_tag, escape),
+                field_name,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    TableIterator::new(field_rows)
+}
+
+#[pg_extern]
+pub fn highlight_offsets_bm25(
+    config_json: JsonB,
+) -> TableIterator<
+    'static,
+    (
+        name!(id, i64),
+        name!(highlight_field, String),
+        name!(fragment, String),
+        name!(offsets, Vec<pgrx::Range<i32>>),
+    ),
+> {
+    let JsonB(search_config_json) = config_json;
+    let search_config: SearchConfig =
+        serde_json::from_value(search_config_json).expect("could not parse search config");
+    let parade_index = search_config.parade_index();
+
+    let field_rows = highlights(&search_config, parade_index)
+        .into_iter()
+        .map(|(key, field_name, highlight)| {
+            let offsets = highlight.char_offsets();
+            (key, field_name, highlight.fragment, offsets)
+        })
+        .collect::<Vec<_>>();
+
+    TableIterator::new(field_rows)
+}
+
+/// Runs the search in the config, and highlights each of its highlight fields in every
+/// document that the search matches.
+fn highlights(
+    search_config: &SearchConfig,
+    parade_index: &ParadeIndex,
+) -> Vec<(i64, String, Highlight)> {
+    let heap_access = HeapAccess::new(search_config, parade_index);
+    let function_schema = &search_config.schema_name;
+    if search_config.highlight_field.is_empty() {
+        panic!("highlight_field parameter required for {function_schema}.highlight function")
+    }
+
+    let mut scan_state = parade_index.scan_state(search_config).unwrap();
+    let searcher = parade_index.searcher();
+    let max_num_chars = search_config.max_num_chars.unwrap_or(DEFAULT_MAX_NUM_CHARS);
+
+    // Build the highlighters before searching, so a bad field name fails fast.
+    let highlighters: Vec<FieldHighlighter> = search_config
+        .highlight_field
+        .iter()
+        .map(|field_name| {
+            FieldHighlighter::new(
+                parade_index,
+                &searcher,
+                scan_state.query.as_ref(),
+                field_name.trim(),
+                max_num_chars,
+            )
+        })
+        .collect();
+
+    let top_docs = heap_access.search(&mut scan_state);
+
+    let mut highlights = Vec::new();
+    for (_score, doc_address) in top_docs.into_iter() {
+        let document = scan_state
+            .doc(doc_address)
+            .unwrap_or_else(|err| panic!("error retrieving document for highlight: {err:?}"));
+        let key = parade_index.get_key_value(&document);
+        for highlighter in &highlighters {
+            highlights.push((
+                key,
+                highlighter.field_name().to_string(),
+                highlighter.highlight(&document),
+            ));
+        }
+    }
+
+    highlights
+}
+
+/// The best fragment of a field's text, with the byte ranges of the matches inside it.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Highlight {
+    fragment: String,
+    highlighted: Vec<Range<usize>>,
+}
+
+impl Highlight {
+    fn new(fragment: String, mut highlighted: Vec<Range<usize>>) -> Self {
+        // Tokens that share a position, like synonyms or phonetic codes kept alongside the
+        // original word, can match the same stretch of text more than once.
+        highlighted.sort_by_key(|range| (range.start, range.end));
+        let mut collapsed: Vec<Range<usize>> = Vec::with_capacity(highlighted.len());
+        for range in highlighted {
+            match collapsed.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => collapsed.push(range),
+            }
+        }
+
+        Self {
+            fragment,
+            highlighted: collapsed,
+        }
+    }
+
+    fn to_tagged(&self, pre_tag: &str, post_tag: &str, escape: bool) -> String {
+        let text = |text: &str| {
+            if escape {
+                escape_html(text)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let mut tagged = String::new();
+        let mut start_from = 0;
+        for range in &self.highlighted {
+            tagged.push_str(&text(&self.fragment[start_from..range.start]));
+            tagged.push_str(pre_tag);
+            tagged.push_str(&text(&self.fragment[range.clone()]));
+            tagged.push_str(post_tag);
+            start_from = range.end;
+        }
+        tagged.push_str(&text(&self.fragment[start_from..]));
+        tagged
+    }
+
+    /// The ranges of the matches, in characters rather than bytes, so they can be
+    /// used directly by clients that don't index strings by bytes.
+    fn char_offsets(&self) -> Vec<pgrx::Range<i32>> {
+        let char_offset = |byte_offset: usize| self.fragment[..byte_offset].chars().count() as i32;
+        self.highlighted
+            .iter()
+            .map(|range| {
+                pgrx::Range::new(
+                    RangeBound::Inclusive(char_offset(range.start)),
+                    RangeBound::Exclusive(char_offset(range.end)),
+                )
+            })
+            .collect()
+    }
+}
+
+impl From<Snippet> for Highlight {
+    fn from(snippet: Snippet) -> Self {
+        Self::new(
+            snippet.fragment().to_string(),
+            snippet.highlighted().to_vec(),
+        )
+    }
+}
+
+enum FieldHighlighter {
+    Text {
+        field_name: String,
+        snippet_generator: SnippetGenerator,
+    },
+    /// Tantivy's snippet generator only reads text values, so the string values inside
+    /// json fields are highlighted here instead, against the query terms under the same path.
+    Json {
+        field_name: String,
+        field: Field,
+        path: Vec<String>,
+        terms: BTreeMap<(String, String), Score>,
+        analyzer: TextAnalyzer,
+        max_num_chars: usize,
+    },
+    /// Numeric, boolean and date fields have no text to highlight, so every row gets an
+    /// empty fragment.
+    Empty { field_name: String },
+}
+
+impl FieldHighlighter {
+    fn new(
+        parade_index: &ParadeIndex,
+        searcher: &Searcher,
+        query: &dyn Query,
+        field_name: &str,
+        max_num_chars: usize,
+    ) -> Self {
+        let schema = parade_index.schema();
+        let (field, json_path) = schema
+            .find_field(field_name)
+            .unwrap_or_else(|| panic!("error highlighting field {field_name}: field not found"));
+
+        match schema.get_field_entry(field).field_type() {
+            FieldType::Str(_) if json_path.is_empty() => {
+                let mut snippet_generator = SnippetGenerator::create(searcher, query, field)
+                    .unwrap_or_else(|err| {
+                        panic!(
+                            "failed to create snippet generator for field: {field_name}... {err}"
+                        )
+                    });
+                snippet_generator.set_max_num_chars(max_num_chars);
+
+                Self::Text {
+                    field_name: field_name.to_string(),
+                    snippet_generator,
+                }
+            }
+            FieldType::JsonObject(_) => {
+                let mut query_terms = vec![];
+                query.query_terms(&mut |term, _| {
+                    if term.field() == field {
+                        query_terms.push(term.clone());
+                    }
+                });
+
+                let mut terms = BTreeMap::new();
+                for term in query_terms {
+                    let value = term.value();
+                    let Some((term_path, term_value)) = value.as_json() else {
+                        continue;
+                    };
+                    let Some(term_text) = term_value.as_str() else {
+                        continue;
+                    };
+                    let doc_freq = searcher.doc_freq(&term).unwrap_or_else(|err| {
+                        panic!("error reading terms for field {field_name}: {err:?}")
+                    });
+                    // Rarer terms make for better fragments, as in tantivy's snippets.
+                    if doc_freq > 0 {
+                        terms.insert(
+                            (term_path.to_string(), term_text.to_string()),
+                            1.0 / (1.0 + doc_freq as Score),
+                        );
+                    }
+                }
+
+                let analyzer = parade_index
+                    .tokenizer_for_field(field)
+                    .unwrap_or_else(|err| {
+                        panic!("error loading tokenizer for field {field_name}: {err:?}")
+                    });
+
+                Self::Json {
+                    field_name: field_name.to_string(),
+                    field,
+                    path: json_path
+                        .split('.')
+                        .filter(|segment| !segment.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                    terms,
+                    analyzer,
+                    max_num_chars,
+                }
+            }
+            _ if json_path.is_empty() => Self::Empty {
+                field_name: field_name.to_string(),
+            },
+            _ => panic!(
+                "can only highlight text fields and paths in json fields, {field_name} is neither"
+            ),
+        }
+    }
+
+    fn field_name(&self) -> &str {
+        match self {
+            Self::Text { field_name, .. }
+            | Self::Json { field_name, .. }
+            | Self::Empty { field_name } => field_name,
+        }
+    }
+
+    fn highlight(&self, document: &Document) -> Highlight {
+        match self {
+            Self::Text {
+                snippet_generator, ..
+            } => snippet_generator.snippet_from_doc(document).into(),
+            Self::Json {
+                field,
+                path,
+                terms,
+                analyzer,
+                max_num_chars,
+                ..
+            } => {
+                // Every string under the path is a candidate, and the best fragment wins.
+                let mut analyzer = analyzer.clone();
+                let mut best: Option<(Score, Highlight)> = None;
+                for value in document.get_all(*field) {
+                    let Value::JsonObject(object) = value else {
+                        continue;
+                    };
+                    for (text_path, text) in json_strings(object, path) {
+                        let fragment =
+                            best_fragment(&mut analyzer, text, *max_num_chars, |token| {
+                                terms.get(&(text_path.clone(), token.to_string())).copied()
+                            });
+                        if let Some((score, highlight)) = fragment {
+                            if best
+                                .as_ref()
+                                .map_or(true, |(best_score, _)| score > *best_score)
+                            {
+                                best = Some((score, highlight));
+                            }
+                        }
+                    }
+                }
+
+                best.map(|(_, highlight)| highlight).unwrap_or_default()
+            }
+            Self::Empty { .. } => Highlight::default(),
+        }
+    }
+}
+
+/// The strings in a json object under a path, along with their full paths in the form
+/// that the terms of json fields store them.
+fn json_strings<'a>(
+    object: &'a serde_json::Map<String, serde_json::Value>,
+    path: &'a [String],
+) -> Vec<(String, &'a str)> {
+    let mut strings = vec![];
+    let mut json_path: Vec<&str> = vec![];
+
+    match path.split_first() {
+        None => {
+            for (key, value) in object {
+                json_path.push(key);
+                collect_json_strings(value, &mut json_path, &mut strings);
+                json_path.pop();
+            }
+        }
+        Some((first, rest)) => {
+            let mut value = object.get(first.as_str());
+            for segment in rest {
+                value = value.and_then(|value| value.get(segment.as_str()));
+            }
+            if let Some(value) = value {
+                json_path.extend(path.iter().map(String::as_str));
+                collect_json_strings(value, &mut json_path, &mut strings);
+            }
+        }
+    }
+
+    strings
+}
+
+fn collect_json_strings<'a>(
+    value: &'a serde_json::Value,
+    json_path: &mut Vec<&'a str>,
+    strings: &mut Vec<(String, &'a str)>,
+) {
+    match value {
+        serde_json::Value::String(text) => {
+            strings.push((json_path.join(&JSON_PATH_SEGMENT_SEP.to_string()), text))
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                collect_json_strings(value, json_path, strings);
+            }
+        }
+        serde_json::Value::Object(object) => {
+            for (key, value) in object {
+                json_path.push(key);
+                collect_json_strings(value, json_path, strings);
+                json_path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Picks the fragment of at most `max_num_chars` bytes whose matching tokens score the
+/// highest, the same way tantivy's snippet generator does for text fields. Returns `None`
+/// if no token in the text matches.
+fn best_fragment(
+    analyzer: &mut TextAnalyzer,
+    text: &str,
+    max_num_chars: usize,
+    score: impl Fn(&str) -> Option<Score>,
+) -> Option<(Score, Highlight)> {
+    let mut best: Option<(Score, Range<usize>, Vec<Range<usize>>)> = None;
+    let mut consider = |candidate: (Score, Range<usize>, Vec<Range<usize>>)| {
+        // Ties go to the earliest fragment.
+        if candidate.0 > 0.0 && best.as_ref().map_or(true, |best| candidate.0 > best.0) {
+            best = Some(candidate);
+        }
+    };
+
+    let mut candidate: (Score, Range<usize>, Vec<Range<usize>>) = (0.0, 0..0, vec![]);
+    let mut token_stream = analyzer.token_stream(text);
+    while token_stream.advance() {
+        let token = token_stream.token();
+        if token.offset_to.saturating_sub(candidate.1.start) > max_num_chars {
+            let next = (0.0, token.offset_from..token.offset_from, vec![]);
+            consider(std::mem::replace(&mut candidate, next));
+        }
+
+        candidate.1.end = token.offset_to;
+        if let Some(token_score) = score(&token.text) {
+            candidate.0 += token_score;
+            candidate.2.push(token.offset_from..token.offset_to);
+        }
+    }
+    consider(candidate);
+
+    best.map(|(score, range, highlighted)| {
+        let highlighted = highlighted
+            .into_iter()
+            .map(|highlight| highlight.start - range.start..highlight.end - range.start)
+            .collect();
+        (score, Highlight::new(text[range].to_string(), highlighted))
+    })
+}
+
+/// Escapes the same characters as the html snippets that tantivy renders.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+
+    #[pg_test]
+    fn test_highlight() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let query = r#"
+            SELECT highlight_bm25
+            FROM one_republic_songs.highlight('lyrics:im', highlight_field => 'lyrics', max_num_chars => 10);
+        "#;
+
+        let highlight = Spi::get_one::<&str>(query)
+            .expect("failed to highlight lyrics")
+            .unwrap();
+        assert_eq!(highlight, "<b>Im</b> holding");
+    }
+
+    #[pg_test]
+    fn test_highlight_multiple_fields() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let query = r#"
+            SELECT string_agg(highlight_field || ': ' || highlight_bm25, ' | ' ORDER BY highlight_field)
+            FROM one_republic_songs.highlight(
+                'title:counting OR description:counting',
+                highlight_field => 'title, description',
+                highlight_pre_tag => '<em>',
+                highlight_post_tag => '</em>'
+            );
+        "#;
+
+        let highlights = Spi::get_one::<String>(query)
+            .expect("failed to highlight fields")
+            .unwrap();
+        assert_eq!(
+            highlights,
+            "description: A brief description of the song <em>Counting</em> Stars. | title: <em>Counting</em> Stars"
+        );
+    }
+
+    #[pg_test]
+    fn test_highlight_plain() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let query = r#"
+            SELECT highlight_bm25
+            FROM one_republic_songs.highlight('title:counting', highlight_field => 'title', highlight_format => 'plain');
+        "#;
+        let highlight = Spi::get_one::<&str>(query)
+            .expect("failed to highlight title")
+            .unwrap();
+        assert_eq!(highlight, "Counting Stars");
+
+        let query = r#"
+            SELECT highlight_bm25
+            FROM one_republic_songs.highlight(
+                'title:counting',
+                highlight_field => 'title',
+                highlight_format => 'plain',
+                highlight_pre_tag => '**',
+                highlight_post_tag => '**'
+            );
+        "#;
+        let highlight = Spi::get_one::<&str>(query)
+            .expect("failed to highlight title")
+            .unwrap();
+        assert_eq!(highlight, "**Counting** Stars");
+    }
+
+    #[pg_test]
+    fn test_highlight_offsets() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let query = r#"
+            SELECT fragment || ' ' || offsets::text
+            FROM one_republic_songs.highlight_offsets('title:stars', highlight_field => 'title');
+        "#;
+        let highlight = Spi::get_one::<String>(query)
+            .expect("failed to highlight title")
+            .unwrap();
+        assert_eq!(highlight, r#"Counting Stars {"[9,14)"}"#);
+    }
+
+    #[pg_test]
+    fn test_highlight_json_path() {
+        crate::setup_background_workers();
+        Spi::run(
+            r#"
+            CALL paradedb.create_bm25_test_table(table_name => 'bm25_search', schema_name => 'public');
+            CALL paradedb.create_bm25(
+                index_name => 'bm25_search',
+                table_name => 'bm25_search',
+                key_field => 'id',
+                text_fields => '{description: {}}',
+                json_fields => '{metadata: {}}'
+            );
+            "#,
+        )
+        .expect("failed to create index and table");
+
+        let query = r#"
+            SELECT string_agg(highlight_bm25, ',' ORDER BY id)
+            FROM bm25_search.highlight('metadata.color:white', highlight_field => 'metadata.color');
+        "#;
+        let highlights = Spi::get_one::<String>(query)
+            .expect("failed to highlight json path")
+            .unwrap();
+        assert_eq!(highlights, "<b>White</b>,<b>White</b>");
+
+        let query = r#"
+            SELECT highlight_bm25
+            FROM bm25_search.highlight('metadata.color:white', highlight_field => 'metadata')
+            LIMIT 1;
+        "#;
+        let highlight = Spi::get_one::<&str>(query)
+            .expect("failed to highlight json field")
+            .unwrap();
+        assert_eq!(highlight, "<b>White</b>");
+    }
+
+    #[pg_test]
+    fn test_highlight_numeric_field() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let highlight = Spi::get_one::<&str>(
+            "SELECT highlight_bm25 FROM one_republic_songs.highlight('title:counting', highlight_field => 'release_year')",
+        )
+        .expect("failed to highlight numeric field")
+        .unwrap();
+        assert_eq!(highlight, "");
+    }
+}
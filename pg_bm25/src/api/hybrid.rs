@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use pgrx::{prelude::TableIterator, *};
+
+use crate::{index_access::utils::SearchConfig, security::HeapAccess};
+
+const DEFAULT_RRF_K: i32 = 60;
+
+/// How the BM25 and similarity rankings are combined into a single score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FusionStrategy {
+    /// Scores each result by the reciprocal of its rank in each ranking, offset by k.
+    /// Only the order of each ranking matters, so it isn't thrown off by outlying scores.
+    ReciprocalRank { k: f32 },
+    /// Scales each ranking's scores between 0 and 1.
+    MinMax,
+    /// Scales each ranking's scores by their distance from the mean, in standard deviations.
+    ZScore,
+}
+
+impl FusionStrategy {
+    fn new(name: &str, rrf_k: i32) -> Self {
+        let mut strategy: Self = name.parse().unwrap_or_else(|err| panic!("{err}"));
+        if let Self::ReciprocalRank { k } = &mut strategy {
+            if rrf_k < 0 {
+                panic!("rrf_k must not be negative, received {rrf_k}");
+            }
+            *k = rrf_k as f32;
+        }
+        strategy
+    }
+
+    /// Normalizes the scores of a ranking, ordered from best to worst. Higher scores must
+    /// be better.
+    fn normalize(&self, scores: &[f32]) -> Vec<f32> {
+        match self {
+            Self::ReciprocalRank { k } => (1..=scores.len())
+                .map(|rank| 1.0 / (k + rank as f32))
+                .collect(),
+            Self::MinMax => {
+                let (min, max) = scores
+                    .iter()
+                    .fold((f32::MAX, f32::MIN), |(min, max), score| {
+                        (min.min(*score), max.max(*score))
+                    });
+                let range = max - min;
+                scores
+                    .iter()
+                    .map(|score| {
+                        if range == 0.0 {
+                            1.0
+                        } else {
+                            (score - min) / range
+                        }
+                    })
+                    .collect()
+            }
+            Self::ZScore => {
+                let count = scores.len() as f32;
+                let mean = scores.iter().sum::<f32>() / count;
+                let variance = scores
+                    .iter()
+                    .map(|score| (score - mean).powi(2))
+                    .sum::<f32>()
+                    / count;
+                let std_dev = variance.sqrt();
+                scores
+                    .iter()
+                    .map(|score| {
+                        if std_dev == 0.0 {
+                            0.0
+                        } else {
+                            (score - mean) / std_dev
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+impl FromStr for FusionStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rrf" => Ok(Self::ReciprocalRank {
+                k: DEFAULT_RRF_K as f32,
+            }),
+            "minmax" => Ok(Self::MinMax),
+            "zscore" => Ok(Self::ZScore),
+            _ => Err(format!(
+                "unknown hybrid strategy {s}, expected one of rrf, minmax or zscore"
+            )),
+        }
+    }
+}
+
+#[pg_extern]
+pub fn rank_hybrid(
+    config_json: JsonB,
+    similarity_keys: Vec<i64>,
+    similarity_scores: default!(Option<Vec<f32>>, "NULL"),
+    strategy: default!(&str, "'rrf'"),
+    rrf_k: default!(i32, 60),
+    bm25_weight: default!(f32, 0.5),
+    similarity_weight: default!(f32, 0.5),
+) -> TableIterator<'static, (name!(id, i64), name!(rank_hybrid, f32))> {
+    let JsonB(search_config_json) = config_json;
+    let search_config: SearchConfig =
+        serde_json::from_value(search_config_json).expect("could not parse search config");
+    let parade_index = search_config.parade_index();
+    let heap_access = HeapAccess::new(&search_config, parade_index);
+    let strategy = FusionStrategy::new(strategy, rrf_k);
+
+    let mut scan_state = parade_index.scan_state(&search_config).unwrap();
+    // Filter before fusing, so rows the user can't see don't affect the ranking.
+    let bm25: Vec<(i64, f32)> = heap_access
+        .search(&mut scan_state)
+        .into_iter()
+        .map(|(score, _)| (score.key, score.bm25))
+        .collect();
+
+    // Similarity scores are distances, like the ones pgvector returns, where smaller is
+    // more similar. They're negated so that higher is better, like BM25 scores.
+    let similarity: Vec<(i64, f32)> = match similarity_scores {
+        Some(scores) => {
+            if scores.len() != similarity_keys.len() {
+                panic!(
+                    "received {} similarity_keys but {} similarity_scores",
+                    similarity_keys.len(),
+                    scores.len()
+                );
+            }
+            // The keys are ranked by their distances, so they can be passed in any order.
+            let mut similarity: Vec<(i64, f32)> = similarity_keys
+                .into_iter()
+                .zip(scores.into_iter().map(|distance| -distance))
+                .collect();
+            similarity.sort_by(|(_, score_a), (_, score_b)| score_b.total_cmp(score_a));
+            similarity
+        }
+        None if matches!(strategy, FusionStrategy::ReciprocalRank { .. }) => {
+            similarity_keys.into_iter().map(|key| (key, 0.0)).collect()
+        }
+        None => panic!("similarity_scores are required by the {strategy:?} hybrid strategy"),
+    };
+
+    TableIterator::new(fuse(
+        strategy,
+        &[(bm25, bm25_weight), (similarity, similarity_weight)],
+    ))
+}
+
+/// Combines weighted rankings, each ordered from best to worst, into one ranking ordered
+/// by descending score. Results that are missing from a ranking get nothing from it.
+fn fuse(strategy: FusionStrategy, rankings: &[(Vec<(i64, f32)>, f32)]) -> Vec<(i64, f32)> {
+    let mut fused: HashMap<i64, f32> = HashMap::new();
+
+    for (ranking, weight) in rankings {
+        if ranking.is_empty() {
+            continue;
+        }
+
+        let scores: Vec<f32> = ranking.iter().map(|(_, score)| *score).collect();
+        for ((key, _), score) in ranking.iter().zip(strategy.normalize(&scores)) {
+            *fused.entry(*key).or_default() += weight * score;
+        }
+    }
+
+    let mut fused: Vec<(i64, f32)> = fused.into_iter().collect();
+    // Ties are broken by ascending key, like BM25 scores.
+    fused.sort_by(|(key_a, score_a), (key_b, score_b)| {
+        score_b.total_cmp(score_a).then_with(|| key_a.cmp(key_b))
+    });
+    fused
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::{fuse, FusionStrategy};
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+
+    fn fused_keys(strategy: FusionStrategy, weights: (f32, f32)) -> Vec<i64> {
+        let bm25 = vec![(1, 3.0), (2, 1.0)];
+        // Negated distances, as rank_hybrid passes them.
+        let similarity = vec![(2, -0.1), (3, -0.5)];
+        fuse(strategy, &[(bm25, weights.0), (similarity, weights.1)])
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    #[pg_test]
+    fn test_fuse() {
+        // 2 is ranked by both, so reciprocal rank fusion puts it first.
+        assert_eq!(
+            fused_keys(FusionStrategy::ReciprocalRank { k: 60.0 }, (0.5, 0.5)),
+            vec![2, 1, 3]
+        );
+        // 1 and 2 both end up with 0.5, and the tie goes to the lower key.
+        assert_eq!(
+            fused_keys(FusionStrategy::MinMax, (0.5, 0.5)),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            fused_keys(FusionStrategy::MinMax, (0.1, 0.9)),
+            vec![2, 1, 3]
+        );
+        assert_eq!(
+            fused_keys(FusionStrategy::ZScore, (0.5, 0.5)),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            fused_keys(FusionStrategy::ZScore, (0.1, 0.9)),
+            vec![2, 1, 3]
+        );
+    }
+
+    #[pg_test]
+    fn test_normalize() {
+        let scores = [3.0, 2.0, 1.0];
+        assert_eq!(
+            FusionStrategy::MinMax.normalize(&scores),
+            vec![1.0, 0.5, 0.0]
+        );
+        assert_eq!(
+            FusionStrategy::MinMax.normalize(&[2.0, 2.0]),
+            vec![1.0, 1.0]
+        );
+        assert_eq!(
+            FusionStrategy::ReciprocalRank { k: 1.0 }.normalize(&scores),
+            vec![0.5, 1.0 / 3.0, 0.25]
+        );
+
+        let z_scores = FusionStrategy::ZScore.normalize(&scores);
+        assert_eq!(z_scores[1], 0.0);
+        assert!((z_scores[0] - 1.2247449).abs() < 1e-6);
+    }
+
+    #[pg_test]
+    fn test_rank_hybrid() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let keys = Spi::get_one::<String>(
+            r#"
+            SELECT string_agg(id::text, ',')
+            FROM paradedb.rank_hybrid(
+                jsonb_build_object(
+                    'query', 'title:counting',
+                    'schema_name', current_schema(),
+                    'index_name', 'one_republic_songs_bm25_index',
+                    'table_name', 'one_republic_songs',
+                    'key_field', 'song_id'
+                ),
+                ARRAY[3, 6]::bigint[]
+            )
+            "#,
+        )
+        .expect("failed to rank query")
+        .unwrap();
+        assert_eq!(keys, "6,3");
+    }
+
+    #[pg_test]
+    fn test_rank_hybrid_sorts_similarity_scores() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        // 3 is the most similar row, even though it's passed last, so it's ranked above 6.
+        // It ties with 1, the only row that matches the BM25 query, and ties go to the
+        // lower key.
+        let keys = Spi::get_one::<String>(
+            r#"
+            SELECT string_agg(id::text, ',')
+            FROM paradedb.rank_hybrid(
+                jsonb_build_object(
+                    'query', 'title:secrets',
+                    'schema_name', current_schema(),
+                    'index_name', 'one_republic_songs_bm25_index',
+                    'table_name', 'one_republic_songs',
+                    'key_field', 'song_id'
+                ),
+                ARRAY[6, 3]::bigint[],
+                similarity_scores => ARRAY[0.9, 0.1]::real[]
+            )
+            "#,
+        )
+        .expect("failed to rank query")
+        .unwrap();
+        assert_eq!(keys, "1,3,6");
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_rank_hybrid_requires_scores() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        Spi::run(
+            r#"
+            SELECT * FROM paradedb.rank_hybrid(
+                jsonb_build_object(
+                    'query', 'title:counting',
+                    'schema_name', current_schema(),
+                    'index_name', 'one_republic_songs_bm25_index',
+                    'table_name', 'one_republic_songs',
+                    'key_field', 'song_id'
+                ),
+                ARRAY[3, 6]::bigint[],
+                strategy => 'minmax'
+            )
+            "#,
+        )
+        .unwrap();
+    }
+}
@@ -1,8 +1,10 @@
 use pgrx::{iter::TableIterator, *};
 use tantivy::schema::*;
 
-use crate::index_access::utils::get_parade_index;
-use crate::parade_index::fields::ToString;
+use crate::index_access::utils::{get_index_oid, get_parade_index};
+use crate::parade_index::fields::{ParadeOption, ToString};
+use crate::parade_index::gc;
+use crate::parade_index::index::ParadeIndex;
 
 #[allow(clippy::type_complexity)]
 #[pg_extern]
@@ -19,6 +21,7 @@ pub fn schema_bm25(
     name!(tokenizer, Option<String>),
     name!(record, Option<String>),
     name!(normalizer, Option<String>),
+    name!(filters, Option<Vec<String>>),
 )> {
     let bm25_index_name = format!("{}_bm25_index", index_name);
     let parade_index = get_parade_index(&bm25_index_name);
@@ -64,6 +67,21 @@ pub fn schema_bm25(
                 _ => ("Other".to_string(), None, None, None, None),
             };
 
+        // The filters aren't part of the Tantivy schema, only of the analyzer registered
+        // under the field's tokenizer name, so they're read from the field's options.
+        let filters = match parade_index.field_configs.get(&name) {
+            Some(ParadeOption::Text(text_options)) => Some(text_options.filter_names()),
+            Some(ParadeOption::Json(json_options)) => Some(
+                json_options
+                    .tokenizer
+                    .default_filters()
+                    .iter()
+                    .map(|filter| filter.name())
+                    .collect(),
+            ),
+            _ => None,
+        };
+
         let row = (
             name,
             field_type,
@@ -75,6 +93,7 @@ pub fn schema_bm25(
             tokenizer,
             record,
             normalizer,
+            filters,
         );
 
         field_rows.push(row);
@@ -83,12 +102,85 @@ pub fn schema_bm25(
     TableIterator::new(field_rows)
 }
 
+/// Reports the health of a bm25 index: its documents, segments, the terms of each field and
+/// the bytes on disk of each part of the index.
+#[pg_extern]
+pub fn index_stats_bm25(index_name: &str) -> JsonB {
+    let bm25_index_name = format!("{}_bm25_index", index_name);
+    let parade_index = get_parade_index(&bm25_index_name);
+    let stats = parade_index
+        .stats()
+        .unwrap_or_else(|err| panic!("error reading stats of index {index_name}: {err}"));
+
+    JsonB(
+        serde_json::to_value(stats)
+            .unwrap_or_else(|err| panic!("could not serialize stats of index {index_name}: {err}")),
+    )
+}
+
+/// Merges the segments of a bm25 index until it has at most `max_segments` of them. An index
+/// that was bulk loaded can end up with many small segments, which slow down searches.
+/// Like REINDEX, only the owner of the index can optimize it.
+#[pg_extern]
+pub fn optimize_bm25(index_name: &str, max_segments: default!(i32, 1)) {
+    if max_segments < 1 {
+        panic!("max_segments must be at least 1, received {max_segments}");
+    }
+
+    let bm25_index_name = format!("{}_bm25_index", index_name);
+    let index_oid = get_index_oid(None, &bm25_index_name);
+    if !is_index_owner(index_oid) {
+        panic!("must be owner of index {bm25_index_name}");
+    }
+
+    let parade_index = ParadeIndex::from_index_oid(index_oid);
+    parade_index
+        .optimize(max_segments as usize)
+        .unwrap_or_else(|err| panic!("error optimizing index {index_name}: {err}"));
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13", feature = "pg14", feature = "pg15"))]
+fn is_index_owner(index_oid: pg_sys::Oid) -> bool {
+    unsafe { pg_sys::pg_class_ownercheck(index_oid, pg_sys::GetUserId()) }
+}
+
+#[cfg(feature = "pg16")]
+fn is_index_owner(index_oid: pg_sys::Oid) -> bool {
+    unsafe { pg_sys::object_ownercheck(pg_sys::RelationRelationId, index_oid, pg_sys::GetUserId()) }
+}
+
+/// Deletes the index directories that no bm25 index owns, like the ones left behind by a
+/// crash, along with the user dictionary files that no index uses anymore, and returns the
+/// paths that were deleted along with the space they took up.
+#[pg_extern]
+pub fn gc_bm25() -> TableIterator<'static, (name!(path, String), name!(reclaimed_bytes, i64))> {
+    if !unsafe { pg_sys::superuser() } {
+        panic!("must be superuser to run gc_bm25");
+    }
+
+    let mut reclaimed = gc::collect_dropped_databases();
+    reclaimed.extend(gc::collect_current_database());
+    reclaimed.extend(gc::collect_user_dictionaries());
+
+    TableIterator::new(
+        reclaimed
+            .into_iter()
+            .map(|(path, bytes)| (path, bytes as i64)),
+    )
+}
+
 #[cfg(any(test, feature = "pg_test"))]
 #[pgrx::pg_schema]
 mod tests {
-    use super::schema_bm25;
+    use super::{gc_bm25, index_stats_bm25, optimize_bm25, schema_bm25};
+    use crate::operator::get_index_oid;
+    use crate::parade_index::index::ParadeIndex;
+    use crate::tokenizers::lindera::resolve_user_dictionary;
     use pgrx::*;
     use shared::testing::SETUP_SQL;
+    use std::fs;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
 
     #[pg_test]
     fn test_schema_bm25() {
@@ -114,5 +206,225 @@ mod tests {
                 "ctid",
             ]
         );
+
+        let description = schemas
+            .iter()
+            .find(|schema| schema.0 == "description")
+            .unwrap();
+        assert_eq!(
+            description.10,
+            Some(vec![
+                "remove_long_limit:40".to_string(),
+                "lowercase".to_string()
+            ])
+        );
+    }
+
+    #[pg_test]
+    fn test_schema_bm25_filters() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to setup index");
+        Spi::run(
+            r#"
+            CALL paradedb.create_bm25(
+                index_name => 'filtered_songs',
+                table_name => 'one_republic_songs',
+                key_field => 'song_id',
+                text_fields => '{lyrics: {filters: [{type: "lowercase"}, {type: "stopwords", language: "english"}, {type: "stemmer", language: "english"}]}}'
+            );
+            "#,
+        )
+        .expect("failed to create index with filters");
+
+        let lyrics = schema_bm25("filtered_songs")
+            .find(|schema| schema.0 == "lyrics")
+            .unwrap();
+        assert_eq!(
+            lyrics.10,
+            Some(vec![
+                "lowercase".to_string(),
+                "stopwords_language:english".to_string(),
+                "stemmer_language:english".to_string()
+            ])
+        );
+
+        // Stopwords aren't indexed, and stemmed terms match their other forms.
+        let count = Spi::get_one::<i64>("SELECT COUNT(*) FROM filtered_songs.search('lyrics:the')")
+            .unwrap();
+        assert_eq!(count, Some(0));
+        let count =
+            Spi::get_one::<i64>("SELECT COUNT(*) FROM filtered_songs.search('lyrics:dream')")
+                .unwrap();
+        assert_eq!(count, Some(1));
+    }
+
+    #[pg_test]
+    fn test_gc_bm25() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to setup index");
+        let oid = get_index_oid("one_republic_songs_bm25_index", "bm25")
+            .expect("could not find oid for one_republic")
+            .unwrap();
+
+        // A directory for a relation that doesn't exist, and a generation that the index
+        // doesn't point at.
+        let orphan = ParadeIndex::get_index_directory(pg_sys::Oid::from(u32::MAX - 1));
+        let generation = ParadeIndex::get_generation_directory(oid, 7);
+        fs::create_dir_all(&orphan).unwrap();
+        fs::write(format!("{orphan}/meta.json"), "{}").unwrap();
+        fs::create_dir_all(&generation).unwrap();
+
+        let reclaimed: Vec<String> = gc_bm25().map(|(path, _)| path).collect();
+        assert!(reclaimed.contains(&orphan));
+        assert!(reclaimed.contains(&generation));
+        assert!(!Path::new(&orphan).exists());
+        assert!(ParadeIndex::exists(oid));
+
+        let count = Spi::get_one::<i64>(
+            "SELECT COUNT(*) FROM one_republic_songs.search('album:\"Native\"')",
+        )
+        .unwrap();
+        assert_eq!(count, Some(2));
+    }
+
+    #[pg_test]
+    fn test_gc_bm25_user_dictionaries() {
+        crate::setup_background_workers();
+        Spi::run(
+            r#"
+            CREATE TABLE product_terms (surface TEXT, part_of_speech TEXT, reading TEXT);
+            INSERT INTO product_terms VALUES ('東京スカイツリー', 'カスタム名詞', 'トウキョウスカイツリー');
+            CREATE TABLE products (id SERIAL PRIMARY KEY, description TEXT);
+            INSERT INTO products (description) VALUES ('東京スカイツリーの最寄り駅');
+            CALL paradedb.create_bm25(
+                index_name => 'products',
+                table_name => 'products',
+                key_field => 'id',
+                text_fields => '{description: {tokenizer: {type: "japanese_lindera", user_dictionary: {table: "product_terms"}}}}'
+            );
+            "#,
+        )
+        .expect("failed to create index with a user dictionary");
+
+        // The dictionary the index uses, one that no index uses, and one that was just
+        // written. The first two were written long enough ago to be collected.
+        let data_dir = crate::env::postgres_data_dir_path();
+        let used = data_dir.join(resolve_user_dictionary("product_terms"));
+        let unused = data_dir.join("paradedb/lindera/0000000000000000.csv");
+        let recent = data_dir.join("paradedb/lindera/0000000000000001.csv");
+        fs::write(&unused, "").unwrap();
+        fs::write(&recent, "").unwrap();
+        for path in [&used, &unused] {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(2 * 60 * 60))
+                .unwrap();
+        }
+
+        let reclaimed: Vec<String> = gc_bm25().map(|(path, _)| path).collect();
+        assert!(reclaimed.contains(&unused.display().to_string()));
+        assert!(!unused.exists());
+        assert!(used.exists());
+        assert!(recent.exists());
+
+        let count = Spi::get_one::<i64>(
+            "SELECT COUNT(*) FROM products.search('description:東京スカイツリー')",
+        )
+        .unwrap();
+        assert_eq!(count, Some(1));
+    }
+
+    #[pg_test]
+    fn test_index_stats_bm25() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to setup index");
+
+        let JsonB(stats) = index_stats_bm25("one_republic_songs");
+        let num_segments = stats["num_segments"].as_u64().unwrap();
+        assert!(num_segments >= 1);
+        assert_eq!(
+            stats["segments"].as_array().unwrap().len() as u64,
+            num_segments
+        );
+        assert_eq!(stats["num_docs"].as_u64(), Some(7));
+        assert_eq!(stats["num_deleted_docs"].as_u64(), Some(0));
+        assert!(stats["total_bytes"].as_u64().unwrap() > 0);
+        assert!(stats["components"]["postings"].as_u64().unwrap() > 0);
+        assert!(stats["fields"]["title"]["num_terms"].as_u64().unwrap() > 0);
+        assert!(stats["fields"].get("ctid").is_some());
+    }
+
+    #[pg_test]
+    fn test_optimize_bm25() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to setup index");
+
+        // Each search commits the rows inserted before it into a segment of their own.
+        for release_year in 2020..2023 {
+            Spi::run(&format!(
+                "INSERT INTO one_republic_songs (title, album, release_year, genre, description, lyrics)
+                 VALUES ('Untitled', 'Unreleased', {release_year}, 'Pop', 'An unreleased song.', 'No lyrics yet.')"
+            ))
+            .expect("failed to insert row");
+            Spi::run("SELECT * FROM one_republic_songs.search('album:\"Unreleased\"')")
+                .expect("failed to search index");
+        }
+
+        let JsonB(stats) = index_stats_bm25("one_republic_songs");
+        assert!(stats["num_segments"].as_u64().unwrap() > 1);
+
+        optimize_bm25("one_republic_songs", 1);
+
+        let JsonB(stats) = index_stats_bm25("one_republic_songs");
+        assert_eq!(stats["num_segments"].as_u64(), Some(1));
+        assert_eq!(stats["num_docs"].as_u64(), Some(10));
+    }
+
+    #[pg_test]
+    fn test_optimize_bm25_leaves_uncommitted_rows() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to setup index");
+        let JsonB(stats) = index_stats_bm25("one_republic_songs");
+        assert_eq!(stats["num_docs"].as_u64(), Some(7));
+
+        // The row isn't committed to the index until this transaction commits, even if the
+        // index is optimized in the meantime.
+        Spi::run(
+            "INSERT INTO one_republic_songs (title, album, release_year, genre, description, lyrics)
+             VALUES ('Untitled', 'Unreleased', 2024, 'Pop', 'An unreleased song.', 'No lyrics yet.')",
+        )
+        .expect("failed to insert row");
+        optimize_bm25("one_republic_songs", 1);
+
+        let oid = get_index_oid("one_republic_songs_bm25_index", "bm25")
+            .expect("could not find oid for one_republic")
+            .unwrap();
+        let parade_index = ParadeIndex::from_index_oid(oid);
+        parade_index.reader.reload().unwrap();
+        assert_eq!(parade_index.reader.searcher().num_docs(), 7);
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "must be owner of index one_republic_songs_bm25_index")]
+    fn test_optimize_bm25_requires_owner() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to setup index");
+        Spi::run(
+            "CREATE ROLE bm25_optimizer;
+            SET ROLE bm25_optimizer;",
+        )
+        .expect("failed to set role");
+
+        optimize_bm25("one_republic_songs", 1);
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_optimize_bm25_max_segments() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to setup index");
+        optimize_bm25("one_republic_songs", 0);
     }
 }
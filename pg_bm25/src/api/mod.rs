@@ -1,2 +1,7 @@
+mod autocomplete;
+mod highlight;
+mod hybrid;
 mod index;
 mod search;
+mod suggest;
+mod tokenize;
@@ -1,75 +1,57 @@
 use crate::{
-    index_access::utils::{get_parade_index, SearchConfig},
-    parade_index::index::ParadeIndex,
+    index_access::utils::SearchConfig, parade_index::score::SearchAfter, security::HeapAccess,
 };
 use pgrx::{prelude::TableIterator, *};
-use tantivy::{schema::FieldType, SnippetGenerator};
 
 #[pg_extern]
 pub fn rank_bm25(
     config_json: JsonB,
-) -> TableIterator<'static, (name!(id, i64), name!(rank_bm25, f32))> {
+) -> TableIterator<
+    'static,
+    (
+        name!(id, i64),
+        name!(rank_bm25, f32),
+        name!(cursor, Option<String>),
+    ),
+> {
     let JsonB(search_config_json) = config_json;
     let search_config: SearchConfig =
         serde_json::from_value(search_config_json).expect("could not parse search config");
-    let parade_index = get_parade_index(&search_config.index_name);
+    let parade_index = search_config.parade_index();
+    let heap_access = HeapAccess::new(&search_config, parade_index);
 
     let mut scan_state = parade_index.scan_state(&search_config).unwrap();
-    let top_docs = scan_state.search();
+    let top_docs = heap_access.search(&mut scan_state);
 
+    // Results sorted by fields can't be continued with search_after, so they have no cursor.
+    let sorted = !search_config.sort_by.is_empty();
     let mut field_rows = Vec::new();
     for (score, _) in top_docs.into_iter() {
-        field_rows.push((score.key, score.bm25));
+        let cursor = (!sorted).then(|| SearchAfter(score).to_string());
+        field_rows.push((score.key, score.bm25, cursor));
     }
     TableIterator::new(field_rows)
 }
 
 #[pg_extern]
-pub fn highlight_bm25(
-    config_json: JsonB,
-) -> TableIterator<'static, (name!(id, i64), name!(highlight_bm25, String))> {
+pub fn explain_bm25(config_json: JsonB, key: i64) -> Option<JsonB> {
     let JsonB(search_config_json) = config_json;
     let search_config: SearchConfig =
         serde_json::from_value(search_config_json).expect("could not parse search config");
-    let parade_index = get_parade_index(&search_config.index_name);
-    let schema = parade_index.schema();
-    let function_schema = &search_config.schema_name;
-    let field_name = search_config.highlight_field.as_ref().unwrap_or_else(|| {
-        panic!("highlight_field parameter required for {function_schema}.highlight function")
-    });
-    let mut scan_state = parade_index.scan_state(&search_config).unwrap();
-    let top_docs = scan_state.search();
-
-    let highlight_field = schema
-        .get_field(field_name)
-        .unwrap_or_else(|err| panic!("error highlighting field {field_name}: {err:?}"));
-    let highlight_field_entry = schema.get_field_entry(highlight_field);
-
-    let mut snippet_generator = if let FieldType::Str(_) = highlight_field_entry.field_type() {
-        SnippetGenerator::create(&parade_index.searcher(), &scan_state.query, highlight_field)
-            .unwrap_or_else(|err| {
-                panic!("failed to create snippet generator for field: {field_name}... {err}")
-            })
-    } else {
-        panic!("can only highlight text fields")
-    };
-
-    if let Some(max_num_chars) = search_config.max_num_chars {
-        snippet_generator.set_max_num_chars(max_num_chars);
-    }
+    let parade_index = search_config.parade_index();
+    let heap_access = HeapAccess::new(&search_config, parade_index);
 
-    let mut field_rows = Vec::new();
-    for (_score, doc_address) in top_docs.into_iter() {
-        let document = scan_state
-            .doc(doc_address)
-            .unwrap_or_else(|err| panic!("error retrieving document for highlight: {err:?}"));
-        let snippet = snippet_generator.snippet_from_doc(&document);
-        let html = snippet.to_html();
-        let key = parade_index.get_key_value(&document);
-        field_rows.push((key, html));
+    // Don't explain scores for rows the user can't see.
+    if heap_access.filter(vec![key], |key| *key).is_empty() {
+        return None;
     }
 
-    TableIterator::new(field_rows)
+    let scan_state = parade_index.scan_state(&search_config).unwrap();
+    let explanation = scan_state.explain(key)?;
+
+    Some(JsonB(serde_json::to_value(explanation).unwrap_or_else(
+        |err| panic!("error serializing explanation for key {key}: {err}"),
+    )))
 }
 
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
@@ -80,10 +62,12 @@ pub fn minmax_bm25(
     let JsonB(search_config_json) = config_json;
     let search_config: SearchConfig =
         serde_json::from_value(search_config_json).expect("could not parse search config");
-    let parade_index = get_parade_index(&search_config.index_name);
+    let parade_index = search_config.parade_index();
+    let heap_access = HeapAccess::new(&search_config, parade_index);
 
     let mut scan_state = parade_index.scan_state(&search_config).unwrap();
-    let top_docs = scan_state.search();
+    // Filter before normalizing, so rows the user can't see don't affect the score range.
+    let top_docs = heap_access.search(&mut scan_state);
     let (min_score, max_score) = top_docs
         .iter()
         .map(|(score, _)| *score)
@@ -110,13 +94,6 @@ pub fn minmax_bm25(
     TableIterator::new(field_rows)
 }
 
-#[pg_extern]
-fn drop_bm25_internal(index_name: &str) {
-    // Drop the Tantivy data directory.
-    ParadeIndex::drop_index(index_name)
-        .unwrap_or_else(|err| panic!("error dropping index {index_name}: {err}"));
-}
-
 #[cfg(any(test, feature = "pg_test"))]
 #[pgrx::pg_schema]
 mod tests {
@@ -147,18 +124,54 @@ mod tests {
     }
 
     #[pg_test]
-    fn test_highlight() {
+    fn test_explain_bm25() {
         crate::setup_background_workers();
         Spi::run(SETUP_SQL).expect("failed to create index and table");
 
-        let query = r#"
-            SELECT highlight_bm25
-            FROM one_republic_songs.highlight('lyrics:im', highlight_field => 'lyrics', max_num_chars => 10);
+        let config = r#"
+            jsonb_build_object(
+                'query', 'lyrics:im',
+                'schema_name', current_schema(),
+                'index_name', 'one_republic_songs_bm25_index',
+                'table_name', 'one_republic_songs',
+                'key_field', 'song_id'
+            )
         "#;
 
-        let highlight = Spi::get_one::<&str>(query)
-            .expect("failed to highlight lyrics")
-            .unwrap();
-        assert_eq!(highlight, "<b>Im</b> holding");
+        let apologize_id =
+            Spi::get_one::<i32>("SELECT song_id FROM one_republic_songs WHERE title = 'Apologize'")
+                .expect("failed to get song id")
+                .unwrap();
+
+        let explained_score = Spi::get_one::<f32>(&format!(
+            "SELECT (paradedb.explain_bm25({config}, {apologize_id}) ->> 'value')::real"
+        ))
+        .expect("failed to explain score")
+        .unwrap();
+        let rank = Spi::get_one::<f32>(&format!(
+            "SELECT rank_bm25 FROM one_republic_songs.rank('lyrics:im') WHERE song_id = {apologize_id}"
+        ))
+        .expect("failed to rank query")
+        .unwrap();
+        assert_eq!(explained_score, rank);
+
+        let explanation = Spi::get_one::<JsonB>(&format!(
+            "SELECT paradedb.explain_bm25({config}, {apologize_id})"
+        ))
+        .expect("failed to explain score")
+        .unwrap();
+        assert!(explanation.0.to_string().contains("idf"));
+
+        // Secrets doesn't match the query, and there's no song with key 0.
+        let secrets_explanation = Spi::get_one::<JsonB>(&format!(
+            "SELECT paradedb.explain_bm25({config}, song_id) FROM one_republic_songs WHERE title = 'Secrets'"
+        ))
+        .expect("failed to explain score");
+        assert!(secrets_explanation.is_none());
+
+        let missing_explanation =
+            Spi::get_one::<JsonB>(&format!("SELECT paradedb.explain_bm25({config}, 0)"))
+                .expect("failed to explain score");
+        assert!(missing_explanation.is_none());
     }
 }
@@ -0,0 +1,211 @@
+use pgrx::{iter::TableIterator, *};
+use serde_json::json;
+use tantivy::{tokenizer::TokenStream, Term};
+
+use crate::index_access::utils::get_index_oid;
+use crate::parade_index::fields::ParadeOption;
+use crate::parade_index::fuzzy::{DEFAULT_TRANSPOSE_COST_ONE, MAX_FUZZY_DISTANCE};
+use crate::parade_index::index::ParadeIndex;
+use crate::security::HeapAccess;
+
+/// How many alternatives to return for each misspelled term.
+const MAX_SUGGESTIONS_PER_TERM: usize = 5;
+
+#[pg_extern]
+pub fn suggest_bm25(
+    index_name: &str,
+    query: &str,
+    field_name: &str,
+    max_distance: default!(i32, 2),
+) -> TableIterator<'static, (name!(corrected_query, String), name!(suggestions, JsonB))> {
+    let bm25_index_name = format!("{}_bm25_index", index_name);
+    let index_oid = get_index_oid(None, &bm25_index_name);
+    let parade_index = ParadeIndex::from_index_oid(index_oid);
+
+    // Like autocomplete, suggestions come from the index vocabulary as a whole, which
+    // might contain terms from rows the current user isn't allowed to see.
+    HeapAccess::from_index(index_oid, parade_index).deny_row_security("suggest_bm25");
+
+    let max_distance: u8 = u8::try_from(max_distance)
+        .ok()
+        .filter(|distance| *distance <= MAX_FUZZY_DISTANCE)
+        .unwrap_or_else(|| {
+            panic!(
+                "max_distance must be between 0 and {MAX_FUZZY_DISTANCE}, received {max_distance}"
+            )
+        });
+
+    let field = parade_index
+        .schema()
+        .get_field(field_name)
+        .unwrap_or_else(|err| panic!("error suggesting terms for field {field_name}: {err:?}"));
+
+    if !matches!(
+        parade_index.field_configs.get(field_name),
+        Some(ParadeOption::Text(_))
+    ) {
+        panic!("can only suggest terms for text fields, {field_name} is not a text field")
+    }
+
+    // Tokenize the query the same way the field was tokenized when it was indexed, so that
+    // each token can be looked up in the term dictionary as is.
+    let mut analyzer = parade_index
+        .tokenizer_for_field(field)
+        .unwrap_or_else(|err| panic!("error loading tokenizer for field {field_name}: {err:?}"));
+    let mut tokens = vec![];
+    let mut token_stream = analyzer.token_stream(query);
+    while token_stream.advance() {
+        tokens.push(token_stream.token().clone());
+    }
+
+    let searcher = parade_index.searcher();
+    let mut corrected_query = String::with_capacity(query.len());
+    let mut corrected_until = 0;
+    let mut suggestions = vec![];
+
+    for token in tokens {
+        let doc_freq = searcher
+            .doc_freq(&Term::from_field_text(field, &token.text))
+            .unwrap_or_else(|err| panic!("error reading terms for field {field_name}: {err:?}"));
+
+        // Terms that are already in the index are left alone.
+        if doc_freq > 0 {
+            continue;
+        }
+
+        let mut candidates: Vec<(String, (u8, u64))> = parade_index
+            .fuzzy_terms(field, &token.text, max_distance, DEFAULT_TRANSPOSE_COST_ONE)
+            .unwrap_or_else(|err| panic!("error reading terms for field {field_name}: {err:?}"))
+            .into_iter()
+            .collect();
+
+        // Closest terms first, then the most frequent ones, then alphabetically.
+        candidates.sort_by(|(term_a, (dist_a, freq_a)), (term_b, (dist_b, freq_b))| {
+            dist_a
+                .cmp(dist_b)
+                .then_with(|| freq_b.cmp(freq_a))
+                .then_with(|| term_a.cmp(term_b))
+        });
+        candidates.truncate(MAX_SUGGESTIONS_PER_TERM);
+
+        // Replace the misspelled token in the original query with the best candidate, if
+        // there is one, cased like the text it replaces. Everything else is copied from the
+        // query as is. Some tokenizers produce overlapping tokens, so only the first
+        // replacement for a given stretch of the query is applied.
+        if let Some((best, _)) = candidates.first() {
+            if token.offset_from >= corrected_until {
+                corrected_query.push_str(&query[corrected_until..token.offset_from]);
+                corrected_query.push_str(&match_case(
+                    &query[token.offset_from..token.offset_to],
+                    best,
+                ));
+                corrected_until = token.offset_to;
+            }
+        }
+
+        suggestions.push(json!({
+            "term": token.text,
+            "suggestions": candidates
+                .into_iter()
+                .map(|(term, (distance, doc_freq))| json!({
+                    "term": term,
+                    "distance": distance,
+                    "doc_freq": doc_freq,
+                }))
+                .collect::<Vec<_>>(),
+        }));
+    }
+    corrected_query.push_str(&query[corrected_until..]);
+
+    TableIterator::once((corrected_query, JsonB(json!(suggestions))))
+}
+
+/// Cases a suggested term like the text it replaces. Indexed terms are usually lowercased,
+/// so this restores an uppercased or capitalized word, and leaves any other term as is.
+fn match_case(original: &str, suggestion: &str) -> String {
+    let letters: Vec<char> = original.chars().filter(|c| c.is_alphabetic()).collect();
+
+    if letters.len() > 1 && letters.iter().all(|c| c.is_uppercase()) {
+        suggestion.to_uppercase()
+    } else if letters.first().is_some_and(|c| c.is_uppercase()) {
+        let mut chars = suggestion.chars();
+        chars
+            .next()
+            .map(|c| c.to_uppercase().chain(chars).collect())
+            .unwrap_or_default()
+    } else {
+        suggestion.to_string()
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::suggest_bm25;
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+
+    #[pg_test]
+    fn test_suggest_bm25() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let (corrected_query, JsonB(suggestions)) =
+            suggest_bm25("one_republic_songs", "Countng Stars", "title", 2)
+                .next()
+                .unwrap();
+        assert_eq!(corrected_query, "Counting Stars");
+
+        let suggestions = suggestions.as_array().unwrap();
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0]["term"], "countng");
+        assert_eq!(suggestions[0]["suggestions"][0]["term"], "counting");
+        assert_eq!(suggestions[0]["suggestions"][0]["distance"], 1);
+        assert_eq!(suggestions[0]["suggestions"][0]["doc_freq"], 1);
+    }
+
+    #[pg_test]
+    fn test_suggest_bm25_ranks_closest_terms_first() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        // "stop" is one edit away from "stog" and is in a single description, while "song"
+        // is two edits away and is in every description.
+        let (corrected_query, JsonB(suggestions)) =
+            suggest_bm25("one_republic_songs", "stog", "description", 2)
+                .next()
+                .unwrap();
+        assert_eq!(corrected_query, "stop");
+
+        let candidates = suggestions[0]["suggestions"].as_array().unwrap();
+        assert_eq!(candidates[0]["term"], "stop");
+        assert_eq!(candidates[0]["distance"], 1);
+        assert!(candidates
+            .iter()
+            .any(|candidate| candidate["term"] == "song" && candidate["doc_freq"] == 7));
+    }
+
+    #[pg_test]
+    fn test_suggest_bm25_keeps_query_casing() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let (corrected_query, _) = suggest_bm25("one_republic_songs", "STARS countng", "title", 2)
+            .next()
+            .unwrap();
+        assert_eq!(corrected_query, "STARS counting");
+    }
+
+    #[pg_test]
+    fn test_suggest_bm25_without_match() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let (corrected_query, JsonB(suggestions)) =
+            suggest_bm25("one_republic_songs", "zzzzzz", "title", 1)
+                .next()
+                .unwrap();
+        assert_eq!(corrected_query, "zzzzzz");
+        assert_eq!(suggestions[0]["suggestions"].as_array().unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,184 @@
+use pgrx::{iter::TableIterator, *};
+use serde::Deserialize;
+use tantivy::tokenizer::{TextAnalyzer, TokenStream};
+
+use crate::index_access::utils::get_index_oid;
+use crate::parade_index::fields::{
+    ParadeOption, ParadeTextOptions, ParadeTokenFilter, ParadeTokenizer,
+};
+use crate::parade_index::index::ParadeIndex;
+
+type TokenRow = (
+    name!(token, String),
+    name!(position, i32),
+    name!(offset_from, i32),
+    name!(offset_to, i32),
+);
+
+/// A tokenizer, as it would be configured in the options of a text field, along with the
+/// filters to apply to its tokens.
+#[derive(Deserialize)]
+struct TokenizeOptions {
+    #[serde(flatten)]
+    tokenizer: ParadeTokenizer,
+    #[serde(default)]
+    filters: Option<Vec<ParadeTokenFilter>>,
+}
+
+#[pg_extern]
+pub fn tokenize(tokenizer: JsonB, input: &str) -> TableIterator<'static, TokenRow> {
+    let JsonB(tokenizer_json) = tokenizer;
+    let tokenize_options: TokenizeOptions = serde_json::from_value(tokenizer_json)
+        .unwrap_or_else(|err| panic!("could not parse tokenizer: {err}"));
+
+    let mut text_options = ParadeTextOptions::default();
+    text_options.tokenizer = tokenize_options.tokenizer;
+    text_options.filters = tokenize_options.filters;
+    text_options.validate("tokenize");
+
+    TableIterator::new(token_rows(&mut text_options.analyzer(), input))
+}
+
+/// Tokenizes text the way a field is indexed, or with `query`, the way queries on the field
+/// are tokenized, which differs for fields with phonetic filters or the edge_ngram tokenizer.
+#[pg_extern]
+pub fn tokenize_field(
+    index_name: &str,
+    field_name: &str,
+    input: &str,
+    query: default!(bool, false),
+) -> TableIterator<'static, TokenRow> {
+    let bm25_index_name = format!("{}_bm25_index", index_name);
+    let index_oid = get_index_oid(None, &bm25_index_name);
+    let parade_index = ParadeIndex::from_index_oid(index_oid);
+
+    let field = parade_index
+        .schema()
+        .get_field(field_name)
+        .unwrap_or_else(|err| panic!("error tokenizing field {field_name}: {err:?}"));
+
+    let mut analyzer = match parade_index.field_configs.get(field_name) {
+        Some(ParadeOption::Text(text_options)) if query => text_options.query_analyzer(),
+        Some(ParadeOption::Json(json_options)) if query => json_options.tokenizer.query_analyzer(),
+        // This is the analyzer registered with the index under the field's tokenizer name,
+        // so it's the one the field is indexed with.
+        Some(ParadeOption::Text(_)) | Some(ParadeOption::Json(_)) => parade_index
+            .tokenizer_for_field(field)
+            .unwrap_or_else(|err| {
+                panic!("error loading tokenizer for field {field_name}: {err:?}")
+            }),
+        _ => panic!("can only tokenize text and json fields, {field_name} is neither"),
+    };
+
+    TableIterator::new(token_rows(&mut analyzer, input))
+}
+
+/// Offsets are in bytes, like the offsets Tantivy records.
+fn token_rows(analyzer: &mut TextAnalyzer, input: &str) -> Vec<TokenRow> {
+    let mut rows = vec![];
+    let mut token_stream = analyzer.token_stream(input);
+    while token_stream.advance() {
+        let token = token_stream.token();
+        rows.push((
+            token.text.clone(),
+            token.position as i32,
+            token.offset_from as i32,
+            token.offset_to as i32,
+        ));
+    }
+    rows
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::{tokenize, tokenize_field};
+    use pgrx::*;
+    use serde_json::json;
+    use shared::testing::SETUP_SQL;
+
+    #[pg_test]
+    fn test_tokenize() {
+        let rows: Vec<_> = tokenize(JsonB(json!({"type": "default"})), "Hello, World").collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("hello".to_string(), 0, 0, 5),
+                ("world".to_string(), 1, 7, 12)
+            ]
+        );
+
+        let rows: Vec<_> = tokenize(
+            JsonB(json!({
+                "type": "whitespace",
+                "filters": [{"type": "lowercase"}, {"type": "stopwords", "words": ["the"]}]
+            })),
+            "The Stars",
+        )
+        .map(|(token, ..)| token)
+        .collect();
+        assert_eq!(rows, vec!["stars".to_string()]);
+
+        let rows: Vec<_> = tokenize(
+            JsonB(json!({
+                "type": "stem",
+                "language": "english",
+                "filters": [{"type": "lowercase"}]
+            })),
+            "Running Shoes",
+        )
+        .map(|(token, ..)| token)
+        .collect();
+        assert_eq!(rows, vec!["run".to_string(), "shoe".to_string()]);
+    }
+
+    #[pg_test]
+    fn test_tokenize_field() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CALL paradedb.create_bm25(
+                index_name => 'stemmed_songs',
+                table_name => 'one_republic_songs',
+                key_field => 'song_id',
+                text_fields => '{lyrics: {tokenizer: {type: "en_stem"}}}'
+            );
+            "#,
+        )
+        .expect("failed to create index");
+
+        let rows: Vec<_> = tokenize_field("stemmed_songs", "lyrics", "Counting Stars", false)
+            .map(|(token, ..)| token)
+            .collect();
+        assert_eq!(rows, vec!["count".to_string(), "star".to_string()]);
+    }
+
+    #[pg_test]
+    fn test_tokenize_field_for_query() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CALL paradedb.create_bm25(
+                index_name => 'edge_ngram_songs',
+                table_name => 'one_republic_songs',
+                key_field => 'song_id',
+                text_fields => '{title: {tokenizer: {type: "edge_ngram", min_gram: 2, max_gram: 3}}}'
+            );
+            "#,
+        )
+        .expect("failed to create index");
+
+        // The field is indexed by the prefixes of its words, but queried by whole words.
+        let rows: Vec<_> = tokenize_field("edge_ngram_songs", "title", "Stop", false)
+            .map(|(token, ..)| token)
+            .collect();
+        assert_eq!(rows, vec!["st".to_string(), "sto".to_string()]);
+
+        let rows: Vec<_> = tokenize_field("edge_ngram_songs", "title", "Stop", true)
+            .map(|(token, ..)| token)
+            .collect();
+        assert_eq!(rows, vec!["stop".to_string()]);
+    }
+}
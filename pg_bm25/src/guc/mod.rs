@@ -0,0 +1,188 @@
+use pgrx::*;
+
+use crate::parade_index::fuzzy::{DEFAULT_FUZZY_DISTANCE, MAX_FUZZY_DISTANCE};
+
+// Initialize extension-specific GUC settings.
+pub static PARADE_GUC: ParadeGUC = ParadeGUC::new();
+
+// Default limit_rows of a search, where 0 returns every match
+const DEFAULT_LIMIT_ROWS: i32 = 0;
+const MIN_LIMIT_ROWS: i32 = 0;
+const MAX_LIMIT_ROWS: i32 = i32::MAX;
+
+// Default edit distance of fuzzy searches
+const MIN_FUZZY_DISTANCE: i32 = 0;
+
+// Blocks of the document store cached by each segment reader
+const DEFAULT_STORE_CACHE_BLOCKS: i32 = 10;
+const MIN_STORE_CACHE_BLOCKS: i32 = 0;
+const MAX_STORE_CACHE_BLOCKS: i32 = 10_000;
+
+// Writer memory budget, shared by the indexing threads of each index writer
+const DEFAULT_WRITER_MEMORY_BUDGET_MB: i32 = 500;
+const MIN_WRITER_MEMORY_BUDGET_MB: i32 = 15;
+const MAX_WRITER_MEMORY_BUDGET_MB: i32 = 32768;
+
+// Writer indexing threads
+const DEFAULT_WRITER_THREADS: i32 = 0;
+const MIN_WRITER_THREADS: i32 = 0;
+const MAX_WRITER_THREADS: i32 = 64;
+
+// Commit coalescing interval
+const DEFAULT_COMMIT_INTERVAL_MS: i32 = 0;
+const MIN_COMMIT_INTERVAL_MS: i32 = 0;
+const MAX_COMMIT_INTERVAL_MS: i32 = 60_000;
+
+// Log writer commits
+const DEFAULT_LOG_WRITER_COMMITS: bool = false;
+
+pub struct ParadeGUC {
+    pub default_limit_rows: GucSetting<i32>,
+    pub default_fuzzy_distance: GucSetting<i32>,
+    pub store_cache_blocks: GucSetting<i32>,
+    pub writer_memory_budget_mb: GucSetting<i32>,
+    pub writer_threads: GucSetting<i32>,
+    pub commit_interval_ms: GucSetting<i32>,
+    pub log_writer_commits: GucSetting<bool>,
+}
+
+impl ParadeGUC {
+    pub const fn new() -> Self {
+        Self {
+            default_limit_rows: GucSetting::<i32>::new(DEFAULT_LIMIT_ROWS),
+            default_fuzzy_distance: GucSetting::<i32>::new(DEFAULT_FUZZY_DISTANCE as i32),
+            store_cache_blocks: GucSetting::<i32>::new(DEFAULT_STORE_CACHE_BLOCKS),
+            writer_memory_budget_mb: GucSetting::<i32>::new(DEFAULT_WRITER_MEMORY_BUDGET_MB),
+            writer_threads: GucSetting::<i32>::new(DEFAULT_WRITER_THREADS),
+            commit_interval_ms: GucSetting::<i32>::new(DEFAULT_COMMIT_INTERVAL_MS),
+            log_writer_commits: GucSetting::<bool>::new(DEFAULT_LOG_WRITER_COMMITS),
+        }
+    }
+
+    /// Registers the settings with Postgres. Must be called in `_PG_init()`.
+    ///
+    /// The search settings are read by the backend running the search, so they can be
+    /// changed for a session. The writer settings are read by the writer background worker,
+    /// which is started by the postmaster, so they can only be changed in postgresql.conf,
+    /// and take effect when Postgres is restarted.
+    pub fn init(&self) {
+        GucRegistry::define_int_guc(
+            "paradedb.pg_bm25.default_limit_rows",
+            "The number of rows a bm25 search function returns when it isn't given a limit_rows.",
+            "Searches with a limit return their best scoring rows. 0 returns every matching row. The @@@ operator isn't limited. Defaults to 0.",
+            &self.default_limit_rows,
+            MIN_LIMIT_ROWS,
+            MAX_LIMIT_ROWS,
+            GucContext::Userset,
+            GucFlags::default(),
+        );
+
+        GucRegistry::define_int_guc(
+            "paradedb.pg_bm25.default_fuzzy_distance",
+            "The edit distance of fuzzy bm25 searches that aren't given a distance.",
+            "Defaults to 2.",
+            &self.default_fuzzy_distance,
+            MIN_FUZZY_DISTANCE,
+            MAX_FUZZY_DISTANCE as i32,
+            GucContext::Userset,
+            GucFlags::default(),
+        );
+
+        GucRegistry::define_int_guc(
+            "paradedb.pg_bm25.store_cache_blocks",
+            "The number of decompressed blocks of stored documents cached for each segment of a bm25 index.",
+            "Takes effect for indexes that are opened after it's changed. Defaults to 10.",
+            &self.store_cache_blocks,
+            MIN_STORE_CACHE_BLOCKS,
+            MAX_STORE_CACHE_BLOCKS,
+            GucContext::Userset,
+            GucFlags::default(),
+        );
+
+        GucRegistry::define_int_guc(
+            "paradedb.pg_bm25.writer_memory_budget",
+            "The memory used to buffer documents before they're written to a bm25 index.",
+            "Each index that's being written to gets this much memory, split between its indexing threads. Defaults to 500MB.",
+            &self.writer_memory_budget_mb,
+            MIN_WRITER_MEMORY_BUDGET_MB,
+            MAX_WRITER_MEMORY_BUDGET_MB,
+            GucContext::Postmaster,
+            GucFlags::UNIT_MB,
+        );
+
+        GucRegistry::define_int_guc(
+            "paradedb.pg_bm25.writer_threads",
+            "The number of threads that index documents for each bm25 index.",
+            "0 picks one thread per CPU, up to 8. Each thread needs at least 15MB of the writer memory budget. Defaults to 0.",
+            &self.writer_threads,
+            MIN_WRITER_THREADS,
+            MAX_WRITER_THREADS,
+            GucContext::Postmaster,
+            GucFlags::default(),
+        );
+
+        GucRegistry::define_int_guc(
+            "paradedb.pg_bm25.commit_interval",
+            "How long committing transactions can wait for their writes to bm25 indexes to be committed together.",
+            "Committing transactions wait for the commit that covers their writes. 0 commits the writes of each transaction on its own. Defaults to 0.",
+            &self.commit_interval_ms,
+            MIN_COMMIT_INTERVAL_MS,
+            MAX_COMMIT_INTERVAL_MS,
+            GucContext::Postmaster,
+            GucFlags::UNIT_MS,
+        );
+
+        GucRegistry::define_bool_guc(
+            "paradedb.pg_bm25.log_writer_commits",
+            "Log every commit of the bm25 writer to the server log.",
+            "Each entry has the number of indexes committed and how long the commit took. Defaults to false.",
+            &self.log_writer_commits,
+            GucContext::Postmaster,
+            GucFlags::default(),
+        );
+
+        // Logging to the paradedb.logs table has its own setting, shared with the other
+        // ParadeDB extensions.
+        crate::PARADE_LOGS_GLOBAL.init();
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::PARADE_GUC;
+    use pgrx::*;
+
+    #[pg_test]
+    fn test_search_settings() {
+        Spi::run("SET paradedb.pg_bm25.default_limit_rows = 5").expect("failed to set limit");
+        assert_eq!(PARADE_GUC.default_limit_rows.get(), 5);
+
+        Spi::run("RESET paradedb.pg_bm25.default_limit_rows").expect("failed to reset limit");
+        assert_eq!(PARADE_GUC.default_limit_rows.get(), 0);
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_fuzzy_distance_setting_bounds() {
+        Spi::run("SET paradedb.pg_bm25.default_fuzzy_distance = 3").unwrap();
+    }
+
+    #[pg_test]
+    fn test_writer_settings() {
+        let memory_budget = Spi::get_one::<String>("SHOW paradedb.pg_bm25.writer_memory_budget")
+            .expect("failed to show writer_memory_budget");
+        assert_eq!(memory_budget.as_deref(), Some("500MB"));
+
+        let commit_interval = Spi::get_one::<String>("SHOW paradedb.pg_bm25.commit_interval")
+            .expect("failed to show commit_interval");
+        assert_eq!(commit_interval.as_deref(), Some("0"));
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_writer_settings_need_restart() {
+        // The writer runs in a background worker, so its settings can't change in a session.
+        Spi::run("SET paradedb.pg_bm25.writer_threads = 2").unwrap();
+    }
+}
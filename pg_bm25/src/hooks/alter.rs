@@ -0,0 +1,242 @@
+use pgrx::*;
+use serde_json::json;
+use shared::plog;
+
+use crate::index_access::build;
+use crate::index_access::options::ParadeOptions;
+use crate::index_access::utils::is_bm25_index;
+use crate::parade_index::fields::ParadeOptionMap;
+use crate::parade_index::index::ParadeIndex;
+use crate::parade_index::merge_policy::ParadeMergePolicy;
+
+/// The options of a bm25 index from before an ALTER INDEX statement changed them.
+pub struct AlteredIndex {
+    oid: pg_sys::Oid,
+    key_field: String,
+    field_configs: ParadeOptionMap,
+    merge_policy: ParadeMergePolicy,
+}
+
+/// Remembers the options of a bm25 index that's about to have them set or reset, so that
+/// the fields that changed can be found afterwards.
+pub unsafe fn before_alter(alter_stmt: *mut pg_sys::AlterTableStmt) -> Option<AlteredIndex> {
+    let cmds = PgList::<pg_sys::AlterTableCmd>::from_pg((*alter_stmt).cmds);
+    let alters_options = cmds.iter_ptr().any(|cmd| {
+        matches!(
+            (*cmd).subtype,
+            pg_sys::AlterTableType_AT_SetRelOptions | pg_sys::AlterTableType_AT_ResetRelOptions
+        )
+    });
+    if !alters_options {
+        return None;
+    }
+
+    let flags = if (*alter_stmt).missing_ok {
+        pg_sys::RVROption_RVR_MISSING_OK
+    } else {
+        0
+    };
+    let rangevar_oid = pg_sys::RangeVarGetRelidExtended(
+        (*alter_stmt).relation,
+        pg_sys::ShareUpdateExclusiveLock as i32,
+        flags,
+        None,
+        std::ptr::null_mut(),
+    );
+    if rangevar_oid == pg_sys::InvalidOid {
+        return None;
+    }
+
+    let index_relation = PgRelation::open(rangevar_oid);
+    if !is_bm25_index(&index_relation) || index_relation.rd_options.is_null() {
+        return None;
+    }
+
+    let options = &*(index_relation.rd_options as *const ParadeOptions);
+    Some(AlteredIndex {
+        oid: rangevar_oid,
+        key_field: options.get_key_field(),
+        field_configs: options.get_field_configs(),
+        merge_policy: options.get_merge_policy(),
+    })
+}
+
+/// Rebuilds a bm25 index whose fields were changed by ALTER INDEX, as part of the statement.
+/// Other transactions keep querying and writing to the current generation until the new one
+/// is swapped in at commit, while this one uses the new generation right away.
+pub unsafe fn after_alter(altered_index: AlteredIndex) {
+    // Make the new options visible to this command.
+    pg_sys::CommandCounterIncrement();
+
+    let index_relation = PgRelation::open(altered_index.oid);
+    let index_name = index_relation.name();
+    if index_relation.rd_options.is_null() {
+        panic!("bm25 index {index_name} must have options")
+    }
+
+    let options = &*(index_relation.rd_options as *const ParadeOptions);
+    if options.get_key_field() != altered_index.key_field {
+        panic!("the key_field of bm25 index {index_name} cannot be changed, create a new index instead")
+    }
+
+    // A rebuild picks up the new merge policy along with the new fields, so the merge
+    // policy only has to be changed on its own when none of the fields changed.
+    let changed_fields = changed_fields(&altered_index.field_configs, &options.get_field_configs());
+    if changed_fields.is_empty() {
+        let merge_policy = options.get_merge_policy();
+        if merge_policy != altered_index.merge_policy {
+            ParadeIndex::set_merge_policy(altered_index.oid, &merge_policy).unwrap_or_else(|err| {
+                panic!("error changing the merge policy of {index_name}: {err:?}")
+            });
+        }
+        return;
+    }
+
+    let heap_relation = index_relation
+        .heap_relation()
+        .unwrap_or_else(|| panic!("bm25 index {index_name} has no table"));
+
+    plog!(
+        "rebuilding bm25 index",
+        json!({
+            "name": index_name,
+            "changed_fields": changed_fields
+        })
+    );
+
+    build::rebuild_concurrently(&heap_relation, &index_relation);
+}
+
+/// The names of the fields that were added, removed or configured differently.
+fn changed_fields(old: &ParadeOptionMap, new: &ParadeOptionMap) -> Vec<String> {
+    let config = |field_configs: &ParadeOptionMap, field_name: &str| {
+        field_configs.get(field_name).map(|field_config| {
+            serde_json::to_value(field_config)
+                .unwrap_or_else(|err| panic!("could not serialize options of {field_name}: {err}"))
+        })
+    };
+
+    let mut field_names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    field_names.sort();
+    field_names.dedup();
+    field_names
+        .into_iter()
+        .filter(|field_name| config(old, field_name) != config(new, field_name))
+        .cloned()
+        .collect()
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use crate::operator::get_index_oid;
+    use crate::parade_index::index::ParadeIndex;
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+    use std::fs;
+    use std::path::Path;
+
+    #[pg_test]
+    fn test_alter_index_builds_generation() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            "ALTER INDEX one_republic_songs_bm25_index SET (text_fields = '{title: {}, description: {}}')",
+        )
+        .expect("failed to alter index");
+
+        // The new generation only has the fields that are left, along with the key, the
+        // numeric field and the ctid.
+        let oid = get_index_oid("one_republic_songs_bm25_index", "bm25")
+            .expect("could not find oid for one_republic")
+            .unwrap();
+        let generation =
+            ParadeIndex::from_index_directory(&ParadeIndex::get_generation_directory(oid, 1));
+        assert_eq!(generation.fields.len(), 5);
+
+        // Other backends keep reading the current generation until the transaction commits,
+        // while this one reads the new generation.
+        let current_data_directory =
+            ParadeIndex::current_data_directory(&ParadeIndex::get_index_directory(oid)).unwrap();
+        assert_eq!(
+            current_data_directory,
+            ParadeIndex::get_index_directory(oid)
+        );
+        assert_eq!(ParadeIndex::from_index_oid(oid).generation, 1);
+        let count = Spi::get_one::<i64>(
+            "SELECT count(*) FROM one_republic_songs.search('title:\"Counting Stars\"')",
+        )
+        .expect("failed to search index");
+        assert_eq!(count, Some(1));
+    }
+
+    #[pg_test]
+    fn test_alter_index_then_insert() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            "ALTER INDEX one_republic_songs_bm25_index SET (text_fields = '{title: {}, description: {}}')",
+        )
+        .expect("failed to alter index");
+
+        // Rows written after the rebuild in the same transaction go into the new generation.
+        Spi::run(
+            "INSERT INTO one_republic_songs (song_id, title, description) VALUES (100, 'Rescue Me', 'A new song')",
+        )
+        .expect("failed to insert row");
+        let count =
+            Spi::get_one::<i64>("SELECT count(*) FROM one_republic_songs.search('title:rescue')")
+                .expect("failed to search index");
+        assert_eq!(count, Some(1));
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_alter_index_key_field() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run("ALTER INDEX one_republic_songs_bm25_index SET (key_field = 'title')").unwrap();
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_alter_index_validates_options() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run("ALTER INDEX one_republic_songs_bm25_index SET (numeric_fields = '{title: {}}')")
+            .unwrap();
+    }
+
+    #[pg_test]
+    fn test_alter_index_merge_policy() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            "ALTER INDEX one_republic_songs_bm25_index SET (merge_policy = '{type: \"no_merge\"}')",
+        )
+        .expect("failed to alter index");
+
+        // Changing the merge policy doesn't rebuild the index, only its configuration.
+        let oid = get_index_oid("one_republic_songs_bm25_index", "bm25")
+            .expect("could not find oid for one_republic")
+            .unwrap();
+        assert!(!Path::new(&ParadeIndex::get_generation_directory(oid, 1)).exists());
+
+        let config_path =
+            ParadeIndex::get_field_configs_path(ParadeIndex::get_index_directory(oid));
+        let config: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(config_path).unwrap()).unwrap();
+        assert_eq!(config["merge_policy"]["type"], "no_merge");
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_alter_index_validates_merge_policy() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            "ALTER INDEX one_republic_songs_bm25_index SET (merge_policy = '{type: \"log\", min_num_segments: 1}')",
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use once_cell::unsync::Lazy;
+use pgrx::*;
+use std::os::raw::{c_int, c_void};
+
+use crate::parade_index::index::ParadeIndex;
+
+static mut PREV_OBJECT_ACCESS_HOOK: pg_sys::object_access_hook_type = None;
+
+/// The bm25 indexes dropped by the current transaction, along with the subtransaction that
+/// dropped each of them. Their directories are deleted once the transaction commits.
+static mut PENDING_DROPS: Lazy<Vec<(pg_sys::Oid, pg_sys::SubTransactionId)>> = Lazy::new(Vec::new);
+
+/// Registers the hook that removes the directory of a bm25 index when it's dropped.
+/// Indexes are dropped by more than DROP statements, like DROP TABLE and DROP SCHEMA
+/// with CASCADE, or REINDEX CONCURRENTLY dropping the index it replaced, so this is
+/// hooked into object access rather than into utility statements.
+pub unsafe fn init() {
+    PREV_OBJECT_ACCESS_HOOK = pg_sys::object_access_hook;
+    pg_sys::object_access_hook = Some(object_access);
+
+    pg_sys::RegisterXactCallback(Some(xact_callback), std::ptr::null_mut());
+    pg_sys::RegisterSubXactCallback(Some(subxact_callback), std::ptr::null_mut());
+}
+
+#[pg_guard]
+unsafe extern "C" fn object_access(
+    access: pg_sys::ObjectAccessType,
+    class_id: pg_sys::Oid,
+    object_id: pg_sys::Oid,
+    sub_id: c_int,
+    arg: *mut c_void,
+) {
+    if let Some(prev_hook) = PREV_OBJECT_ACCESS_HOOK {
+        prev_hook(access, class_id, object_id, sub_id, arg);
+    }
+
+    // Only bm25 indexes have a directory, so the directory is enough to tell them apart
+    // from every other relation. The directory can't be deleted yet, because the index
+    // has to keep its data if the drop is rolled back. This runs for every relation that's
+    // dropped, so indexes built by older versions aren't moved into their directory here,
+    // and a dropped index that was never moved keeps its old directory.
+    if access == pg_sys::ObjectAccessType_OAT_DROP
+        && class_id == pg_sys::RelationRelationId
+        && sub_id == 0
+        && ParadeIndex::directory_exists(object_id)
+    {
+        PENDING_DROPS.push((object_id, pg_sys::GetCurrentSubTransactionId()));
+    }
+}
+
+#[pg_guard]
+unsafe extern "C" fn xact_callback(event: pg_sys::XactEvent, _arg: *mut c_void) {
+    match event {
+        // A prepared transaction can be committed by another process, which wouldn't know
+        // which directories to delete.
+        pg_sys::XactEvent_XACT_EVENT_PRE_PREPARE if !PENDING_DROPS.is_empty() => {
+            panic!("cannot prepare a transaction that dropped a bm25 index")
+        }
+        pg_sys::XactEvent_XACT_EVENT_COMMIT | pg_sys::XactEvent_XACT_EVENT_PARALLEL_COMMIT => {
+            // The transaction has already committed, so errors can only be logged.
+            for (index_oid, _) in PENDING_DROPS.drain(..) {
+                ParadeIndex::drop_index(index_oid).unwrap_or_else(|err| {
+                    pgrx::log!("error while dropping index {index_oid:?}: {err:?}")
+                });
+            }
+        }
+        pg_sys::XactEvent_XACT_EVENT_ABORT | pg_sys::XactEvent_XACT_EVENT_PARALLEL_ABORT => {
+            PENDING_DROPS.clear();
+        }
+        _ => {}
+    }
+}
+
+#[pg_guard]
+unsafe extern "C" fn subxact_callback(
+    event: pg_sys::SubXactEvent,
+    my_subid: pg_sys::SubTransactionId,
+    parent_subid: pg_sys::SubTransactionId,
+    _arg: *mut c_void,
+) {
+    match event {
+        // Drops made by a subtransaction that was rolled back to a savepoint never happened.
+        pg_sys::SubXactEvent_SUBXACT_EVENT_ABORT_SUB => {
+            PENDING_DROPS.retain(|(_, subid)| *subid != my_subid);
+        }
+        // Drops made by a subtransaction that committed now belong to its parent, and are
+        // undone if the parent is rolled back.
+        pg_sys::SubXactEvent_SUBXACT_EVENT_COMMIT_SUB => {
+            for (_, subid) in PENDING_DROPS.iter_mut() {
+                if *subid == my_subid {
+                    *subid = parent_subid;
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::PENDING_DROPS;
+    use crate::operator::get_index_oid;
+    use crate::parade_index::index::ParadeIndex;
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+
+    #[pg_test]
+    fn test_drop_index_waits_for_commit() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        let oid = get_index_oid("one_republic_songs_bm25_index", "bm25")
+            .expect("could not find oid for one_republic")
+            .unwrap();
+
+        Spi::run("DROP TABLE one_republic_songs CASCADE").expect("failed to drop table");
+        assert!(ParadeIndex::directory_exists(oid));
+        assert!(unsafe { PENDING_DROPS.iter().any(|(index_oid, _)| *index_oid == oid) });
+    }
+
+    #[pg_test]
+    fn test_drop_index_rolled_back() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        let oid = get_index_oid("one_republic_songs_bm25_index", "bm25")
+            .expect("could not find oid for one_republic")
+            .unwrap();
+
+        // The exception block runs the drop in a subtransaction, which is rolled back.
+        Spi::run(
+            r#"
+            DO $$
+            BEGIN
+                DROP INDEX one_republic_songs_bm25_index;
+                RAISE EXCEPTION 'roll back the drop';
+            EXCEPTION WHEN OTHERS THEN
+                NULL;
+            END $$;
+            "#,
+        )
+        .expect("failed to run drop");
+        assert!(unsafe { !PENDING_DROPS.iter().any(|(index_oid, _)| *index_oid == oid) });
+    }
+}
@@ -0,0 +1,64 @@
+use pgrx::pg_sys::NodeTag;
+use pgrx::*;
+use std::ffi::CStr;
+
+use crate::hooks::alter::{after_alter, before_alter};
+use crate::hooks::rename::{after_rename, before_alter_schema, before_rename};
+
+#[allow(clippy::type_complexity)]
+#[allow(clippy::too_many_arguments)]
+pub fn process_utility(
+    pstmt: PgBox<pg_sys::PlannedStmt>,
+    query_string: &CStr,
+    read_only_tree: Option<bool>,
+    context: pg_sys::ProcessUtilityContext,
+    params: PgBox<pg_sys::ParamListInfoData>,
+    query_env: PgBox<pg_sys::QueryEnvironment>,
+    dest: PgBox<pg_sys::DestReceiver>,
+    completion_tag: *mut pg_sys::QueryCompletion,
+    prev_hook: fn(
+        pstmt: PgBox<pg_sys::PlannedStmt>,
+        query_string: &CStr,
+        read_only_tree: Option<bool>,
+        context: pg_sys::ProcessUtilityContext,
+        params: PgBox<pg_sys::ParamListInfoData>,
+        query_env: PgBox<pg_sys::QueryEnvironment>,
+        dest: PgBox<pg_sys::DestReceiver>,
+        completion_tag: *mut pg_sys::QueryCompletion,
+    ) -> HookResult<()>,
+) {
+    unsafe {
+        let plan = pstmt.utilityStmt;
+
+        // Some statements need to know what a bm25 index looked like before they changed it.
+        let altered_index = match (*plan).type_ {
+            NodeTag::T_AlterTableStmt => before_alter(plan as *mut pg_sys::AlterTableStmt),
+            _ => None,
+        };
+        let renamed_indexes = match (*plan).type_ {
+            NodeTag::T_RenameStmt => before_rename(plan as *mut pg_sys::RenameStmt),
+            NodeTag::T_AlterObjectSchemaStmt => {
+                before_alter_schema(plan as *mut pg_sys::AlterObjectSchemaStmt)
+            }
+            _ => vec![],
+        };
+
+        let _ = prev_hook(
+            pstmt,
+            query_string,
+            read_only_tree,
+            context,
+            params,
+            query_env,
+            dest,
+            completion_tag,
+        );
+
+        if let Some(altered_index) = altered_index {
+            after_alter(altered_index);
+        }
+        if !renamed_indexes.is_empty() {
+            after_rename(renamed_indexes);
+        }
+    }
+}
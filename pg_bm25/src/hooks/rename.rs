@@ -0,0 +1,242 @@
+use pgrx::pg_sys::AsPgCStr;
+use pgrx::*;
+
+use crate::index_access::options::ParadeOptions;
+use crate::parade_index::index::ParadeIndex;
+
+/// The suffix of the indexes that `create_bm25` creates. Their search functions are
+/// generated into a schema named after the rest of the index name.
+const CREATE_BM25_SUFFIX: &str = "_bm25_index";
+
+/// A bm25 index that a statement might rename, or whose table it might rename or move,
+/// along with the name the index had before the statement ran.
+pub struct RenamedIndex {
+    oid: pg_sys::Oid,
+    name: String,
+}
+
+/// Finds the bm25 indexes affected by renaming an index, a table or a schema.
+pub unsafe fn before_rename(rename_stmt: *mut pg_sys::RenameStmt) -> Vec<RenamedIndex> {
+    match (*rename_stmt).renameType {
+        pg_sys::ObjectType_OBJECT_INDEX
+        | pg_sys::ObjectType_OBJECT_TABLE
+        | pg_sys::ObjectType_OBJECT_MATVIEW => {
+            match range_var_oid((*rename_stmt).relation, (*rename_stmt).missing_ok) {
+                Some(relid) => bm25_indexes(relid, pg_sys::InvalidOid),
+                None => vec![],
+            }
+        }
+        pg_sys::ObjectType_OBJECT_SCHEMA => {
+            let namespace_oid = pg_sys::get_namespace_oid((*rename_stmt).subname, true);
+            if namespace_oid == pg_sys::InvalidOid {
+                return vec![];
+            }
+            bm25_indexes(pg_sys::InvalidOid, namespace_oid)
+        }
+        _ => vec![],
+    }
+}
+
+/// Finds the bm25 indexes of a table that's being moved to another schema.
+pub unsafe fn before_alter_schema(
+    alter_stmt: *mut pg_sys::AlterObjectSchemaStmt,
+) -> Vec<RenamedIndex> {
+    match (*alter_stmt).objectType {
+        pg_sys::ObjectType_OBJECT_TABLE | pg_sys::ObjectType_OBJECT_MATVIEW => {
+            match range_var_oid((*alter_stmt).relation, (*alter_stmt).missing_ok) {
+                Some(relid) => bm25_indexes(relid, pg_sys::InvalidOid),
+                None => vec![],
+            }
+        }
+        _ => vec![],
+    }
+}
+
+/// Brings renamed bm25 indexes up to date with their new names. The index directory is
+/// keyed by OID, so it stays where it is, but the name in the index configuration and the
+/// functions generated by `create_bm25`, which refer to the index and its table by name,
+/// are updated.
+pub unsafe fn after_rename(renamed_indexes: Vec<RenamedIndex>) {
+    // Make the new names visible to this command.
+    pg_sys::CommandCounterIncrement();
+
+    for RenamedIndex {
+        oid,
+        name: old_name,
+    } in renamed_indexes
+    {
+        let index_relation = PgRelation::open(oid);
+        let name = index_relation.name().to_string();
+
+        // Indexes that weren't created by create_bm25 don't have any functions to update.
+        let function_schema = old_name
+            .strip_suffix(CREATE_BM25_SUFFIX)
+            .filter(|schema_name| namespace_exists(schema_name));
+
+        let new_function_schema = name.strip_suffix(CREATE_BM25_SUFFIX);
+
+        if name != old_name {
+            match (function_schema, new_function_schema) {
+                (Some(_), None) => panic!(
+                    "bm25 index {old_name} was created by create_bm25, so its new name must end with {CREATE_BM25_SUFFIX}"
+                ),
+                (Some(schema_name), Some(new_schema_name)) => {
+                    Spi::run(&format!(
+                        "ALTER SCHEMA {} RENAME TO {}",
+                        spi::quote_identifier(schema_name),
+                        spi::quote_identifier(new_schema_name)
+                    ))
+                    .unwrap_or_else(|err| {
+                        panic!("error renaming the functions of bm25 index {old_name}: {err:?}")
+                    });
+                }
+                _ => {}
+            }
+
+            ParadeIndex::rename(oid, &name)
+                .unwrap_or_else(|err| panic!("error renaming bm25 index {old_name}: {err}"));
+        }
+
+        let Some(function_schema) = function_schema.and(new_function_schema) else {
+            continue;
+        };
+
+        let heap_relation = index_relation
+            .heap_relation()
+            .unwrap_or_else(|| panic!("bm25 index {name} has no table"));
+        if index_relation.rd_options.is_null() {
+            panic!("bm25 index {name} must have options")
+        }
+        let options = &*(index_relation.rd_options as *const ParadeOptions);
+
+        Spi::run_with_args(
+            "CALL paradedb.create_bm25_functions($1, $2, $3, $4)",
+            Some(vec![
+                (PgBuiltInOids::TEXTOID.oid(), function_schema.into_datum()),
+                (
+                    PgBuiltInOids::TEXTOID.oid(),
+                    heap_relation.name().into_datum(),
+                ),
+                (
+                    PgBuiltInOids::TEXTOID.oid(),
+                    options.get_key_field().into_datum(),
+                ),
+                (
+                    PgBuiltInOids::TEXTOID.oid(),
+                    heap_relation.namespace().into_datum(),
+                ),
+            ]),
+        )
+        .unwrap_or_else(|err| panic!("error updating the functions of bm25 index {name}: {err:?}"));
+    }
+}
+
+unsafe fn range_var_oid(relation: *mut pg_sys::RangeVar, missing_ok: bool) -> Option<pg_sys::Oid> {
+    let flags = if missing_ok {
+        pg_sys::RVROption_RVR_MISSING_OK
+    } else {
+        0
+    };
+    // The statement takes the locks it needs, this only finds out what it's about to rename.
+    let relid = pg_sys::RangeVarGetRelidExtended(
+        relation,
+        pg_sys::NoLock as i32,
+        flags,
+        None,
+        std::ptr::null_mut(),
+    );
+    (relid != pg_sys::InvalidOid).then_some(relid)
+}
+
+fn namespace_exists(schema_name: &str) -> bool {
+    unsafe { pg_sys::get_namespace_oid(schema_name.as_pg_cstr(), true) != pg_sys::InvalidOid }
+}
+
+/// The bm25 indexes that are a relation, that are on a relation, or that are on a table
+/// in a schema.
+fn bm25_indexes(relid: pg_sys::Oid, namespace_oid: pg_sys::Oid) -> Vec<RenamedIndex> {
+    let indexes = Spi::connect(|client| {
+        client
+            .select(
+                "SELECT pg_index.indexrelid, pg_class.relname::text
+                 FROM pg_index
+                 INNER JOIN pg_class ON pg_class.oid = pg_index.indexrelid
+                 INNER JOIN pg_am ON pg_am.oid = pg_class.relam
+                 INNER JOIN pg_class heap ON heap.oid = pg_index.indrelid
+                 WHERE pg_am.amname = 'bm25'
+                 AND (pg_index.indexrelid = $1 OR pg_index.indrelid = $1 OR heap.relnamespace = $2)",
+                None,
+                Some(vec![
+                    (PgBuiltInOids::OIDOID.oid(), relid.into_datum()),
+                    (PgBuiltInOids::OIDOID.oid(), namespace_oid.into_datum()),
+                ]),
+            )?
+            .map(|row| {
+                Ok(RenamedIndex {
+                    oid: row[1].value::<pg_sys::Oid>()?.unwrap_or(pg_sys::InvalidOid),
+                    name: row[2].value::<String>()?.unwrap_or_default(),
+                })
+            })
+            .collect::<Result<Vec<_>, spi::Error>>()
+    })
+    .unwrap_or_else(|err| panic!("error looking up bm25 indexes to rename: {err:?}"));
+
+    // Indexes from before directories were named after OIDs are found by their old name.
+    for index in &indexes {
+        ParadeIndex::migrate_legacy_directory(index.oid);
+    }
+    indexes
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use crate::operator::get_index_oid;
+    use crate::parade_index::index::ParadeIndex;
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+
+    #[pg_test]
+    fn test_rename_index() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run("ALTER INDEX one_republic_songs_bm25_index RENAME TO songs_bm25_index")
+            .expect("failed to rename index");
+
+        let oid = get_index_oid("songs_bm25_index", "bm25")
+            .expect("could not find oid for songs")
+            .unwrap();
+        assert_eq!(ParadeIndex::from_index_oid(oid).name, "songs_bm25_index");
+
+        // The functions move to a schema named after the index.
+        let count = Spi::get_one::<i64>("SELECT count(*) FROM songs.search('album:\"Native\"')")
+            .expect("failed to search index");
+        assert_eq!(count, Some(2));
+        let exists = Spi::get_one::<bool>(
+            "SELECT EXISTS (SELECT FROM pg_namespace WHERE nspname = 'one_republic_songs')",
+        )
+        .expect("failed to look up schema");
+        assert_eq!(exists, Some(false));
+    }
+
+    #[pg_test]
+    fn test_rename_table() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run("ALTER TABLE one_republic_songs RENAME TO songs").expect("failed to rename table");
+
+        let count = Spi::get_one::<i64>(
+            "SELECT count(*) FROM one_republic_songs.search('album:\"Native\"')",
+        )
+        .expect("failed to search index");
+        assert_eq!(count, Some(2));
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_rename_index_keeps_suffix() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run("ALTER INDEX one_republic_songs_bm25_index RENAME TO songs_index").unwrap();
+    }
+}
@@ -1,17 +1,22 @@
 use crate::index_access::options::ParadeOptions;
-use crate::index_access::utils::{create_parade_index, get_parade_index, lookup_index_tupdesc};
+use crate::index_access::utils::{create_parade_index, lookup_index_tupdesc};
+use crate::parade_index::index::ParadeIndex;
 use pgrx::*;
 use std::panic::{self, AssertUnwindSafe};
 
-// For now just pass the count and parade
+// For now just pass the count and the directory of the parade
 // index on the build callback state
 struct BuildState {
     count: usize,
+    index_directory_path: String,
 }
 
 impl BuildState {
-    fn new() -> Self {
-        BuildState { count: 0 }
+    fn new(index_directory_path: &str) -> Self {
+        BuildState {
+            count: 0,
+            index_directory_path: index_directory_path.to_string(),
+        }
     }
 }
 
@@ -24,23 +29,52 @@ pub extern "C" fn ambuild(
 ) -> *mut pg_sys::IndexBuildResult {
     let heap_relation = unsafe { PgRelation::from_pg(heaprel) };
     let index_relation = unsafe { PgRelation::from_pg(indexrel) };
-    let index_name = index_relation.name().to_string();
+    let index_oid = index_relation.oid();
 
-    // rdopts are passed on to create_parade_index
-    let rdopts: PgBox<ParadeOptions> = if !index_relation.rd_options.is_null() {
-        unsafe { PgBox::from_pg(index_relation.rd_options as *mut ParadeOptions) }
-    } else {
-        let ops = unsafe { PgBox::<ParadeOptions>::alloc0() };
-        ops.into_pg_boxed()
-    };
+    // An index that's created in this transaction can't have a directory yet, unless one
+    // was left behind by a relation that used to have the same OID.
+    if index_relation.rd_createSubid != pg_sys::InvalidSubTransactionId
+        && ParadeIndex::exists(index_oid)
+    {
+        ParadeIndex::drop_index(index_oid)
+            .unwrap_or_else(|err| panic!("error removing stale index directory: {err}"));
+    }
 
-    create_parade_index(index_name.clone(), &heap_relation, rdopts).unwrap();
+    // An existing index is being rebuilt by REINDEX, TRUNCATE, VACUUM FULL or CLUSTER.
+    let count = if ParadeIndex::exists(index_oid) {
+        let heap_blocks = unsafe {
+            pg_sys::RelationGetNumberOfBlocksInFork(heaprel, pg_sys::ForkNumber_MAIN_FORKNUM)
+        };
+        if heap_blocks == 0 {
+            // TRUNCATE gives the table a new, empty file before rebuilding its indexes, so
+            // there's nothing to build, and the index is emptied in place instead.
+            ParadeIndex::from_index_oid(index_oid)
+                .delete_all()
+                .unwrap_or_else(|err| panic!("error emptying {}: {err:?}", index_relation.name()));
+            0
+        } else {
+            // The new build replaces the index's data when the transaction commits.
+            rebuild(&heap_relation, &index_relation)
+        }
+    } else {
+        // rdopts are passed on to create_parade_index
+        let rdopts = index_options(&index_relation);
+        let parade_index = create_parade_index(&index_relation, &heap_relation, rdopts).unwrap();
+        let index_directory_path = parade_index.index_directory_path.clone();
+        parade_index.discard_on_abort();
 
-    let state = do_heap_scan(index_info, &heap_relation, &index_relation);
+        do_heap_scan(
+            &index_directory_path,
+            index_info,
+            &heap_relation,
+            &index_relation,
+        )
+        .count
+    };
 
     let mut result = unsafe { PgBox::<pg_sys::IndexBuildResult>::alloc0() };
-    result.heap_tuples = state.count as f64;
-    result.index_tuples = state.count as f64;
+    result.heap_tuples = count as f64;
+    result.index_tuples = count as f64;
 
     result.into_pg()
 }
@@ -48,12 +82,182 @@ pub extern "C" fn ambuild(
 #[pg_guard]
 pub extern "C" fn ambuildempty(_index_relation: pg_sys::Relation) {}
 
+/// Builds the next generation of an index with its current options, while the generation
+/// it replaces keeps serving queries to other transactions. The new generation is swapped in
+/// when the transaction commits. Returns the number of rows that were indexed.
+pub fn rebuild(heap_relation: &PgRelation, index_relation: &PgRelation) -> usize {
+    let index_info = unsafe { pg_sys::BuildIndexInfo(index_relation.as_ptr()) };
+    let (generation_directory_path, count) =
+        build_generation(heap_relation, index_relation, index_info);
+
+    ParadeIndex::promote_on_commit(index_relation.oid(), &generation_directory_path);
+
+    count
+}
+
+/// Rebuilds an index like `rebuild`, while other transactions keep writing to its table.
+/// Their writes go to the current generation, and the rows that are in it but missing from
+/// the new generation are copied over by catch-up passes once the build is done. Only the
+/// last pass keeps other transactions from writing, until this one ends, so that none of
+/// their rows are left out of the new generation when it's swapped in.
+pub fn rebuild_concurrently(heap_relation: &PgRelation, index_relation: &PgRelation) -> usize {
+    // VACUUM could otherwise delete rows from the current generation while it's compared
+    // with the new one, and another transaction could start rebuilding the index too.
+    unsafe {
+        pg_sys::LockRelationOid(
+            heap_relation.oid(),
+            pg_sys::ShareUpdateExclusiveLock as pg_sys::LOCKMODE,
+        );
+    }
+
+    // Like CREATE INDEX CONCURRENTLY, the build scans the table with an MVCC snapshot, so
+    // that it doesn't wait for the transactions that are writing to it.
+    let index_info = unsafe { pg_sys::BuildIndexInfo(index_relation.as_ptr()) };
+    unsafe { (*index_info).ii_Concurrent = true };
+    let (generation_directory_path, mut count) =
+        build_generation(heap_relation, index_relation, index_info);
+
+    // Most of the rows written during the build are caught up on while writes continue,
+    // so that the last pass, which waits for the transactions writing to the table to
+    // finish and keeps new ones from starting, has few rows left to index.
+    count += catch_up(
+        heap_relation,
+        index_relation,
+        index_info,
+        &generation_directory_path,
+    );
+    unsafe {
+        pg_sys::LockRelationOid(heap_relation.oid(), pg_sys::ShareLock as pg_sys::LOCKMODE);
+    }
+    count += catch_up(
+        heap_relation,
+        index_relation,
+        index_info,
+        &generation_directory_path,
+    );
+
+    ParadeIndex::promote_on_commit(index_relation.oid(), &generation_directory_path);
+
+    count
+}
+
+/// Builds a new generation of an index, returning its directory and the number of rows
+/// that were indexed.
+fn build_generation(
+    heap_relation: &PgRelation,
+    index_relation: &PgRelation,
+    index_info: *mut pg_sys::IndexInfo,
+) -> (String, usize) {
+    let index_name = index_relation.name();
+    let rdopts = index_options(index_relation);
+
+    let generation = ParadeIndex::new_generation(index_relation, heap_relation, rdopts)
+        .unwrap_or_else(|err| panic!("error creating new generation of {index_name}: {err}"));
+    let generation_directory_path = generation.index_directory_path.clone();
+    generation.discard_on_abort();
+
+    let state = do_heap_scan(
+        &generation_directory_path,
+        index_info,
+        heap_relation,
+        index_relation,
+    );
+
+    (generation_directory_path, state.count)
+}
+
+/// Indexes the rows that are in the current generation of an index, but missing from a new
+/// generation of it, into the new generation. Returns the number of rows that were indexed.
+fn catch_up(
+    heap_relation: &PgRelation,
+    index_relation: &PgRelation,
+    index_info: *mut pg_sys::IndexInfo,
+    generation_directory_path: &str,
+) -> usize {
+    let index_name = index_relation.name();
+    let current_ctids = ParadeIndex::from_index_oid(index_relation.oid())
+        .ctids()
+        .unwrap_or_else(|err| panic!("error reading rows of {index_name}: {err:?}"));
+    let generation = ParadeIndex::from_index_directory(generation_directory_path);
+    let generation_ctids = generation.ctids().unwrap_or_else(|err| {
+        panic!("error reading rows of new generation of {index_name}: {err:?}")
+    });
+
+    let tupdesc = lookup_index_tupdesc(index_relation);
+    let mut values = [pg_sys::Datum::from(0); pg_sys::INDEX_MAX_KEYS as usize];
+    let mut isnull = [false; pg_sys::INDEX_MAX_KEYS as usize];
+    let mut count = 0;
+
+    unsafe {
+        // The index's expression is evaluated against each row, like the build does.
+        let heap = heap_relation.as_ptr();
+        let slot = pg_sys::table_slot_create(heap, std::ptr::null_mut());
+        let estate = pg_sys::CreateExecutorState();
+        let econtext = pg_sys::MakePerTupleExprContext(estate);
+        (*econtext).ecxt_scantuple = slot;
+        let fetch_row_version = (*(*heap).rd_tableam)
+            .tuple_fetch_row_version
+            .expect("table access method must be able to fetch rows");
+
+        for ctid_value in current_ctids.difference(&generation_ctids) {
+            check_for_interrupts!();
+
+            // The current generation can have rows that aren't visible anymore, like the
+            // build of an index that isn't concurrent does, so they're fetched regardless.
+            let mut ctid = pg_sys::ItemPointerData::default();
+            u64_to_item_pointer(*ctid_value, &mut ctid);
+            if !fetch_row_version(
+                heap,
+                &mut ctid,
+                std::ptr::addr_of_mut!(pg_sys::SnapshotAnyData),
+                slot,
+            ) {
+                continue;
+            }
+
+            pg_sys::MemoryContextReset((*econtext).ecxt_per_tuple_memory);
+            pg_sys::FormIndexDatum(
+                index_info,
+                slot,
+                estate,
+                values.as_mut_ptr(),
+                isnull.as_mut_ptr(),
+            );
+
+            let index_entries = generation
+                .row_to_index_entries(ctid, &tupdesc, values.as_mut_ptr())
+                .unwrap_or_else(|err| {
+                    panic!("error creating index entries for index '{index_name}': {err:?}")
+                });
+            generation.insert(index_entries).unwrap_or_else(|err| {
+                panic!("error inserting row into new generation of {index_name}: {err:?}")
+            });
+            count += 1;
+        }
+
+        pg_sys::ExecDropSingleTupleTableSlot(slot);
+        pg_sys::FreeExecutorState(estate);
+    }
+
+    count
+}
+
+fn index_options(index_relation: &PgRelation) -> PgBox<ParadeOptions> {
+    if !index_relation.rd_options.is_null() {
+        unsafe { PgBox::from_pg(index_relation.rd_options as *mut ParadeOptions) }
+    } else {
+        let ops = unsafe { PgBox::<ParadeOptions>::alloc0() };
+        ops.into_pg_boxed()
+    }
+}
+
 fn do_heap_scan<'a>(
+    index_directory_path: &str,
     index_info: *mut pg_sys::IndexInfo,
     heap_relation: &'a PgRelation,
     index_relation: &'a PgRelation,
 ) -> BuildState {
-    let mut state = BuildState::new();
+    let mut state = BuildState::new(index_directory_path);
     let _ = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
         pg_sys::IndexBuildHeapScan(
             heap_relation.as_ptr(),
@@ -98,15 +302,18 @@ unsafe extern "C" fn build_callback(
 unsafe fn build_callback_internal(
     ctid: pg_sys::ItemPointerData,
     values: *mut pg_sys::Datum,
-    _state: *mut std::os::raw::c_void,
+    state: *mut std::os::raw::c_void,
     index: pg_sys::Relation,
 ) {
     check_for_interrupts!();
 
+    let state = (state as *mut BuildState)
+        .as_mut()
+        .expect("no build state in build callback");
     let index_relation_ref: PgRelation = PgRelation::from_pg(index);
     let tupdesc = lookup_index_tupdesc(&index_relation_ref);
     let index_name = index_relation_ref.name();
-    let parade_index = get_parade_index(index_name);
+    let parade_index = ParadeIndex::from_index_directory(&state.index_directory_path);
     let index_entries = parade_index
         .row_to_index_entries(ctid, &tupdesc, values)
         .unwrap_or_else(|err| {
@@ -116,4 +323,48 @@ unsafe fn build_callback_internal(
     parade_index.insert(index_entries).unwrap_or_else(|err| {
         panic!("error inserting json builder during index build callback: {err:?}")
     });
+    state.count += 1;
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::{build_generation, catch_up};
+    use crate::operator::get_index_oid;
+    use crate::parade_index::index::ParadeIndex;
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+
+    #[pg_test]
+    fn test_catch_up_indexes_rows_written_during_build() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let oid = get_index_oid("one_republic_songs_bm25_index", "bm25")
+            .expect("could not find oid for one_republic")
+            .unwrap();
+        let index_relation = unsafe { PgRelation::open(oid) };
+        let heap_relation = index_relation.heap_relation().unwrap();
+        let index_info = unsafe { pg_sys::BuildIndexInfo(index_relation.as_ptr()) };
+        let (generation_directory_path, count) =
+            build_generation(&heap_relation, &index_relation, index_info);
+        assert_eq!(count, 7);
+
+        // A row written after the build only goes into the current generation.
+        Spi::run(
+            "INSERT INTO one_republic_songs (song_id, title, description) VALUES (100, 'Rescue Me', 'A new song')",
+        )
+        .expect("failed to insert row");
+
+        let caught_up = catch_up(
+            &heap_relation,
+            &index_relation,
+            index_info,
+            &generation_directory_path,
+        );
+        assert_eq!(caught_up, 1);
+
+        let generation = ParadeIndex::from_index_directory(&generation_directory_path);
+        assert_eq!(generation.ctids().unwrap().len(), 8);
+    }
 }
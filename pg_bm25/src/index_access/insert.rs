@@ -1,7 +1,7 @@
 use crate::index_access::utils::lookup_index_tupdesc;
 use pgrx::*;
 
-use super::utils::get_parade_index;
+use crate::parade_index::index::ParadeIndex;
 
 #[allow(clippy::too_many_arguments)]
 #[cfg(any(feature = "pg14", feature = "pg15", feature = "pg16"))]
@@ -42,7 +42,7 @@ unsafe fn aminsert_internal(
     let index_relation_ref: PgRelation = PgRelation::from_pg(index_relation);
     let tupdesc = lookup_index_tupdesc(&index_relation_ref);
     let index_name = index_relation_ref.name();
-    let parade_index = get_parade_index(index_name);
+    let parade_index = ParadeIndex::from_index_oid(index_relation_ref.oid());
     let index_entries = parade_index
         .row_to_index_entries(*ctid, &tupdesc, values)
         .unwrap_or_else(|err| {
@@ -1,6 +1,6 @@
 use pgrx::*;
 
-mod build;
+pub mod build;
 mod cost;
 mod delete;
 mod insert;
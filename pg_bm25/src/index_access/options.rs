@@ -6,8 +6,10 @@ use std::collections::HashMap;
 use std::ffi::CStr;
 
 use crate::parade_index::fields::{
-    ParadeBooleanOptions, ParadeJsonOptions, ParadeNumericOptions, ParadeTextOptions,
+    ParadeBooleanOptions, ParadeJsonOptions, ParadeNumericOptions, ParadeOption, ParadeOptionMap,
+    ParadeTextOptions,
 };
+use crate::parade_index::merge_policy::ParadeMergePolicy;
 
 /* ADDING OPTIONS
  * in init(), call pg_sys::add_{type}_reloption (check postgres docs for what args you need)
@@ -17,6 +19,13 @@ use crate::parade_index::fields::{
  * yourself (see get_tokenizer)
 */
 
+/* ALTERING OPTIONS
+ * ALTER INDEX ... SET (...) calls amoptions() with validate set to true, and with every option the
+ * index will have afterwards, so it's where options are checked against each other
+ * The field options only take a ShareUpdateExclusiveLock, so that the index can keep serving queries
+ * while it's rebuilt with the new options (see hooks/alter.rs)
+*/
+
 /* READING OPTIONS
  * options are placed in relation.rd_options
  * As in ambuild(), cast relation.rd_options into ParadeOptions using PgBox (because ParadeOptions
@@ -36,6 +45,7 @@ pub struct ParadeOptions {
     boolean_fields_offset: i32,
     json_fields_offset: i32,
     key_field_offset: i32,
+    merge_policy_offset: i32,
 }
 
 #[pg_guard]
@@ -46,8 +56,12 @@ extern "C" fn validate_text_fields(value: *const std::os::raw::c_char) {
         return;
     }
 
-    let _options: HashMap<String, ParadeTextOptions> =
+    let options: HashMap<String, ParadeTextOptions> =
         from_str(&json_str).expect("failed to validate text_fields");
+
+    for (field_name, field_options) in &options {
+        field_options.validate(field_name);
+    }
 }
 
 #[pg_guard]
@@ -82,8 +96,25 @@ extern "C" fn validate_json_fields(value: *const std::os::raw::c_char) {
         return;
     }
 
-    let _options: HashMap<String, ParadeJsonOptions> =
-        from_str(&json_str).expect("failed to validate boolean_fields");
+    let options: HashMap<String, ParadeJsonOptions> =
+        from_str(&json_str).expect("failed to validate json_fields");
+
+    for (field_name, field_options) in &options {
+        field_options.tokenizer.validate(field_name);
+    }
+}
+
+#[pg_guard]
+extern "C" fn validate_merge_policy(value: *const std::os::raw::c_char) {
+    let json_str = cstr_to_rust_str(value);
+
+    if json_str.is_empty() {
+        return;
+    }
+
+    let merge_policy: ParadeMergePolicy =
+        from_str(&json_str).expect("failed to validate merge_policy");
+    merge_policy.validate();
 }
 
 #[pg_guard]
@@ -104,7 +135,7 @@ fn cstr_to_rust_str(value: *const std::os::raw::c_char) -> String {
 }
 
 // For now, we support changing the tokenizer between default, raw, and en_stem
-const NUM_REL_OPTS: usize = 5;
+const NUM_REL_OPTS: usize = 6;
 #[pg_guard]
 pub unsafe extern "C" fn amoptions(
     reloptions: pg_sys::Datum,
@@ -136,8 +167,19 @@ pub unsafe extern "C" fn amoptions(
             opttype: pg_sys::relopt_type_RELOPT_TYPE_STRING,
             offset: offset_of!(ParadeOptions, key_field_offset) as i32,
         },
+        pg_sys::relopt_parse_elt {
+            optname: "merge_policy".as_pg_cstr(),
+            opttype: pg_sys::relopt_type_RELOPT_TYPE_STRING,
+            offset: offset_of!(ParadeOptions, merge_policy_offset) as i32,
+        },
     ];
-    build_relopts(reloptions, validate, options)
+    let rdopts = build_relopts(reloptions, validate, options);
+
+    if validate && !rdopts.is_null() {
+        (*(rdopts as *const ParadeOptions)).validate();
+    }
+
+    rdopts
 }
 
 #[cfg(any(feature = "pg13", feature = "pg14", feature = "pg15", feature = "pg16"))]
@@ -243,6 +285,68 @@ impl ParadeOptions {
         key_field
     }
 
+    /// Indexes without a merge_policy option use Tantivy's default merge policy.
+    pub fn get_merge_policy(&self) -> ParadeMergePolicy {
+        let merge_policy = self.get_str(self.merge_policy_offset, "".to_string());
+
+        if merge_policy.is_empty() {
+            return ParadeMergePolicy::default();
+        }
+
+        from_str::<ParadeMergePolicy>(&merge_policy).expect("failed to parse merge_policy")
+    }
+
+    /// Every configured field, keyed by field name.
+    pub fn get_field_configs(&self) -> ParadeOptionMap {
+        let mut field_configs: ParadeOptionMap = HashMap::new();
+
+        for (field_name, options) in self.get_text_fields() {
+            field_configs.insert(field_name, ParadeOption::Text(options));
+        }
+
+        for (field_name, options) in self.get_json_fields() {
+            field_configs.insert(field_name, ParadeOption::Json(options));
+        }
+
+        for (field_name, options) in self.get_numeric_fields() {
+            field_configs.insert(field_name, ParadeOption::Numeric(options));
+        }
+
+        for (field_name, options) in self.get_boolean_fields() {
+            field_configs.insert(field_name, ParadeOption::Boolean(options));
+        }
+
+        field_configs
+    }
+
+    fn validate(&self) {
+        self.get_key_field();
+
+        let text_fields = self.get_text_fields();
+        let numeric_fields = self.get_numeric_fields();
+        let boolean_fields = self.get_boolean_fields();
+        let json_fields = self.get_json_fields();
+
+        if text_fields.is_empty()
+            && numeric_fields.is_empty()
+            && boolean_fields.is_empty()
+            && json_fields.is_empty()
+        {
+            panic!("no text_fields, numeric_fields, boolean_fields, or json_fields were specified")
+        }
+
+        let mut field_names: Vec<&String> = text_fields
+            .keys()
+            .chain(numeric_fields.keys())
+            .chain(boolean_fields.keys())
+            .chain(json_fields.keys())
+            .collect();
+        field_names.sort();
+        if let Some(window) = field_names.windows(2).find(|window| window[0] == window[1]) {
+            panic!("field {} is configured more than once", window[0])
+        }
+    }
+
     fn get_str(&self, offset: i32, default: String) -> String {
         if offset == 0 {
             default
@@ -268,7 +372,7 @@ pub unsafe fn init() {
         Some(validate_text_fields),
         #[cfg(any(feature = "pg13", feature = "pg14", feature = "pg15", feature = "pg16"))]
         {
-            pg_sys::AccessExclusiveLock as pg_sys::LOCKMODE
+            pg_sys::ShareUpdateExclusiveLock as pg_sys::LOCKMODE
         },
     );
     pg_sys::add_string_reloption(
@@ -279,7 +383,7 @@ pub unsafe fn init() {
         Some(validate_numeric_fields),
         #[cfg(any(feature = "pg13", feature = "pg14", feature = "pg15", feature = "pg16"))]
         {
-            pg_sys::AccessExclusiveLock as pg_sys::LOCKMODE
+            pg_sys::ShareUpdateExclusiveLock as pg_sys::LOCKMODE
         },
     );
     pg_sys::add_string_reloption(
@@ -290,7 +394,7 @@ pub unsafe fn init() {
         Some(validate_boolean_fields),
         #[cfg(any(feature = "pg13", feature = "pg14", feature = "pg15", feature = "pg16"))]
         {
-            pg_sys::AccessExclusiveLock as pg_sys::LOCKMODE
+            pg_sys::ShareUpdateExclusiveLock as pg_sys::LOCKMODE
         },
     );
     pg_sys::add_string_reloption(
@@ -301,7 +405,7 @@ pub unsafe fn init() {
         Some(validate_json_fields),
         #[cfg(any(feature = "pg13", feature = "pg14", feature = "pg15", feature = "pg16"))]
         {
-            pg_sys::AccessExclusiveLock as pg_sys::LOCKMODE
+            pg_sys::ShareUpdateExclusiveLock as pg_sys::LOCKMODE
         },
     );
     pg_sys::add_string_reloption(
@@ -315,4 +419,15 @@ pub unsafe fn init() {
             pg_sys::AccessExclusiveLock as pg_sys::LOCKMODE
         },
     );
+    pg_sys::add_string_reloption(
+        RELOPT_KIND_PDB,
+        "merge_policy".as_pg_cstr(),
+        "JSON string specifying how segments of the index should be merged".as_pg_cstr(),
+        std::ptr::null(),
+        Some(validate_merge_policy),
+        #[cfg(any(feature = "pg13", feature = "pg14", feature = "pg15", feature = "pg16"))]
+        {
+            pg_sys::ShareUpdateExclusiveLock as pg_sys::LOCKMODE
+        },
+    );
 }
@@ -1,6 +1,6 @@
 use crate::{
-    index_access::utils::{get_parade_index, SearchConfig},
-    parade_index::state::TantivyScanState,
+    index_access::utils::SearchConfig,
+    parade_index::{index::ParadeIndex, state::TantivyScanState},
 };
 use pgrx::*;
 
@@ -46,17 +46,16 @@ pub extern "C" fn amrescan(
 
     let query_config =
         SearchConfig::from_jsonb(config_jsonb).expect("could not parse search config");
-    let index_name = &query_config.index_name;
 
     // Create the index and scan state
-    let parade_index = get_parade_index(index_name);
+    let index_oid = unsafe { (*scan.indexRelation).rd_id };
+    let parade_index = ParadeIndex::from_index_oid(index_oid);
     let mut state = parade_index.scan_state(&query_config).unwrap();
 
-    let top_docs = state.search();
+    let top_docs = state.scan();
 
     // Store the search results in the scan state, ensuring they get freed when the current memory context is deleted.
-    state.iterator =
-        PgMemoryContexts::CurrentMemoryContext.leak_and_drop_on_delete(top_docs.into_iter());
+    state.iterator = PgMemoryContexts::CurrentMemoryContext.leak_and_drop_on_delete(top_docs);
 
     // Save the scan state onto the current memory context.
     scan.opaque =
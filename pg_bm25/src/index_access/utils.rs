@@ -1,3 +1,4 @@
+use pgrx::pg_sys::AsPgCStr;
 use pgrx::*;
 use serde::Deserialize;
 
@@ -11,6 +12,8 @@ use std::str::FromStr;
 
 use crate::index_access::options::ParadeOptions;
 use crate::parade_index::index::ParadeIndex;
+use crate::parade_index::score::SearchAfter;
+use crate::parade_index::sort::SortBy;
 use crate::writer::{IndexEntry, IndexError, IndexKey, IndexValue};
 
 #[derive(Debug, Deserialize, Default, Clone, PartialEq)]
@@ -30,13 +33,42 @@ pub struct SearchConfig {
     #[serde(default, deserialize_with = "from_csv")]
     pub regex_fields: Vec<String>,
     pub max_num_chars: Option<usize>,
-    pub highlight_field: Option<String>,
+    #[serde(default, deserialize_with = "from_csv")]
+    pub highlight_field: Vec<String>,
+    pub highlight_pre_tag: Option<String>,
+    pub highlight_post_tag: Option<String>,
+    pub highlight_format: Option<HighlightFormat>,
+    #[serde(default, deserialize_with = "from_csv")]
+    pub phrase_fields: Vec<String>,
+    pub slop: Option<u32>,
+    pub phrase_prefix: Option<bool>,
+    #[serde(default, deserialize_with = "from_csv")]
+    pub sort_by: Vec<SortBy>,
+    pub search_after: Option<SearchAfter>,
+    pub order_by_score: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightFormat {
+    /// Fragments are html escaped, and matches are wrapped in <b></b> tags by default.
+    #[default]
+    Html,
+    /// Fragments are returned as they are, and matches are only wrapped in tags if
+    /// tags are given.
+    Plain,
 }
 
 impl SearchConfig {
     pub fn from_jsonb(JsonB(config_json_value): JsonB) -> Result<Self, serde_json::Error> {
         serde_json::from_value(config_json_value)
     }
+
+    /// The index to search, which is in the same schema as the table it indexes.
+    pub fn parade_index(&self) -> &'static mut ParadeIndex {
+        let schema_name = Some(self.schema_name.as_str()).filter(|name| !name.is_empty());
+        ParadeIndex::from_index_oid(get_index_oid(schema_name, &self.index_name))
+    }
 }
 
 impl FromStr for SearchConfig {
@@ -48,16 +80,67 @@ impl FromStr for SearchConfig {
     }
 }
 
-pub fn create_parade_index(
-    index_name: String,
+pub fn create_parade_index<'a>(
+    index_relation: &PgRelation,
     heap_relation: &PgRelation,
     options: PgBox<ParadeOptions>,
-) -> Result<&mut ParadeIndex, Box<dyn Error>> {
-    ParadeIndex::new(index_name, heap_relation, options)
+) -> Result<&'a mut ParadeIndex, Box<dyn Error>> {
+    ParadeIndex::new(index_relation, heap_relation, options)
 }
 
 pub fn get_parade_index(index_name: &str) -> &'static mut ParadeIndex {
-    ParadeIndex::from_index_name(index_name)
+    ParadeIndex::from_index_oid(get_index_oid(None, index_name))
+}
+
+/// Looks up the OID of a bm25 index by name. Without a schema, the index is looked up on
+/// the search path, and then in every schema, as long as only one index has the name.
+pub fn get_index_oid(schema_name: Option<&str>, index_name: &str) -> pg_sys::Oid {
+    let oid = unsafe {
+        match schema_name {
+            Some(schema_name) => {
+                let namespace_oid = pg_sys::get_namespace_oid(schema_name.as_pg_cstr(), false);
+                pg_sys::get_relname_relid(index_name.as_pg_cstr(), namespace_oid)
+            }
+            None => pg_sys::RelnameGetRelid(index_name.as_pg_cstr()),
+        }
+    };
+    if oid != pg_sys::InvalidOid {
+        return oid;
+    }
+    if schema_name.is_some() {
+        panic!("bm25 index {index_name} does not exist");
+    }
+
+    let oids: Vec<pg_sys::Oid> = Spi::connect(|client| {
+        client
+            .select(
+                "SELECT pg_class.oid
+                 FROM pg_class
+                 INNER JOIN pg_am ON pg_am.oid = pg_class.relam
+                 WHERE pg_class.relname = $1 AND pg_am.amname = 'bm25'",
+                None,
+                Some(vec![(
+                    PgBuiltInOids::TEXTOID.oid(),
+                    index_name.into_datum(),
+                )]),
+            )?
+            .map(|row| row["oid"].value::<pg_sys::Oid>())
+            .collect::<Result<Option<Vec<_>>, _>>()
+    })
+    .unwrap_or_else(|err| panic!("error looking up bm25 index {index_name}: {err}"))
+    .unwrap_or_default();
+
+    match oids.as_slice() {
+        [oid] => *oid,
+        [] => panic!("bm25 index {index_name} does not exist"),
+        _ => panic!("more than one bm25 index is named {index_name}, set the search_path to the schema of the one you want"),
+    }
+}
+
+pub fn is_bm25_index(relation: &PgRelation) -> bool {
+    let rd_rel = unsafe { &*relation.rd_rel };
+    rd_rel.relkind as u8 == pg_sys::RELKIND_INDEX
+        && rd_rel.relam == unsafe { pg_sys::get_index_am_oid("bm25".as_pg_cstr(), true) }
 }
 
 pub fn lookup_index_tupdesc(indexrel: &PgRelation) -> PgTupleDesc<'static> {
@@ -22,7 +22,7 @@ pub extern "C" fn amvacuumcleanup(
     let index_rel: pg_sys::Relation = info.index;
     let index_relation = unsafe { PgRelation::from_pg(index_rel) };
     let index_name = index_relation.name();
-    let parade_index = ParadeIndex::from_index_name(index_name);
+    let parade_index = ParadeIndex::from_index_oid(index_relation.oid());
 
     // Garbage collect the index and clear the writer cache to free up locks.
     parade_index
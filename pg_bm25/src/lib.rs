@@ -1,12 +1,17 @@
 mod api;
 mod env;
+mod guc;
+mod hooks;
 mod index_access;
 mod operator;
 mod parade_index;
+mod security;
 mod tokenizers;
 mod writer;
 
-use pgrx::bgworkers::{BackgroundWorker, BackgroundWorkerBuilder, SignalWakeFlags};
+use pgrx::bgworkers::{
+    BackgroundWorker, BackgroundWorkerBuilder, DynamicBackgroundWorker, SignalWakeFlags,
+};
 use pgrx::*;
 use shared::logs::ParadeLogsGlobal;
 use shared::telemetry;
@@ -14,6 +19,9 @@ use std::net::SocketAddr;
 use std::process;
 use std::time::Duration;
 
+use crate::guc::PARADE_GUC;
+use crate::hooks::ParadeHook;
+
 #[derive(Copy, Clone, Default)]
 pub struct WriterStatus {
     pub addr: Option<SocketAddr>,
@@ -39,18 +47,32 @@ static PARADE_LOGS_GLOBAL: ParadeLogsGlobal =
 // This is global shared state for the writer background worker.
 static WRITER_STATUS: PgLwLock<WriterStatus> = PgLwLock::new();
 
+// These are the hooks that we register with Postgres.
+static mut PARADE_HOOK: ParadeHook = ParadeHook;
+
 pgrx::pg_module_magic!();
 
 extension_sql_file!("../sql/_bootstrap.sql");
 
-// Initializes option parsing and telemetry
+// Initializes option parsing, hooks and telemetry
 #[allow(clippy::missing_safety_doc)]
 #[allow(non_snake_case)]
 #[pg_guard]
 pub unsafe extern "C" fn _PG_init() {
     index_access::options::init();
     telemetry::posthog::init(shared::constants::PG_BM25_NAME);
-    PARADE_LOGS_GLOBAL.init();
+    PARADE_GUC.init();
+
+    #[allow(unknown_lints)]
+    #[allow(static_mut_ref)]
+    register_hook(&mut PARADE_HOOK);
+    hooks::drop::init();
+
+    // Cached indexes need to be checked against the disk after an index is altered.
+    pg_sys::CacheRegisterRelcacheCallback(
+        Some(parade_index::index::invalidate_cached_indexes),
+        pg_sys::Datum::from(0),
+    );
 
     // Set up the writer bgworker shared state.
     pg_shmem_init!(WRITER_STATUS);
@@ -121,9 +143,68 @@ pub extern "C" fn pg_bm25_insert_worker(_arg: pg_sys::Datum) {
         return;
     }
 
+    // Sweep up index directories left behind by a crash. The sweep deletes them through the
+    // writer server, so it's run by a separate worker that waits for the server to start.
+    start_gc_worker(pg_sys::InvalidOid);
+
     server.start().expect("writer server crashed");
 }
 
+/// Starts a worker that deletes unused index directories. Without a database, it deletes the
+/// directories of dropped databases and then starts a worker for each remaining database,
+/// because a worker can only read the catalog of the database it's connected to.
+fn start_gc_worker(database_oid: pg_sys::Oid) -> Option<DynamicBackgroundWorker> {
+    BackgroundWorkerBuilder::new(&format!("pg_bm25_gc_worker {}", database_oid.as_u32()))
+        // Must be the name of a function in this file.
+        .set_function("pg_bm25_gc_worker")
+        // Must be the name of this library.
+        .set_library("pg_bm25")
+        .set_argument(database_oid.into_datum())
+        .enable_spi_access()
+        .set_restart_time(None)
+        .set_notify_pid(unsafe { pg_sys::MyProcPid })
+        .load_dynamic()
+        .map_err(|err| log!("could not start pg_bm25 gc worker: {err:?}"))
+        .ok()
+}
+
+#[pg_guard]
+#[no_mangle]
+pub extern "C" fn pg_bm25_gc_worker(arg: pg_sys::Datum) {
+    let database_oid = unsafe { pg_sys::Oid::from_datum(arg, false) }.unwrap_or(pg_sys::InvalidOid);
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGTERM);
+    unsafe {
+        pg_sys::BackgroundWorkerInitializeConnectionByOid(database_oid, pg_sys::InvalidOid, 0);
+    }
+
+    let reclaimed = BackgroundWorker::transaction(|| {
+        if database_oid == pg_sys::InvalidOid {
+            let mut reclaimed = parade_index::gc::collect_dropped_databases();
+            reclaimed.extend(parade_index::gc::collect_user_dictionaries());
+            reclaimed
+        } else {
+            parade_index::gc::collect_current_database()
+        }
+    });
+
+    if !reclaimed.is_empty() {
+        let bytes: u64 = reclaimed.iter().map(|(_, bytes)| bytes).sum();
+        log!(
+            "pg_bm25 gc worker reclaimed {bytes} bytes from {} unused index paths",
+            reclaimed.len()
+        );
+    }
+
+    // Databases are swept one at a time, so the sweep doesn't take up every worker slot.
+    if database_oid == pg_sys::InvalidOid {
+        for database_oid in parade_index::gc::database_oids() {
+            if let Some(worker) = start_gc_worker(database_oid) {
+                let _ = worker.wait_for_shutdown();
+            }
+        }
+    }
+}
+
 #[pg_guard]
 #[no_mangle]
 pub extern "C" fn pg_bm25_shutdown_worker(_arg: pg_sys::Datum) {
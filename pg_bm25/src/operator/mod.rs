@@ -1,4 +1,4 @@
-use crate::index_access::utils::{get_parade_index, SearchConfig};
+use crate::index_access::utils::SearchConfig;
 use pgrx::{prelude::PgHeapTuple, *};
 use rustc_hash::FxHashSet;
 
@@ -13,15 +13,14 @@ fn search_tantivy(
         let search_config: SearchConfig = serde_json::from_value(search_config_json.clone())
             .expect("could not parse search config");
 
-        let parade_index = get_parade_index(&search_config.index_name);
-        let mut scan_state = parade_index.scan_state(&search_config).unwrap();
-        let top_docs = scan_state.search();
-        let mut hs = FxHashSet::default();
-
-        for (_, doc_address) in top_docs {
-            let key_field_value = scan_state.key_field_value(doc_address);
-            hs.insert(key_field_value);
-        }
+        // The matches only go into a set, so they don't need to be ranked first.
+        let unordered_config = SearchConfig {
+            order_by_score: Some(false),
+            ..search_config.clone()
+        };
+        let parade_index = search_config.parade_index();
+        let mut scan_state = parade_index.scan_state(&unordered_config).unwrap();
+        let hs: FxHashSet<i64> = scan_state.scan().map(|(score, _)| score.key).collect();
 
         (search_config, hs)
     };
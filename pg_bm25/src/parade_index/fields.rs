@@ -1,25 +1,55 @@
 use serde::*;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use tantivy::{
     schema::*,
     tokenizer::{
         AsciiFoldingFilter, Language, LowerCaser, NgramTokenizer, RawTokenizer, RemoveLongFilter,
-        SimpleTokenizer, Stemmer, TextAnalyzer, WhitespaceTokenizer,
+        SimpleTokenizer, SplitCompoundWords, Stemmer, StopWordFilter, TextAnalyzer,
+        TextAnalyzerBuilder, WhitespaceTokenizer,
     },
 };
 
 use crate::tokenizers::code::CodeTokenizer;
+use crate::tokenizers::edge_ngram::EdgeNgramTokenizer;
 #[cfg(feature = "icu")]
 use crate::tokenizers::icu::ICUTokenizer;
-use crate::tokenizers::lindera::{LinderaJapaneseTokenizer, LinderaKoreanTokenizer};
+use crate::tokenizers::lindera::{
+    check_user_dictionary_privilege, resolve_user_dictionary, user_dictionary_path,
+    LinderaJapaneseTokenizer, LinderaKoreanTokenizer,
+};
+use crate::tokenizers::phonetic::{PhoneticAlgorithm, PhoneticFilter};
+use crate::tokenizers::synonyms::{load_synonym_group, SynonymFilter, SynonymMap};
 use crate::tokenizers::{cjk::ChineseTokenizer, lindera::LinderaChineseTokenizer};
 
 pub const DEFAULT_REMOVE_TOKEN_LENGTH: usize = 255;
+// Tantivy's own default and en_stem analyzers remove tokens longer than this.
+const BUILTIN_REMOVE_TOKEN_LENGTH: usize = 40;
+
+/// A hash of some options, for the names of the analyzers and files that are stored on disk.
+/// It's taken over the options' JSON rather than with their `Hash` implementation, whose
+/// output can change with the hasher's version or the layout of the option types, so that
+/// existing indexes keep finding them. For the same reason, options that are added later
+/// have to be left out of the JSON when they're unset.
+pub fn options_hash<T: Serialize>(options: &T) -> String {
+    let json = serde_json::to_vec(options)
+        .unwrap_or_else(|err| panic!("could not serialize options: {err}"));
+    content_hash(&json)
+}
+
+/// The first 64 bits of the SHA-256 hash of some content, in hex.
+pub fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
 // Tokenizers
 // Serde will pick a ParadeTokenizer variant based on the value of the
 // "type" key, which needs to match one of the variant names below.
 // The "type" field will not be present on the deserialized value.
-#[derive(Default, Copy, Clone, Deserialize, Debug, Serialize, PartialEq, Eq)]
+#[derive(Default, Clone, Deserialize, Debug, Serialize, PartialEq, Eq)]
 #[serde(tag = "type")]
 pub enum ParadeTokenizer {
     #[serde(rename = "default")]
@@ -29,6 +59,8 @@ pub enum ParadeTokenizer {
     Raw,
     #[serde(rename = "en_stem")]
     EnStem,
+    #[serde(rename = "stem")]
+    Stem { language: ParadeLanguage },
     #[serde(rename = "whitespace")]
     WhiteSpace,
     #[serde(rename = "chinese_compatible")]
@@ -41,12 +73,29 @@ pub enum ParadeTokenizer {
         max_gram: usize,
         prefix_only: bool,
     },
+    #[serde(rename = "edge_ngram")]
+    EdgeNgram { min_gram: usize, max_gram: usize },
     #[serde(rename = "chinese_lindera")]
-    ChineseLindera,
+    ChineseLindera {
+        #[serde(default)]
+        mode: LinderaMode,
+        #[serde(default)]
+        user_dictionary: Option<LinderaUserDictionary>,
+    },
     #[serde(rename = "japanese_lindera")]
-    JapaneseLindera,
+    JapaneseLindera {
+        #[serde(default)]
+        mode: LinderaMode,
+        #[serde(default)]
+        user_dictionary: Option<LinderaUserDictionary>,
+    },
     #[serde(rename = "korean_lindera")]
-    KoreanLindera,
+    KoreanLindera {
+        #[serde(default)]
+        mode: LinderaMode,
+        #[serde(default)]
+        user_dictionary: Option<LinderaUserDictionary>,
+    },
     #[cfg(feature = "icu")]
     #[serde(rename = "icu")]
     ICUTokenizer,
@@ -58,6 +107,7 @@ impl ParadeTokenizer {
             ParadeTokenizer::Default => "default".into(),
             ParadeTokenizer::Raw => "raw".into(),
             ParadeTokenizer::EnStem => "en_stem".into(),
+            ParadeTokenizer::Stem { language } => format!("stem_language:{}", language.name()),
             ParadeTokenizer::WhiteSpace => "whitespace".into(),
             ParadeTokenizer::ChineseCompatible => "chinese_compatible".into(),
             ParadeTokenizer::SourceCode => "source_code".into(),
@@ -66,76 +116,458 @@ impl ParadeTokenizer {
                 max_gram,
                 prefix_only,
             } => format!("ngram_mingram:{min_gram}_maxgram:{max_gram}_prefixonly:{prefix_only}"),
-            ParadeTokenizer::ChineseLindera => "chinese_lindera".into(),
-            ParadeTokenizer::JapaneseLindera => "japanese_lindera".into(),
-            ParadeTokenizer::KoreanLindera => "korean_lindera".into(),
+            ParadeTokenizer::EdgeNgram { min_gram, max_gram } => {
+                format!("edge_ngram_mingram:{min_gram}_maxgram:{max_gram}")
+            }
+            ParadeTokenizer::ChineseLindera {
+                mode,
+                user_dictionary,
+            } => lindera_name("chinese_lindera", mode, user_dictionary),
+            ParadeTokenizer::JapaneseLindera {
+                mode,
+                user_dictionary,
+            } => lindera_name("japanese_lindera", mode, user_dictionary),
+            ParadeTokenizer::KoreanLindera {
+                mode,
+                user_dictionary,
+            } => lindera_name("korean_lindera", mode, user_dictionary),
             #[cfg(feature = "icu")]
             ParadeTokenizer::ICUTokenizer => "icu".into(),
         }
     }
 }
 
-impl From<ParadeTokenizer> for TextAnalyzer {
-    fn from(val: ParadeTokenizer) -> Self {
-        match val {
-            ParadeTokenizer::Default => TextAnalyzer::builder(SimpleTokenizer::default())
-                .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                .filter(LowerCaser)
-                .build(),
-            ParadeTokenizer::WhiteSpace => TextAnalyzer::builder(WhitespaceTokenizer::default())
-                .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                .filter(LowerCaser)
-                .build(),
-            ParadeTokenizer::EnStem => TextAnalyzer::builder(SimpleTokenizer::default())
-                .filter(RemoveLongFilter::limit(40))
-                .filter(LowerCaser)
-                .filter(Stemmer::new(Language::English))
-                .build(),
-            ParadeTokenizer::Raw => TextAnalyzer::builder(RawTokenizer::default())
-                .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                .build(),
-            ParadeTokenizer::ChineseCompatible => TextAnalyzer::builder(ChineseTokenizer)
-                .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                .filter(LowerCaser)
-                .build(),
-            ParadeTokenizer::SourceCode => TextAnalyzer::builder(CodeTokenizer::default())
-                .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                .filter(LowerCaser)
-                .filter(AsciiFoldingFilter)
-                .build(),
+impl ParadeTokenizer {
+    /// Panics if the tokenizer can't be built from its options.
+    pub fn validate(&self, field_name: &str) {
+        match self {
             ParadeTokenizer::Ngram {
                 min_gram,
                 max_gram,
                 prefix_only,
             } => {
-                TextAnalyzer::builder(NgramTokenizer::new(min_gram, max_gram, prefix_only).unwrap())
-                    .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                    .filter(LowerCaser)
-                    .build()
+                if let Err(err) = NgramTokenizer::new(*min_gram, *max_gram, *prefix_only) {
+                    panic!("invalid ngram tokenizer for field {field_name}: {err}")
+                }
             }
-            ParadeTokenizer::ChineseLindera => {
-                TextAnalyzer::builder(LinderaChineseTokenizer::default())
-                    .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                    .filter(LowerCaser)
-                    .build()
+            ParadeTokenizer::EdgeNgram { min_gram, max_gram } => {
+                if let Err(err) = EdgeNgramTokenizer::new(*min_gram, *max_gram) {
+                    panic!("invalid edge_ngram tokenizer for field {field_name}: {err}")
+                }
             }
-            ParadeTokenizer::JapaneseLindera => {
-                TextAnalyzer::builder(LinderaJapaneseTokenizer::default())
-                    .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                    .filter(LowerCaser)
-                    .build()
+            _ => {}
+        }
+
+        if let Some(LinderaUserDictionary::Path(path)) = self.lindera_user_dictionary() {
+            check_user_dictionary_privilege(path);
+            let path = user_dictionary_path(path);
+            if !path.is_file() {
+                panic!(
+                    "user dictionary {} for field {field_name} does not exist",
+                    path.display()
+                )
             }
-            ParadeTokenizer::KoreanLindera => {
-                TextAnalyzer::builder(LinderaKoreanTokenizer::default())
-                    .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                    .filter(LowerCaser)
-                    .build()
+        }
+    }
+
+    /// Lindera user dictionaries stored in a table are written to a file under the data
+    /// directory when the index is created, so that the writer process, which can't read
+    /// tables, can load them.
+    pub fn resolve(&mut self) {
+        if let Some(user_dictionary) = self.lindera_user_dictionary_mut() {
+            if let LinderaUserDictionary::Table(table) = user_dictionary {
+                *user_dictionary = LinderaUserDictionary::Path(resolve_user_dictionary(table));
+            }
+        }
+    }
+
+    fn lindera_user_dictionary(&self) -> Option<&LinderaUserDictionary> {
+        match self {
+            ParadeTokenizer::ChineseLindera {
+                user_dictionary, ..
+            }
+            | ParadeTokenizer::JapaneseLindera {
+                user_dictionary, ..
+            }
+            | ParadeTokenizer::KoreanLindera {
+                user_dictionary, ..
+            } => user_dictionary.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn lindera_user_dictionary_mut(&mut self) -> Option<&mut LinderaUserDictionary> {
+        match self {
+            ParadeTokenizer::ChineseLindera {
+                user_dictionary, ..
+            }
+            | ParadeTokenizer::JapaneseLindera {
+                user_dictionary, ..
+            }
+            | ParadeTokenizer::KoreanLindera {
+                user_dictionary, ..
+            } => user_dictionary.as_mut(),
+            _ => None,
+        }
+    }
+
+    /// The tokenizer on its own, boxed so that filters can be appended to it.
+    pub fn analyzer_builder(&self) -> TextAnalyzerBuilder {
+        match self {
+            ParadeTokenizer::Default | ParadeTokenizer::EnStem | ParadeTokenizer::Stem { .. } => {
+                TextAnalyzer::builder(SimpleTokenizer::default()).dynamic()
+            }
+            ParadeTokenizer::WhiteSpace => {
+                TextAnalyzer::builder(WhitespaceTokenizer::default()).dynamic()
+            }
+            ParadeTokenizer::Raw => TextAnalyzer::builder(RawTokenizer::default()).dynamic(),
+            ParadeTokenizer::ChineseCompatible => TextAnalyzer::builder(ChineseTokenizer).dynamic(),
+            ParadeTokenizer::SourceCode => {
+                TextAnalyzer::builder(CodeTokenizer::default()).dynamic()
+            }
+            ParadeTokenizer::Ngram {
+                min_gram,
+                max_gram,
+                prefix_only,
+            } => TextAnalyzer::builder(
+                NgramTokenizer::new(*min_gram, *max_gram, *prefix_only).unwrap(),
+            )
+            .dynamic(),
+            ParadeTokenizer::EdgeNgram { min_gram, max_gram } => {
+                TextAnalyzer::builder(EdgeNgramTokenizer::new(*min_gram, *max_gram).unwrap())
+                    .dynamic()
+            }
+            ParadeTokenizer::ChineseLindera {
+                mode,
+                user_dictionary,
+            } => TextAnalyzer::builder(LinderaChineseTokenizer::new(
+                *mode,
+                user_dictionary.as_ref(),
+            ))
+            .dynamic(),
+            ParadeTokenizer::JapaneseLindera {
+                mode,
+                user_dictionary,
+            } => TextAnalyzer::builder(LinderaJapaneseTokenizer::new(
+                *mode,
+                user_dictionary.as_ref(),
+            ))
+            .dynamic(),
+            ParadeTokenizer::KoreanLindera {
+                mode,
+                user_dictionary,
+            } => {
+                TextAnalyzer::builder(LinderaKoreanTokenizer::new(*mode, user_dictionary.as_ref()))
+                    .dynamic()
             }
             #[cfg(feature = "icu")]
-            ParadeTokenizer::ICUTokenizer => TextAnalyzer::builder(ICUTokenizer)
-                .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                .filter(LowerCaser)
-                .build(),
+            ParadeTokenizer::ICUTokenizer => TextAnalyzer::builder(ICUTokenizer).dynamic(),
+        }
+    }
+
+    /// The tokenizer queries are split with. Edge n-gram fields index the prefixes of each
+    /// word, so their queries are split into whole words, which match the words they're a
+    /// prefix of. Splitting them into prefixes too would match every word that starts with
+    /// the same first letters.
+    pub fn query_analyzer_builder(&self) -> TextAnalyzerBuilder {
+        match self {
+            ParadeTokenizer::EdgeNgram { .. } => {
+                TextAnalyzer::builder(SimpleTokenizer::default()).dynamic()
+            }
+            _ => self.analyzer_builder(),
+        }
+    }
+
+    /// The analyzer queries are tokenized with, for fields that use the tokenizer with its
+    /// default filters.
+    pub fn query_analyzer(&self) -> TextAnalyzer {
+        self.default_filters()
+            .iter()
+            .fold(self.query_analyzer_builder(), |builder, filter| {
+                filter.apply(builder)
+            })
+            .build()
+    }
+
+    /// The filters applied to the tokenizer's output when a field doesn't declare its own.
+    pub fn default_filters(&self) -> Vec<ParadeTokenFilter> {
+        let remove_long = ParadeTokenFilter::RemoveLong {
+            limit: DEFAULT_REMOVE_TOKEN_LENGTH,
+        };
+
+        match self {
+            // These match the analyzers Tantivy registers for these tokenizers by default,
+            // which existing indexes were built with.
+            ParadeTokenizer::Default => vec![
+                ParadeTokenFilter::RemoveLong {
+                    limit: BUILTIN_REMOVE_TOKEN_LENGTH,
+                },
+                ParadeTokenFilter::Lowercase,
+            ],
+            ParadeTokenizer::EnStem => vec![
+                ParadeTokenFilter::RemoveLong {
+                    limit: BUILTIN_REMOVE_TOKEN_LENGTH,
+                },
+                ParadeTokenFilter::Lowercase,
+                ParadeTokenFilter::Stemmer {
+                    language: ParadeLanguage::English,
+                },
+            ],
+            ParadeTokenizer::Stem { language } => vec![
+                remove_long,
+                ParadeTokenFilter::Lowercase,
+                ParadeTokenFilter::Stemmer {
+                    language: *language,
+                },
+            ],
+            ParadeTokenizer::WhiteSpace => vec![],
+            ParadeTokenizer::Raw => vec![remove_long],
+            ParadeTokenizer::SourceCode => vec![
+                remove_long,
+                ParadeTokenFilter::Lowercase,
+                ParadeTokenFilter::AsciiFolding,
+            ],
+            _ => vec![remove_long, ParadeTokenFilter::Lowercase],
+        }
+    }
+}
+
+impl From<ParadeTokenizer> for TextAnalyzer {
+    fn from(val: ParadeTokenizer) -> Self {
+        val.default_filters()
+            .iter()
+            .fold(val.analyzer_builder(), |builder, filter| {
+                filter.apply(builder)
+            })
+            .build()
+    }
+}
+
+/// Lindera tokenizers with the default options keep the names they had before options
+/// were added, so that existing indexes find their analyzers.
+fn lindera_name(
+    base: &str,
+    mode: &LinderaMode,
+    user_dictionary: &Option<LinderaUserDictionary>,
+) -> String {
+    let mut name = base.to_string();
+    if *mode != LinderaMode::Normal {
+        name.push_str(&format!("_mode:{}", mode.name()));
+    }
+    if let Some(user_dictionary) = user_dictionary {
+        name.push_str(&format!("_userdict:{}", options_hash(user_dictionary)));
+    }
+    name
+}
+
+// Lindera options
+#[derive(Default, Copy, Clone, Deserialize, Debug, Serialize, PartialEq, Eq, Hash)]
+pub enum LinderaMode {
+    /// Segments text using the dictionary as is.
+    #[serde(rename = "normal")]
+    #[default]
+    Normal,
+    /// Additionally splits long compound words, like compound nouns, into their parts.
+    #[serde(rename = "decompose")]
+    Decompose,
+}
+
+impl LinderaMode {
+    pub fn name(&self) -> &str {
+        match self {
+            LinderaMode::Normal => "normal",
+            LinderaMode::Decompose => "decompose",
+        }
+    }
+}
+
+/// A user dictionary in Lindera's simple CSV format, with one `surface,part_of_speech,reading`
+/// entry per line.
+#[derive(Clone, Deserialize, Debug, Serialize, PartialEq, Eq, Hash)]
+pub enum LinderaUserDictionary {
+    /// The path of a CSV file, relative to the Postgres data directory.
+    #[serde(rename = "path")]
+    Path(String),
+    /// A table with `surface`, `part_of_speech` and `reading` text columns.
+    #[serde(rename = "table")]
+    Table(String),
+}
+
+// Languages
+#[derive(Copy, Clone, Deserialize, Debug, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ParadeLanguage {
+    Arabic,
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+impl ParadeLanguage {
+    pub fn name(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or_else(|| panic!("could not serialize language {self:?}"))
+    }
+}
+
+impl From<ParadeLanguage> for Language {
+    fn from(val: ParadeLanguage) -> Self {
+        match val {
+            ParadeLanguage::Arabic => Language::Arabic,
+            ParadeLanguage::Danish => Language::Danish,
+            ParadeLanguage::Dutch => Language::Dutch,
+            ParadeLanguage::English => Language::English,
+            ParadeLanguage::Finnish => Language::Finnish,
+            ParadeLanguage::French => Language::French,
+            ParadeLanguage::German => Language::German,
+            ParadeLanguage::Greek => Language::Greek,
+            ParadeLanguage::Hungarian => Language::Hungarian,
+            ParadeLanguage::Italian => Language::Italian,
+            ParadeLanguage::Norwegian => Language::Norwegian,
+            ParadeLanguage::Portuguese => Language::Portuguese,
+            ParadeLanguage::Romanian => Language::Romanian,
+            ParadeLanguage::Russian => Language::Russian,
+            ParadeLanguage::Spanish => Language::Spanish,
+            ParadeLanguage::Swedish => Language::Swedish,
+            ParadeLanguage::Tamil => Language::Tamil,
+            ParadeLanguage::Turkish => Language::Turkish,
+        }
+    }
+}
+
+// Token filters
+// Like tokenizers, filters are picked based on the value of the "type" key.
+#[derive(Clone, Deserialize, Debug, Serialize, PartialEq, Eq, Hash)]
+#[serde(tag = "type")]
+pub enum ParadeTokenFilter {
+    #[serde(rename = "lowercase")]
+    Lowercase,
+    #[serde(rename = "ascii_folding")]
+    AsciiFolding,
+    #[serde(rename = "remove_long")]
+    RemoveLong { limit: usize },
+    #[serde(rename = "stopwords")]
+    Stopwords {
+        #[serde(default)]
+        language: Option<ParadeLanguage>,
+        #[serde(default)]
+        words: Vec<String>,
+    },
+    #[serde(rename = "stemmer")]
+    Stemmer { language: ParadeLanguage },
+    #[serde(rename = "split_compound_words")]
+    SplitCompoundWords { dictionary: Vec<String> },
+    #[serde(rename = "phonetic")]
+    Phonetic {
+        #[serde(default)]
+        algorithm: PhoneticAlgorithm,
+        #[serde(default)]
+        keep_original: bool,
+    },
+}
+
+impl ParadeTokenFilter {
+    pub fn name(&self) -> String {
+        match self {
+            ParadeTokenFilter::Lowercase => "lowercase".into(),
+            ParadeTokenFilter::AsciiFolding => "ascii_folding".into(),
+            ParadeTokenFilter::RemoveLong { limit } => format!("remove_long_limit:{limit}"),
+            ParadeTokenFilter::Stopwords { language, words } => match (language, words.len()) {
+                (Some(language), 0) => format!("stopwords_language:{}", language.name()),
+                (Some(language), count) => {
+                    format!("stopwords_language:{}_words:{count}", language.name())
+                }
+                (None, count) => format!("stopwords_words:{count}"),
+            },
+            ParadeTokenFilter::Stemmer { language } => {
+                format!("stemmer_language:{}", language.name())
+            }
+            ParadeTokenFilter::SplitCompoundWords { dictionary } => {
+                format!("split_compound_words_dictionary:{}", dictionary.len())
+            }
+            ParadeTokenFilter::Phonetic {
+                algorithm,
+                keep_original,
+            } => format!(
+                "phonetic_algorithm:{}_keeporiginal:{keep_original}",
+                algorithm.name()
+            ),
+        }
+    }
+
+    /// Panics if the filter can't be built from its options.
+    pub fn validate(&self, field_name: &str) {
+        match self {
+            ParadeTokenFilter::Stopwords { language, words } => {
+                if let Some(language) = language {
+                    if StopWordFilter::new((*language).into()).is_none() {
+                        panic!(
+                            "no stopword list is available for {} on field {field_name}, pass a list of words instead",
+                            language.name()
+                        )
+                    }
+                } else if words.is_empty() {
+                    panic!("stopwords filter on field {field_name} needs a language or a list of words")
+                }
+            }
+            ParadeTokenFilter::SplitCompoundWords { dictionary } => {
+                if let Err(err) = SplitCompoundWords::from_dictionary(dictionary) {
+                    panic!("invalid split_compound_words dictionary on field {field_name}: {err}")
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Appends the filter to an analyzer.
+    pub fn apply(&self, builder: TextAnalyzerBuilder) -> TextAnalyzerBuilder {
+        match self {
+            ParadeTokenFilter::Lowercase => builder.filter_dynamic(LowerCaser),
+            ParadeTokenFilter::AsciiFolding => builder.filter_dynamic(AsciiFoldingFilter),
+            ParadeTokenFilter::RemoveLong { limit } => {
+                builder.filter_dynamic(RemoveLongFilter::limit(*limit))
+            }
+            ParadeTokenFilter::Stopwords { language, words } => {
+                let mut builder = builder;
+                if let Some(language) = language {
+                    let stop_word_filter =
+                        StopWordFilter::new((*language).into()).unwrap_or_else(|| {
+                            panic!("no stopword list is available for {}", language.name())
+                        });
+                    builder = builder.filter_dynamic(stop_word_filter);
+                }
+                if !words.is_empty() {
+                    builder = builder.filter_dynamic(StopWordFilter::remove(words.clone()));
+                }
+                builder
+            }
+            ParadeTokenFilter::Stemmer { language } => {
+                builder.filter_dynamic(Stemmer::new((*language).into()))
+            }
+            ParadeTokenFilter::SplitCompoundWords { dictionary } => builder.filter_dynamic(
+                SplitCompoundWords::from_dictionary(dictionary)
+                    .unwrap_or_else(|err| panic!("invalid split_compound_words dictionary: {err}")),
+            ),
+            ParadeTokenFilter::Phonetic {
+                algorithm,
+                keep_original,
+            } => builder.filter_dynamic(PhoneticFilter::new(*algorithm, *keep_original)),
         }
     }
 }
@@ -185,8 +617,55 @@ impl ToString for IndexRecordOption {
     }
 }
 
+// Synonyms
+#[derive(Default, Copy, Clone, Deserialize, Serialize, Debug, PartialEq, Eq, Hash)]
+pub enum SynonymExpansion {
+    /// Synonyms are added to the index along with the original terms. Changing them
+    /// requires the index to be rebuilt.
+    #[serde(rename = "index")]
+    Index,
+    /// Query terms are expanded with their synonyms when searching, so synonyms can be
+    /// changed at any time.
+    #[serde(rename = "query")]
+    #[default]
+    Query,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct ParadeSynonymOptions {
+    #[serde(default)]
+    pub expand: SynonymExpansion,
+    /// Synonym sets declared inline in the field options.
+    #[serde(default)]
+    pub sets: Vec<Vec<String>>,
+    /// The name of a group of synonym sets in the paradedb.synonyms table.
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+impl ParadeSynonymOptions {
+    /// All of the synonym sets, including the ones stored in the paradedb.synonyms table.
+    pub fn load_sets(&self) -> Vec<Vec<String>> {
+        let mut sets = self.sets.clone();
+        if let Some(group) = &self.group {
+            sets.extend(load_synonym_group(group));
+        }
+        sets
+    }
+
+    /// Index time synonyms can only change when the index is rebuilt, so the sets stored in
+    /// the paradedb.synonyms table are copied into the options when the index is created.
+    /// This lets the writer process, which can't read tables, build the same analyzer.
+    pub fn resolve(&mut self) {
+        if self.expand == SynonymExpansion::Index {
+            self.sets = self.load_sets();
+            self.group = None;
+        }
+    }
+}
+
 // Text options
-#[derive(Copy, Clone, Debug, Deserialize, Serialize, utoipa::ToSchema)]
+#[derive(Clone, Debug, Deserialize, Serialize, utoipa::ToSchema)]
 pub struct ParadeTextOptions {
     #[serde(default = "default_as_true")]
     indexed: bool,
@@ -203,6 +682,12 @@ pub struct ParadeTextOptions {
     record: IndexRecordOption,
     #[serde(default)]
     normalizer: ParadeNormalizer,
+    #[schema(value_type = Vec<Object>)]
+    #[serde(default)]
+    pub filters: Option<Vec<ParadeTokenFilter>>,
+    #[schema(value_type = Object)]
+    #[serde(default)]
+    pub synonyms: Option<ParadeSynonymOptions>,
 }
 
 impl Default for ParadeTextOptions {
@@ -215,6 +700,135 @@ impl Default for ParadeTextOptions {
             tokenizer: ParadeTokenizer::Default,
             record: IndexRecordOption::Basic,
             normalizer: ParadeNormalizer::Raw,
+            filters: None,
+            synonyms: None,
+        }
+    }
+}
+
+impl ParadeTextOptions {
+    /// Panics if the options can't be used to index the field.
+    pub fn validate(&self, field_name: &str) {
+        self.tokenizer.validate(field_name);
+
+        if let Some(synonyms) = &self.synonyms {
+            // Index time synonyms are stored at the same position as the term they were
+            // expanded from, and are matched by searching for both terms at that position.
+            if synonyms.expand == SynonymExpansion::Index && !self.record.has_positions() {
+                panic!(
+                    "index time synonyms on field {field_name} require record to be set to \"position\""
+                )
+            }
+        }
+
+        for filter in self.filters.iter().flatten() {
+            filter.validate(field_name);
+        }
+    }
+
+    fn index_synonyms(&self) -> Option<&ParadeSynonymOptions> {
+        self.synonyms
+            .as_ref()
+            .filter(|synonyms| synonyms.expand == SynonymExpansion::Index)
+    }
+
+    /// The filters applied to the field's tokens, in order. Fields that don't declare
+    /// their own filters use the default filters of their tokenizer. Stemming is what the
+    /// stem tokenizers are for, so if their filters don't include a stemmer, the tokenizer's
+    /// stemmer is applied after them.
+    pub fn filter_chain(&self) -> Vec<ParadeTokenFilter> {
+        let Some(filters) = &self.filters else {
+            return self.tokenizer.default_filters();
+        };
+
+        let mut filters = filters.clone();
+        let stems = filters
+            .iter()
+            .any(|filter| matches!(filter, ParadeTokenFilter::Stemmer { .. }));
+        let stemmer = self
+            .tokenizer
+            .default_filters()
+            .into_iter()
+            .find(|filter| matches!(filter, ParadeTokenFilter::Stemmer { .. }));
+        if let (false, Some(stemmer)) = (stems, stemmer) {
+            filters.push(stemmer);
+        }
+        filters
+    }
+
+    /// The names of the filters in the field's analyzer, for display.
+    pub fn filter_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .filter_chain()
+            .iter()
+            .map(|filter| filter.name())
+            .collect();
+        if self.index_synonyms().is_some() {
+            names.push("synonyms".into());
+        }
+        names
+    }
+
+    /// The name the field's analyzer is registered under. Fields with their own filters or
+    /// index time synonyms need an analyzer of their own, so their name includes a hash of
+    /// the options that make it different.
+    pub fn tokenizer_name(&self) -> String {
+        let mut name = self.tokenizer.name();
+
+        if let Some(filters) = &self.filters {
+            name.push_str(&format!("_filters:{}", options_hash(filters)));
+        }
+
+        if let Some(synonyms) = self.index_synonyms() {
+            name.push_str(&format!("_synonyms:{}", options_hash(synonyms)));
+        }
+
+        name
+    }
+
+    /// The analyzer the field is indexed with.
+    pub fn analyzer(&self) -> TextAnalyzer {
+        self.build_analyzer(&self.filter_chain(), false)
+    }
+
+    /// The analyzer queries on the field are tokenized with. It's the same as the index
+    /// analyzer, except that phonetic filters only produce the primary code of each word,
+    /// so that a query term matches every word that sounds like it instead of also
+    /// requiring its own spelling, and that edge n-gram fields aren't split into prefixes.
+    pub fn query_analyzer(&self) -> TextAnalyzer {
+        self.build_analyzer(&self.filter_chain(), true)
+    }
+
+    fn build_analyzer(&self, filters: &[ParadeTokenFilter], for_query: bool) -> TextAnalyzer {
+        let tokenizer_builder = if for_query {
+            self.tokenizer.query_analyzer_builder()
+        } else {
+            self.tokenizer.analyzer_builder()
+        };
+        let builder = filters
+            .iter()
+            .fold(tokenizer_builder, |builder, filter| match filter {
+                ParadeTokenFilter::Phonetic { algorithm, .. } if for_query => {
+                    builder.filter_dynamic(PhoneticFilter::for_query(*algorithm))
+                }
+                filter => filter.apply(builder),
+            });
+
+        match self.index_synonyms() {
+            Some(synonyms) => {
+                // Synonyms are normalized by the rest of the analyzer, so that they look
+                // like the tokens they'll be compared against.
+                let mut without_synonyms = ParadeTextOptions {
+                    synonyms: None,
+                    ..self.clone()
+                }
+                .analyzer();
+                let synonym_map = SynonymMap::new(&synonyms.sets, &mut without_synonyms);
+                builder
+                    .filter_dynamic(SynonymFilter::new(synonym_map))
+                    .build()
+            }
+            None => builder.build(),
         }
     }
 }
@@ -233,7 +847,7 @@ impl From<ParadeTextOptions> for TextOptions {
             let text_field_indexing = TextFieldIndexing::default()
                 .set_index_option(parade_options.record)
                 .set_fieldnorms(parade_options.fieldnorms)
-                .set_tokenizer(&parade_options.tokenizer.name());
+                .set_tokenizer(&parade_options.tokenizer_name());
 
             text_options = text_options.set_indexing_options(text_field_indexing);
         }
@@ -321,7 +935,7 @@ impl From<ParadeBooleanOptions> for NumericOptions {
 }
 
 // Json options
-#[derive(Copy, Clone, Debug, Deserialize, Serialize, utoipa::ToSchema)]
+#[derive(Clone, Debug, Deserialize, Serialize, utoipa::ToSchema)]
 pub struct ParadeJsonOptions {
     #[serde(default = "default_as_true")]
     indexed: bool,
@@ -405,9 +1019,12 @@ mod tests {
 
     use tantivy::schema::{JsonObjectOptions, NumericOptions, TextOptions};
 
+    use tantivy::tokenizer::{TextAnalyzer, TokenStream};
+
     use super::{
-        default_as_true, ParadeBooleanOptions, ParadeJsonOptions, ParadeNormalizer,
-        ParadeNumericOptions, ParadeTextOptions, ParadeTokenizer,
+        default_as_true, LinderaMode, LinderaUserDictionary, ParadeBooleanOptions,
+        ParadeJsonOptions, ParadeLanguage, ParadeNormalizer, ParadeNumericOptions,
+        ParadeTextOptions, ParadeTokenFilter, ParadeTokenizer,
     };
 
     #[pgrx::pg_test]
@@ -433,6 +1050,61 @@ mod tests {
                 prefix_only: true
             }
         );
+
+        let json = r#"{"type": "edge_ngram", "min_gram": 2, "max_gram": 10}"#;
+        let tokenizer: ParadeTokenizer = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            tokenizer,
+            ParadeTokenizer::EdgeNgram {
+                min_gram: 2,
+                max_gram: 10
+            }
+        );
+        assert_eq!(tokenizer.name(), "edge_ngram_mingram:2_maxgram:10");
+
+        let json = r#"{"type": "stem", "language": "german"}"#;
+        let tokenizer: ParadeTokenizer = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            tokenizer,
+            ParadeTokenizer::Stem {
+                language: ParadeLanguage::German
+            }
+        );
+        assert_eq!(tokenizer.name(), "stem_language:german");
+
+        // Lindera tokenizers without options keep their original names.
+        let json = r#"{"type": "korean_lindera"}"#;
+        let tokenizer: ParadeTokenizer = serde_json::from_str(json).unwrap();
+        assert_eq!(tokenizer.name(), "korean_lindera");
+
+        let json = r#"{"type": "korean_lindera", "mode": "decompose", "user_dictionary": {"path": "dict.csv"}}"#;
+        let tokenizer: ParadeTokenizer = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            tokenizer,
+            ParadeTokenizer::KoreanLindera {
+                mode: LinderaMode::Decompose,
+                user_dictionary: Some(LinderaUserDictionary::Path("dict.csv".into()))
+            }
+        );
+        assert_eq!(
+            tokenizer.name(),
+            "korean_lindera_mode:decompose_userdict:a7fb3d10b8058430"
+        );
+    }
+
+    #[pgrx::pg_test]
+    fn test_stem_tokenizer() {
+        let mut analyzer = TextAnalyzer::from(ParadeTokenizer::Stem {
+            language: ParadeLanguage::French,
+        });
+        let mut tokens = vec![];
+        for text in ["Chanter", "chantaient"] {
+            let mut token_stream = analyzer.token_stream(text);
+            while token_stream.advance() {
+                tokens.push(token_stream.token().text.clone());
+            }
+        }
+        assert_eq!(tokens, vec!["chant", "chant"]);
     }
 
     #[pgrx::pg_test]
@@ -466,6 +1138,101 @@ mod tests {
         assert_ne!(expected.is_fast(), text_options.is_fast());
     }
 
+    #[pgrx::pg_test]
+    fn test_parade_token_filters() {
+        let json = r#"{
+            "tokenizer": {"type": "default"},
+            "filters": [
+                {"type": "lowercase"},
+                {"type": "stopwords", "language": "english", "words": ["stars"]},
+                {"type": "stemmer", "language": "english"}
+            ]
+        }"#;
+        let parade_text_option: ParadeTextOptions = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            parade_text_option.filters.as_deref(),
+            Some(
+                &[
+                    ParadeTokenFilter::Lowercase,
+                    ParadeTokenFilter::Stopwords {
+                        language: Some(ParadeLanguage::English),
+                        words: vec!["stars".into()],
+                    },
+                    ParadeTokenFilter::Stemmer {
+                        language: ParadeLanguage::English
+                    },
+                ][..]
+            )
+        );
+        assert_eq!(
+            parade_text_option.filter_names(),
+            vec![
+                "lowercase",
+                "stopwords_language:english_words:1",
+                "stemmer_language:english"
+            ]
+        );
+        // The name is stored in the schema of the index, so it must never change.
+        assert_eq!(
+            parade_text_option.tokenizer_name(),
+            "default_filters:fd0bbab3afc7076d"
+        );
+
+        let mut analyzer = parade_text_option.analyzer();
+        let mut token_stream = analyzer.token_stream("Counting the Stars");
+        let mut tokens = vec![];
+        while token_stream.advance() {
+            tokens.push(token_stream.token().text.clone());
+        }
+        assert_eq!(tokens, vec!["count"]);
+
+        // Stem tokenizers keep stemming when their filters leave out the stemmer.
+        let json = r#"{
+            "tokenizer": {"type": "stem", "language": "english"},
+            "filters": [{"type": "lowercase"}]
+        }"#;
+        let parade_text_option: ParadeTextOptions = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            parade_text_option.filter_names(),
+            vec!["lowercase", "stemmer_language:english"]
+        );
+        let mut analyzer = parade_text_option.analyzer();
+        let mut token_stream = analyzer.token_stream("Running Shoes");
+        let mut tokens = vec![];
+        while token_stream.advance() {
+            tokens.push(token_stream.token().text.clone());
+        }
+        assert_eq!(tokens, vec!["run", "shoe"]);
+
+        // Fields without filters keep their tokenizer's name and default filters.
+        let parade_text_option = ParadeTextOptions::default();
+        assert_eq!(parade_text_option.tokenizer_name(), "default");
+        assert_eq!(
+            parade_text_option.filter_names(),
+            vec!["remove_long_limit:40", "lowercase"]
+        );
+    }
+
+    #[pgrx::pg_test]
+    #[should_panic]
+    fn test_invalid_stopwords_filter() {
+        ParadeTokenFilter::Stopwords {
+            language: None,
+            words: vec![],
+        }
+        .validate("description");
+    }
+
+    #[pgrx::pg_test]
+    #[should_panic(expected = "invalid edge_ngram tokenizer for field title")]
+    fn test_invalid_edge_ngram_tokenizer() {
+        ParadeTokenizer::EdgeNgram {
+            min_gram: 5,
+            max_gram: 3,
+        }
+        .validate("title");
+    }
+
     #[pgrx::pg_test]
     fn test_parade_numeric_options() {
         let json = r#"{
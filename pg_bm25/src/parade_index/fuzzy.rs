@@ -0,0 +1,85 @@
+use levenshtein_automata::{Distance, LevenshteinAutomatonBuilder, DFA};
+use once_cell::sync::Lazy;
+use tantivy_fst::Automaton;
+
+// These defaults are shared by fuzzy search and spelling suggestions, so that a
+// suggested term is always one that a fuzzy search for the original term would match.
+pub const DEFAULT_FUZZY_DISTANCE: u8 = 2;
+pub const DEFAULT_TRANSPOSE_COST_ONE: bool = true;
+
+/// The largest edit distance we build automata for. Tantivy's fuzzy queries have the
+/// same limit, because the automata grow very quickly with the distance.
+pub const MAX_FUZZY_DISTANCE: u8 = 2;
+
+/// Building a LevenshteinAutomatonBuilder is expensive, so we keep one around for every
+/// supported combination of distance and transposition cost, like Tantivy does.
+static LEVENSHTEIN_BUILDERS: Lazy<Vec<[LevenshteinAutomatonBuilder; 2]>> = Lazy::new(|| {
+    (0..=MAX_FUZZY_DISTANCE)
+        .map(|distance| {
+            [
+                LevenshteinAutomatonBuilder::new(distance, false),
+                LevenshteinAutomatonBuilder::new(distance, true),
+            ]
+        })
+        .collect()
+});
+
+/// A Levenshtein DFA that can be used to search a Tantivy term dictionary for all the
+/// terms within an edit distance of a query term.
+pub struct LevenshteinDfa(DFA);
+
+impl LevenshteinDfa {
+    pub fn new(term: &str, max_distance: u8, transpose_cost_one: bool) -> Self {
+        if max_distance > MAX_FUZZY_DISTANCE {
+            panic!("fuzzy distance must be at most {MAX_FUZZY_DISTANCE}, received {max_distance}");
+        }
+        let builder = &LEVENSHTEIN_BUILDERS[max_distance as usize][transpose_cost_one as usize];
+        Self(builder.build_dfa(term))
+    }
+
+    /// The edit distance between a term and the query term, if it's within range.
+    pub fn distance(&self, term: &[u8]) -> Option<u8> {
+        match self.0.eval(term) {
+            Distance::Exact(distance) => Some(distance),
+            Distance::AtLeast(_) => None,
+        }
+    }
+}
+
+impl Automaton for LevenshteinDfa {
+    type State = u32;
+
+    fn start(&self) -> Self::State {
+        self.0.initial_state()
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        matches!(self.0.distance(*state), Distance::Exact(_))
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        *state != levenshtein_automata::SINK_STATE
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        self.0.transition(*state, byte)
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::LevenshteinDfa;
+
+    #[pgrx::pg_test]
+    fn test_levenshtein_dfa_distance() {
+        let dfa = LevenshteinDfa::new("keyboard", 2, true);
+        assert_eq!(dfa.distance(b"keyboard"), Some(0));
+        assert_eq!(dfa.distance(b"keybaord"), Some(1));
+        assert_eq!(dfa.distance(b"keybrd"), Some(2));
+        assert_eq!(dfa.distance(b"board"), None);
+
+        let dfa = LevenshteinDfa::new("keyboard", 1, false);
+        assert_eq!(dfa.distance(b"keybaord"), None);
+    }
+}
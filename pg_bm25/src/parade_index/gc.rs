@@ -0,0 +1,233 @@
+use pgrx::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use super::index::ParadeIndex;
+use crate::index_access::utils::is_bm25_index;
+use crate::tokenizers::lindera::USER_DICTIONARY_DIRECTORY;
+
+/// User dictionary files are written before the configuration of the index that uses them,
+/// so files younger than this are kept, in case their index is still being created.
+const USER_DICTIONARY_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// A file or directory that was deleted, and how many bytes it took up.
+pub type ReclaimedPath = (String, u64);
+
+/// Indexes are stored in a directory per database, named after the database's OID.
+fn database_directory(database_oid: pg_sys::Oid) -> PathBuf {
+    crate::env::paradedb_data_dir_path().join(database_oid.as_u32().to_string())
+}
+
+/// The OIDs of the databases that have a directory for their bm25 indexes.
+pub fn database_oids() -> Vec<pg_sys::Oid> {
+    let Ok(entries) = fs::read_dir(crate::env::paradedb_data_dir_path()) else {
+        return vec![];
+    };
+
+    entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .map(pg_sys::Oid::from)
+        .collect()
+}
+
+/// Deletes the directories of databases that have been dropped. Only the shared catalog
+/// of databases is read, so this can run in a process that isn't connected to a database.
+pub fn collect_dropped_databases() -> Vec<ReclaimedPath> {
+    let mut reclaimed = vec![];
+
+    for database_oid in database_oids() {
+        let database_name = unsafe { pg_sys::get_database_name(database_oid) };
+        if !database_name.is_null() {
+            continue;
+        }
+
+        let database_directory = database_directory(database_oid).display().to_string();
+        reclaimed.extend(delete(
+            &database_directory,
+            vec![database_directory.clone()],
+        ));
+    }
+
+    reclaimed
+}
+
+/// Deletes the paths in the current database's directory that no bm25 index owns. These are
+/// left behind when a process crashes, or when an index build fails partway through:
+/// directories of indexes that aren't in the catalog, and generations of an index that it
+/// doesn't point at.
+pub fn collect_current_database() -> Vec<ReclaimedPath> {
+    let database_oid = unsafe { pg_sys::MyDatabaseId };
+    let Ok(entries) = fs::read_dir(database_directory(database_oid)) else {
+        return vec![];
+    };
+
+    // Every path of an index starts with its OID, like "16384", "16384.1" or
+    // "16384_parade_field_configs.json".
+    let mut paths_by_index: HashMap<u32, Vec<String>> = HashMap::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let digits: String = file_name.chars().take_while(char::is_ascii_digit).collect();
+        if let Ok(index_oid) = digits.parse::<u32>() {
+            paths_by_index
+                .entry(index_oid)
+                .or_default()
+                .push(entry.path().display().to_string());
+        }
+    }
+
+    let mut reclaimed = vec![];
+    for (index_oid, paths) in paths_by_index {
+        let index_oid = pg_sys::Oid::from(index_oid);
+
+        // An index that's being built, rebuilt, renamed or dropped is locked by the
+        // transaction doing it, and its directories might only be visible to that
+        // transaction. The lock is held until this transaction ends, so the index can't
+        // start changing while its paths are deleted.
+        let is_locked = unsafe {
+            !pg_sys::ConditionalLockRelationOid(
+                index_oid,
+                pg_sys::ShareUpdateExclusiveLock as pg_sys::LOCKMODE,
+            )
+        };
+        if is_locked {
+            continue;
+        }
+
+        let index_directory_path = ParadeIndex::get_index_directory(index_oid);
+        let paths_to_delete: Vec<String> = if is_bm25_index_oid(index_oid) {
+            let mut paths_in_use = vec![
+                index_directory_path.clone(),
+                ParadeIndex::get_field_configs_path(&index_directory_path),
+            ];
+            match ParadeIndex::current_data_directory(&index_directory_path) {
+                Ok(data_directory) => paths_in_use.push(data_directory),
+                // Without a configuration, there's no telling which generation is current.
+                Err(_) => continue,
+            }
+
+            paths
+                .into_iter()
+                .filter(|path| !paths_in_use.contains(path))
+                .collect()
+        } else {
+            paths
+        };
+
+        if !paths_to_delete.is_empty() {
+            reclaimed.extend(delete(&index_directory_path, paths_to_delete));
+        }
+    }
+
+    reclaimed
+}
+
+/// Deletes the user dictionary files, written from tables for Lindera tokenizers, that no
+/// index configuration in any database refers to anymore. Only files are read, so this can
+/// run in a process that isn't connected to a database.
+pub fn collect_user_dictionaries() -> Vec<ReclaimedPath> {
+    let data_dir = crate::env::postgres_data_dir_path();
+    let Ok(entries) = fs::read_dir(data_dir.join(USER_DICTIONARY_DIRECTORY)) else {
+        return vec![];
+    };
+
+    // Configurations store the path of a dictionary relative to the data directory. Pending
+    // generations have configurations of their own, which match the same pattern.
+    let mut configs = vec![];
+    for database_oid in database_oids() {
+        let Ok(config_entries) = fs::read_dir(database_directory(database_oid)) else {
+            continue;
+        };
+        for entry in config_entries.flatten() {
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .ends_with("_parade_field_configs.json")
+            {
+                if let Ok(config) = fs::read_to_string(entry.path()) {
+                    configs.push(config);
+                }
+            }
+        }
+    }
+
+    let mut reclaimed = vec![];
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !file_name.ends_with(".csv") {
+            continue;
+        }
+
+        let relative_path = format!("{USER_DICTIONARY_DIRECTORY}/{file_name}");
+        if configs.iter().any(|config| config.contains(&relative_path)) {
+            continue;
+        }
+
+        let is_recent = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .map_or(true, |modified| {
+                SystemTime::now()
+                    .duration_since(modified)
+                    .map_or(true, |age| age < USER_DICTIONARY_GRACE_PERIOD)
+            });
+        if is_recent {
+            continue;
+        }
+
+        let path = entry.path();
+        let bytes = disk_usage(&path);
+        match fs::remove_file(&path) {
+            Ok(()) => reclaimed.push((path.display().to_string(), bytes)),
+            Err(err) => warning!("could not delete user dictionary {}: {err}", path.display()),
+        }
+    }
+
+    reclaimed
+}
+
+fn is_bm25_index_oid(index_oid: pg_sys::Oid) -> bool {
+    let relation = unsafe { pg_sys::try_relation_open(index_oid, pg_sys::NoLock as i32) };
+    if relation.is_null() {
+        return false;
+    }
+
+    let relation = unsafe { PgRelation::from_pg_owned(relation) };
+    is_bm25_index(&relation)
+}
+
+fn delete(index_directory_path: &str, paths_to_delete: Vec<String>) -> Vec<ReclaimedPath> {
+    let reclaimed: Vec<ReclaimedPath> = paths_to_delete
+        .iter()
+        .map(|path| (path.clone(), disk_usage(Path::new(path))))
+        .collect();
+
+    match ParadeIndex::delete_paths(index_directory_path, paths_to_delete) {
+        Ok(()) => reclaimed,
+        Err(err) => {
+            warning!("could not delete unused paths of {index_directory_path}: {err}");
+            vec![]
+        }
+    }
+}
+
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
@@ -4,29 +4,43 @@ use pgrx::*;
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::json;
 use shared::{plog, telemetry};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::Path;
 use std::sync::{Arc, Mutex, PoisonError};
+use tantivy::tokenizer::{TextAnalyzer, TokenizerManager};
 use tantivy::{query::QueryParser, schema::*, Document, Index, IndexSettings, Searcher};
 use tantivy::{IndexReader, IndexSortByField, IndexWriter, Order, TantivyError};
 use thiserror::Error;
 
+use super::fuzzy::LevenshteinDfa;
+use super::merge_policy::ParadeMergePolicy;
 use super::state::TantivyScanState;
-use crate::env::{self, Transaction};
+use super::stats::IndexStats;
+use crate::env::Transaction;
+use crate::guc::PARADE_GUC;
 use crate::index_access::options::ParadeOptions;
 use crate::index_access::utils::{row_to_index_entries, SearchConfig};
-use crate::parade_index::fields::{ParadeOption, ParadeOptionMap};
-use crate::tokenizers::{create_normalizer_manager, create_tokenizer_manager};
-use crate::writer::WriterRequest;
+use crate::parade_index::fields::{
+    ParadeJsonOptions, ParadeOption, ParadeOptionMap, ParadeTextOptions, SynonymExpansion,
+};
+use crate::tokenizers::synonyms::SynonymMap;
+use crate::tokenizers::{
+    create_normalizer_manager, create_query_tokenizer_manager, create_tokenizer_manager,
+};
 use crate::writer::{self, IndexEntry, IndexValue};
+use crate::writer::{TransactionWrites, WriterRequest};
 
 type WriterClient = writer::Client<writer::WriterRequest>;
 
-const INDEX_TANTIVY_MEMORY_BUDGET: usize = 500_000_000;
-const CACHE_NUM_BLOCKS: usize = 10;
+const BYTES_IN_MB: usize = 1024 * 1024;
+// Tantivy rejects writers that give an indexing thread less or more memory than this.
+const WRITER_THREAD_MEMORY_BUDGET_MIN: usize = 15_000_000;
+const WRITER_THREAD_MEMORY_BUDGET_MAX: usize = 4_000_000_000;
+// Tantivy's own limit on the number of threads it picks for a writer.
+const WRITER_THREADS_AUTO_MAX: usize = 8;
 const TRANSACTION_CACHE_ID: &str = "parade_index";
 
 /// PostgreSQL operates in a process-per-client model, meaning every client connection
@@ -44,8 +58,27 @@ const TRANSACTION_CACHE_ID: &str = "parade_index";
 /// It's also crucial to remember that this cache is NOT shared across different backend
 /// processes. Each PostgreSQL backend process will have its own separate instance of
 /// this cache, tied to its own lifecycle.
+///
+/// Indexes are cached by the path of their index directory.
 static mut PARADE_INDEX_MEMORY: Lazy<HashMap<String, ParadeIndex>> = Lazy::new(HashMap::new);
 
+/// The directories of the cached indexes that are known to point at the current generation
+/// of their index on disk. An index is rebuilt into a new generation by ALTER INDEX, so the
+/// set is cleared whenever Postgres invalidates its relation cache, and each cached index
+/// is checked against the disk the next time it's used.
+static mut PARADE_INDEX_CURRENT: Lazy<HashSet<String>> = Lazy::new(HashSet::new);
+
+/// The directories of the generations that this backend's transaction is rebuilding indexes
+/// into, by the OIDs of the indexes. Until the transaction ends, the index is read from and
+/// written to its new generation, so that writes made after the rebuild end up in it.
+static mut PARADE_INDEX_PENDING: Lazy<HashMap<pg_sys::Oid, String>> = Lazy::new(HashMap::new);
+
+/// The writes of this backend's transaction, so that an abort can roll back just them while
+/// other transactions are waiting for a coalesced commit. They're only kept track of when
+/// commits are coalesced.
+static mut PARADE_INDEX_TRANSACTION_WRITES: Lazy<TransactionWrites> =
+    Lazy::new(TransactionWrites::default);
+
 /// A global singleton for the instance of the client to the background writer process.
 /// The client is agnotistic to which index we're writing to, so keeping a global one
 /// ensures that the instance can be re-used if a single transaction needs to write to
@@ -53,6 +86,13 @@ static mut PARADE_INDEX_MEMORY: Lazy<HashMap<String, ParadeIndex>> = Lazy::new(H
 static mut PARADE_INDEX_WRITER_CLIENT: Lazy<Arc<Mutex<WriterClient>>> =
     Lazy::new(|| Arc::new(Mutex::new(writer::Client::from_writer_addr())));
 
+/// A relation cache callback, registered with Postgres in `_PG_init`. It must not free any
+/// cached index, because invalidations can be processed while one is in use.
+#[pg_guard]
+pub unsafe extern "C" fn invalidate_cached_indexes(_arg: pg_sys::Datum, _relid: pg_sys::Oid) {
+    PARADE_INDEX_CURRENT.clear();
+}
+
 #[derive(Serialize)]
 pub struct ParadeIndex {
     pub name: String,
@@ -60,6 +100,12 @@ pub struct ParadeIndex {
     pub field_configs: ParadeOptionMap,
     pub key_field_name: String,
     pub data_directory: String,
+    pub generation: u64,
+    pub merge_policy: ParadeMergePolicy,
+    /// The path that the index is looked up and written to by. It's the same as the
+    /// data directory, until the index is rebuilt into a new generation.
+    #[serde(skip_serializing)]
+    pub index_directory_path: String,
     #[serde(skip_serializing)]
     pub reader: IndexReader,
     #[serde(skip_serializing)]
@@ -68,15 +114,79 @@ pub struct ParadeIndex {
     pub ctid_field: Field,
     #[serde(skip_serializing)]
     underlying_index: Index,
+    /// Building the query analyzers can mean loading dictionaries and synonym sets, so
+    /// they're built once for each cached index rather than for every query.
+    #[serde(skip_serializing)]
+    query_tokenizer_manager: TokenizerManager,
 }
 
 impl ParadeIndex {
-    pub fn new(
+    pub fn new<'a>(
+        index_relation: &PgRelation,
+        heap_relation: &PgRelation,
+        options: PgBox<ParadeOptions>,
+    ) -> Result<&'a mut Self, Box<dyn Error>> {
+        let index_directory_path = Self::get_index_directory(index_relation.oid());
+        Self::create(
+            index_relation.name().to_string(),
+            index_directory_path,
+            0,
+            heap_relation,
+            options,
+        )
+    }
+
+    /// Creates an empty index for the next generation of an existing index, next to the
+    /// generation that's currently serving queries. It has its own index directory, so that
+    /// it can be built like any other index before `promote_on_commit` swaps it in.
+    pub fn new_generation<'a>(
+        index_relation: &PgRelation,
+        heap_relation: &PgRelation,
+        options: PgBox<ParadeOptions>,
+    ) -> Result<&'a mut Self, Box<dyn Error>> {
+        let index_oid = index_relation.oid();
+        let generation = Self::from_index_oid(index_oid).generation + 1;
+        let generation_directory_path = Self::get_generation_directory(index_oid, generation);
+
+        // Clean up after a rebuild that never finished, like one that crashed.
+        if Path::new(&generation_directory_path).exists() {
+            Self::drop_index_directory(&generation_directory_path)?;
+        }
+
+        Self::create(
+            index_relation.name().to_string(),
+            generation_directory_path,
+            generation,
+            heap_relation,
+            options,
+        )
+    }
+
+    /// Whether an index has been built for a relation. Rebuilding an index that exists, like
+    /// REINDEX does, builds a new generation of it.
+    pub fn exists(index_oid: pg_sys::Oid) -> bool {
+        Self::migrate_legacy_directory(index_oid);
+        Self::directory_exists(index_oid)
+    }
+
+    /// Whether a relation has an index directory, without moving an index built by an older
+    /// version into it first. It only checks the filesystem, so it's cheap enough to call for
+    /// any relation, like every relation that's dropped.
+    pub fn directory_exists(index_oid: pg_sys::Oid) -> bool {
+        Path::new(&Self::get_field_configs_path(Self::get_index_directory(
+            index_oid,
+        )))
+        .exists()
+    }
+
+    fn create<'a>(
         name: String,
+        index_directory_path: String,
+        generation: u64,
         heap_relation: &PgRelation,
         options: PgBox<ParadeOptions>,
-    ) -> Result<&mut Self, Box<dyn Error>> {
-        let data_directory = Self::data_directory(&name);
+    ) -> Result<&'a mut Self, Box<dyn Error>> {
+        let data_directory = index_directory_path.clone();
 
         // This will fail if the index directory already exists.
         // This should have been cleaned up correctly by the writer server, so we won't
@@ -84,7 +194,28 @@ impl ParadeIndex {
         Self::create_index_directory(&data_directory).expect("failed to create paradedb directory");
 
         let key_field_name = options.get_key_field();
-        let result = Self::build_index_schema(heap_relation, &key_field_name, &options);
+
+        // Synonyms and user dictionaries need to be resolved before the schema is built,
+        // because they determine which analyzer each field is indexed with.
+        let mut text_fields = options.get_text_fields();
+        for text_options in text_fields.values_mut() {
+            text_options.tokenizer.resolve();
+            if let Some(synonyms) = text_options.synonyms.as_mut() {
+                synonyms.resolve();
+            }
+        }
+        let mut json_fields = options.get_json_fields();
+        for json_options in json_fields.values_mut() {
+            json_options.tokenizer.resolve();
+        }
+
+        let result = Self::build_index_schema(
+            heap_relation,
+            &key_field_name,
+            &text_fields,
+            &json_fields,
+            &options,
+        );
         let (schema, fields) = match result {
             Ok((s, f)) => (s, f),
             Err(e) => {
@@ -123,11 +254,11 @@ impl ParadeIndex {
         // We'll need to retrieve these along with the index.
         let mut field_configs: ParadeOptionMap = HashMap::new();
 
-        for (field_name, options) in options.get_text_fields() {
+        for (field_name, options) in text_fields {
             field_configs.insert(field_name, ParadeOption::Text(options));
         }
 
-        for (field_name, options) in options.get_json_fields() {
+        for (field_name, options) in json_fields {
             field_configs.insert(field_name, ParadeOption::Json(options));
         }
 
@@ -140,6 +271,7 @@ impl ParadeIndex {
         }
 
         Self::setup_tokenizers(&mut underlying_index, &field_configs);
+        let query_tokenizer_manager = create_query_tokenizer_manager(&field_configs);
 
         let reader = Self::reader(&underlying_index).unwrap_or_else(|_| {
             panic!("failed to create index reader while creating new index: {name}")
@@ -162,8 +294,12 @@ impl ParadeIndex {
             underlying_index,
             key_field_name,
             data_directory,
+            generation,
+            merge_policy: options.get_merge_policy(),
+            index_directory_path: index_directory_path.clone(),
             key_field,
             ctid_field,
+            query_tokenizer_manager,
         };
 
         // Serialize ParadeIndex to disk so it can be initialized by other connections.
@@ -179,19 +315,10 @@ impl ParadeIndex {
         telemetry::posthog::connection_start();
 
         // We need to return the Self that is borrowed from the cache.
-        let new_self_ref = Self::from_index_name(name.to_string().as_ref());
+        let new_self_ref = Self::from_index_directory(&index_directory_path);
         Ok(new_self_ref)
     }
 
-    fn data_directory(name: &str) -> String {
-        format!(
-            "{}/{}/{}",
-            env::postgres_data_dir_path().display(),
-            "paradedb",
-            name
-        )
-    }
-
     fn setup_tokenizers(underlying_index: &mut Index, field_configs: &ParadeOptionMap) {
         underlying_index.set_tokenizers(create_tokenizer_manager(field_configs));
         underlying_index.set_fast_field_tokenizers(create_normalizer_manager());
@@ -201,30 +328,59 @@ impl ParadeIndex {
         index
             .reader_builder()
             .reload_policy(tantivy::ReloadPolicy::Manual)
+            .doc_store_cache_num_blocks(PARADE_GUC.store_cache_blocks.get() as usize)
             .try_into()
     }
 
     unsafe fn into_cached_index(self) {
-        PARADE_INDEX_MEMORY.insert(self.name.clone(), self);
+        PARADE_INDEX_CURRENT.insert(self.index_directory_path.clone());
+        PARADE_INDEX_MEMORY.insert(self.index_directory_path.clone(), self);
     }
 
-    pub fn from_index_name<'a>(name: &str) -> &'a mut Self {
+    pub fn from_index_oid<'a>(index_oid: pg_sys::Oid) -> &'a mut Self {
+        let index_directory_path = unsafe { PARADE_INDEX_PENDING.get(&index_oid).cloned() }
+            .unwrap_or_else(|| {
+                Self::migrate_legacy_directory(index_oid);
+                Self::get_index_directory(index_oid)
+            });
+        Self::from_index_directory(&index_directory_path)
+    }
+
+    pub fn from_index_directory<'a>(index_directory_path: &str) -> &'a mut Self {
         unsafe {
-            if let Some(new_self) = PARADE_INDEX_MEMORY.get_mut(name) {
+            if !PARADE_INDEX_CURRENT.contains(index_directory_path) {
+                if let Some(cached) = PARADE_INDEX_MEMORY.get_mut(index_directory_path) {
+                    let current_config = Self::current_config(index_directory_path);
+                    let is_current = current_config.as_ref().map_or(true, |(_, data_directory)| {
+                        *data_directory == cached.data_directory
+                    });
+
+                    if is_current {
+                        // A renamed index only needs its new name.
+                        if let Ok((name, _)) = current_config {
+                            cached.name = name;
+                        }
+                        PARADE_INDEX_CURRENT.insert(index_directory_path.to_string());
+                    } else {
+                        PARADE_INDEX_MEMORY.remove(index_directory_path);
+                    }
+                }
+            }
+
+            if let Some(new_self) = PARADE_INDEX_MEMORY.get_mut(index_directory_path) {
                 return new_self;
             }
         }
 
-        let index_directory_path = Self::data_directory(name);
         let new_self =
-            Self::from_disk(&index_directory_path).expect("could not retrieve index from disk");
+            Self::from_disk(index_directory_path).expect("could not retrieve index from disk");
 
         // Since we've re-fetched the index, save it to the cache.
         unsafe {
             new_self.into_cached_index();
         }
 
-        Self::from_index_name(name)
+        Self::from_index_directory(index_directory_path)
     }
 
     pub fn get_key_value(&self, document: &Document) -> i64 {
@@ -241,17 +397,19 @@ impl ParadeIndex {
     }
 
     pub fn query_parser(&self) -> QueryParser {
-        QueryParser::for_index(
-            &self.underlying_index,
-            self.schema()
-                .fields()
-                .map(|(field, _)| field)
-                .collect::<Vec<_>>(),
-        )
+        let schema = self.schema();
+        let fields = schema.fields().map(|(field, _)| field).collect::<Vec<_>>();
+        QueryParser::new(schema, fields, self.query_tokenizer_manager.clone())
     }
 
     pub fn scan_state(&self, config: &SearchConfig) -> Result<TantivyScanState, ParadeIndexError> {
         // Prepare to perform a search.
+        self.reload()?;
+        Ok(TantivyScanState::new(self, config))
+    }
+
+    /// Make the reader see the latest state of the index.
+    fn reload(&self) -> Result<(), ParadeIndexError> {
         // In case this is happening in the same transaction as an index build or an insert,
         // we want to commit first so that the most recent results appear.
         let writer_client = self.writer_client();
@@ -260,7 +418,7 @@ impl ParadeIndex {
         }
 
         self.reader.reload()?;
-        Ok(TantivyScanState::new(self, config))
+        Ok(())
     }
 
     pub fn schema(&self) -> Schema {
@@ -271,17 +429,186 @@ impl ParadeIndex {
         self.reader.searcher()
     }
 
+    /// Statistics about the documents, segments and disk usage of the index, as of its
+    /// latest commit.
+    pub fn stats(&self) -> Result<IndexStats, ParadeIndexError> {
+        self.reload()?;
+        let opstamp = self.underlying_index.load_metas()?.opstamp;
+        IndexStats::new(&self.searcher(), opstamp)
+    }
+
+    /// Sums the document frequency of every term in a field that starts with `prefix`,
+    /// across all segments of the index. The term dictionary of each segment is sorted,
+    /// so we can stop reading it as soon as we're past the prefix. Deleted documents are
+    /// still counted by the term dictionary until their segment is merged, so in segments
+    /// with deletes, the live documents of each term's postings are counted instead.
+    pub fn prefix_terms(
+        &self,
+        field: Field,
+        prefix: &str,
+    ) -> Result<HashMap<String, u64>, ParadeIndexError> {
+        self.reload()?;
+        let mut doc_freqs: HashMap<String, u64> = HashMap::new();
+
+        for segment_reader in self.searcher().segment_readers() {
+            let inverted_index = segment_reader.inverted_index(field)?;
+            let mut term_stream = inverted_index
+                .terms()
+                .range()
+                .ge(prefix.as_bytes())
+                .into_stream()?;
+
+            while term_stream.advance() {
+                if !term_stream.key().starts_with(prefix.as_bytes()) {
+                    break;
+                }
+                let term_info = term_stream.value();
+                let doc_freq = match segment_reader.alive_bitset() {
+                    Some(alive_bitset) => inverted_index
+                        .read_postings_from_terminfo(term_info, IndexRecordOption::Basic)?
+                        .doc_freq_given_deletes(alive_bitset),
+                    None => term_info.doc_freq,
+                };
+                if doc_freq == 0 {
+                    continue;
+                }
+                let term = String::from_utf8_lossy(term_stream.key()).into_owned();
+                *doc_freqs.entry(term).or_default() += doc_freq as u64;
+            }
+        }
+
+        Ok(doc_freqs)
+    }
+
+    /// The ctids of every document in the index, as of its latest commit.
+    pub fn ctids(&self) -> Result<HashSet<u64>, ParadeIndexError> {
+        self.reload()?;
+        let mut ctids = HashSet::new();
+
+        for segment_reader in self.searcher().segment_readers() {
+            let store_reader =
+                segment_reader.get_store_reader(PARADE_GUC.store_cache_blocks.get() as usize)?;
+            for doc_id in segment_reader.doc_ids_alive() {
+                if let Some(Value::U64(ctid)) = store_reader.get(doc_id)?.get_first(self.ctid_field)
+                {
+                    ctids.insert(*ctid);
+                }
+            }
+        }
+
+        Ok(ctids)
+    }
+
+    /// Finds every term in a field within `max_distance` edits of `term`, along with its
+    /// edit distance and its document frequency summed across all segments of the index.
+    pub fn fuzzy_terms(
+        &self,
+        field: Field,
+        term: &str,
+        max_distance: u8,
+        transpose_cost_one: bool,
+    ) -> Result<HashMap<String, (u8, u64)>, ParadeIndexError> {
+        self.reload()?;
+        // The same automaton Tantivy's FuzzyTermQuery walks the term dictionary with, so
+        // only the terms within range are visited.
+        let dfa = LevenshteinDfa::new(term, max_distance, transpose_cost_one);
+        let mut matches: HashMap<String, (u8, u64)> = HashMap::new();
+
+        for segment_reader in self.searcher().segment_readers() {
+            let inverted_index = segment_reader.inverted_index(field)?;
+            let mut term_stream = inverted_index.terms().search(&dfa).into_stream()?;
+
+            while term_stream.advance() {
+                let Some(distance) = dfa.distance(term_stream.key()) else {
+                    continue;
+                };
+                let term = String::from_utf8_lossy(term_stream.key()).into_owned();
+                let (_, doc_freq) = matches.entry(term).or_insert((distance, 0));
+                *doc_freq += term_stream.value().doc_freq as u64;
+            }
+        }
+
+        Ok(matches)
+    }
+
+    /// The analyzer that queries on a text field are tokenized with, or `None` if the
+    /// field isn't a text field.
+    pub fn query_analyzer(&self, field_name: &str) -> Option<TextAnalyzer> {
+        match self.field_configs.get(field_name) {
+            Some(ParadeOption::Text(text_options)) => Some(text_options.query_analyzer()),
+            _ => None,
+        }
+    }
+
+    /// The analyzer that was used to tokenize a field when it was indexed.
+    pub fn tokenizer_for_field(&self, field: Field) -> Result<TextAnalyzer, ParadeIndexError> {
+        Ok(self.underlying_index.tokenizer_for_field(field)?)
+    }
+
+    /// The synonyms to expand queries with, for each text field that uses query time synonyms.
+    /// These are loaded for every search, so that changes to the paradedb.synonyms table
+    /// take effect without having to rebuild the index.
+    pub fn query_synonyms(&self) -> HashMap<Field, SynonymMap> {
+        let mut query_synonyms = HashMap::new();
+
+        for (field_name, field_config) in &self.field_configs {
+            let ParadeOption::Text(text_options) = field_config else {
+                continue;
+            };
+            let Some(synonyms) = &text_options.synonyms else {
+                continue;
+            };
+            if synonyms.expand != SynonymExpansion::Query {
+                continue;
+            }
+            let Some(field) = self.fields.get(field_name) else {
+                continue;
+            };
+
+            let mut analyzer = self.tokenizer_for_field(*field).unwrap_or_else(|err| {
+                panic!("error loading tokenizer for field {field_name}: {err:?}")
+            });
+            let synonym_map = SynonymMap::new(&synonyms.load_sets(), &mut analyzer);
+            if !synonym_map.is_empty() {
+                query_synonyms.insert(*field, synonym_map);
+            }
+        }
+
+        query_synonyms
+    }
+
     /// Retrieve an owned writer for a given index. This is a static method, as
     /// we expect to be called from the writer process. The return type needs to
     /// be entirely owned by the new process, with no references.
     pub fn writer(index_directory_path: &str) -> Result<IndexWriter, ParadeIndexError> {
         let parade_index = Self::from_disk(index_directory_path)?;
+        let (num_threads, memory_budget) = Self::writer_settings();
         let index_writer = parade_index
             .underlying_index
-            .writer(INDEX_TANTIVY_MEMORY_BUDGET)?;
+            .writer_with_num_threads(num_threads, memory_budget)?;
+        index_writer.set_merge_policy((&parade_index.merge_policy).into());
         Ok(index_writer)
     }
 
+    /// The number of indexing threads and the memory budget of a writer, from the writer
+    /// settings. Every thread needs a share of the budget, so a budget that's too small for
+    /// the threads that were asked for gets fewer threads.
+    fn writer_settings() -> (usize, usize) {
+        let memory_budget = PARADE_GUC.writer_memory_budget_mb.get() as usize * BYTES_IN_MB;
+        let num_threads = match PARADE_GUC.writer_threads.get() {
+            0 => std::thread::available_parallelism()
+                .map_or(1, |num_cpus| num_cpus.get())
+                .min(WRITER_THREADS_AUTO_MAX),
+            num_threads => num_threads as usize,
+        };
+
+        let num_threads = num_threads
+            .min(memory_budget / WRITER_THREAD_MEMORY_BUDGET_MIN)
+            .max(1);
+        let memory_budget = memory_budget.min(num_threads * WRITER_THREAD_MEMORY_BUDGET_MAX);
+        (num_threads, memory_budget)
+    }
+
     pub fn get_field_configs_path<T: AsRef<Path>>(index_directory_path: T) -> String {
         format!(
             "{}_parade_field_configs.json",
@@ -314,24 +641,196 @@ impl ParadeIndex {
         file.flush().unwrap();
     }
 
+    /// The directory of the generation that an index currently points at, read without
+    /// opening the index.
+    pub fn current_data_directory(index_directory_path: &str) -> Result<String, ParadeIndexError> {
+        Self::current_config(index_directory_path).map(|(_, data_directory)| data_directory)
+    }
+
+    /// The name and the generation directory in an index's configuration, read without
+    /// opening the index.
+    fn current_config(index_directory_path: &str) -> Result<(String, String), ParadeIndexError> {
+        let config_path = Self::get_field_configs_path(index_directory_path);
+        let config: serde_json::Value = serde_json::from_str(&fs::read_to_string(config_path)?)?;
+        match (config["name"].as_str(), config["data_directory"].as_str()) {
+            (Some(name), Some(data_directory)) => {
+                Ok((name.to_string(), data_directory.to_string()))
+            }
+            _ => Err(ParadeIndexError::IncompleteConfig(
+                index_directory_path.to_string(),
+            )),
+        }
+    }
+
+    /// Records the new name of a renamed index in its configuration, so that every backend
+    /// picks it up when it's invalidated. If the transaction aborts, the old name is put back.
+    pub fn rename(index_oid: pg_sys::Oid, name: &str) -> Result<(), ParadeIndexError> {
+        Self::migrate_legacy_directory(index_oid);
+        let index_directory_path = Self::get_index_directory(index_oid);
+        let old_name = Self::write_config_value(&index_directory_path, "name", json!(name))?;
+
+        register_xact_callback(PgXactCallbackEvent::Abort, move || {
+            Self::write_config_value(&index_directory_path, "name", old_name.clone())
+                .unwrap_or_else(|err| {
+                    pgrx::log!("error while restoring the name of index {old_name}: {err:?}")
+                });
+        });
+
+        Ok(())
+    }
+
+    /// Records a new merge policy in an index's configuration. It doesn't change which
+    /// documents are indexed, so unlike the options of its fields, the index isn't rebuilt.
+    /// The writer server switches its writer over to the new policy once the transaction
+    /// commits. If the transaction aborts, the old policy is put back.
+    pub fn set_merge_policy(
+        index_oid: pg_sys::Oid,
+        merge_policy: &ParadeMergePolicy,
+    ) -> Result<(), ParadeIndexError> {
+        Self::migrate_legacy_directory(index_oid);
+        let index_directory_path = Self::get_index_directory(index_oid);
+        let old_merge_policy = Self::write_config_value(
+            &index_directory_path,
+            "merge_policy",
+            serde_json::to_value(merge_policy)?,
+        )?;
+
+        let request = WriterRequest::SetMergePolicy {
+            index_directory_path: index_directory_path.clone(),
+            merge_policy: merge_policy.clone(),
+        };
+        let writer_client = unsafe { PARADE_INDEX_WRITER_CLIENT.clone() };
+        register_xact_callback(PgXactCallbackEvent::Commit, move || {
+            writer_client
+                .lock()
+                .map_err(ParadeIndexError::from)
+                .and_then(|mut client| client.request(request).map_err(ParadeIndexError::from))
+                .unwrap_or_else(|err| {
+                    pgrx::log!("error while sending merge policy to writer server: {err:?}")
+                });
+        });
+
+        register_xact_callback(PgXactCallbackEvent::Abort, move || {
+            Self::write_config_value(&index_directory_path, "merge_policy", old_merge_policy)
+                .unwrap_or_else(|err| {
+                    pgrx::log!(
+                        "error while restoring the merge policy of {index_directory_path}: {err:?}"
+                    )
+                });
+        });
+
+        Ok(())
+    }
+
+    /// Replaces a value in an index's configuration, and returns the value it replaced, or
+    /// null if there wasn't one.
+    fn write_config_value(
+        index_directory_path: &str,
+        key: &str,
+        value: serde_json::Value,
+    ) -> Result<serde_json::Value, ParadeIndexError> {
+        let config_path = Self::get_field_configs_path(index_directory_path);
+        let mut config: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&config_path)?)?;
+        let old_value = config[key].take();
+        // Configurations written by older versions can be missing keys that have a default,
+        // which has to stay missing when the old value is put back.
+        if value.is_null() {
+            if let Some(config) = config.as_object_mut() {
+                config.remove(key);
+            }
+        } else {
+            config[key] = value;
+        }
+
+        // Like promoting a generation, the configuration is swapped in with an atomic rename,
+        // so other processes never read a partially written file.
+        let temporary_path = format!("{config_path}.tmp");
+        fs::write(&temporary_path, serde_json::to_string(&config)?)?;
+        fs::rename(temporary_path, config_path)?;
+
+        Ok(old_value)
+    }
+
+    /// Points an index at a generation built with `new_generation`, by renaming the
+    /// generation's configuration over the index's. The rename is atomic, so every process
+    /// that opens the index sees either the old generation or the new one.
+    /// This function must not panic, because it is used by the ParadeServer.
+    pub fn promote(
+        index_directory_path: &str,
+        generation_directory_path: &str,
+    ) -> Result<(), ParadeIndexError> {
+        fs::rename(
+            Self::get_field_configs_path(generation_directory_path),
+            Self::get_field_configs_path(index_directory_path),
+        )?;
+
+        Ok(())
+    }
+
+    /// Swaps in a generation of an index built with `new_generation` when the current
+    /// transaction commits, and deletes the generation it replaced. If the transaction
+    /// aborts, the new generation is deleted instead. In the meantime, this backend uses
+    /// the new generation for the index.
+    pub fn promote_on_commit(index_oid: pg_sys::Oid, generation_directory_path: &str) {
+        let request = WriterRequest::Promote {
+            index_directory_path: Self::get_index_directory(index_oid),
+            generation_directory_path: generation_directory_path.to_string(),
+            paths_to_delete: vec![Self::from_index_oid(index_oid).data_directory.clone()],
+        };
+        unsafe {
+            PARADE_INDEX_PENDING.insert(index_oid, generation_directory_path.to_string());
+        }
+
+        let writer_client = unsafe { PARADE_INDEX_WRITER_CLIENT.clone() };
+        register_xact_callback(PgXactCallbackEvent::Commit, move || {
+            unsafe {
+                PARADE_INDEX_PENDING.remove(&index_oid);
+            }
+            writer_client
+                .lock()
+                .map_err(ParadeIndexError::from)
+                .and_then(|mut client| client.request(request).map_err(ParadeIndexError::from))
+                .unwrap_or_else(|err| {
+                    pgrx::log!("error while sending index promotion to writer server: {err:?}")
+                });
+        });
+
+        let generation_directory_path = generation_directory_path.to_string();
+        register_xact_callback(PgXactCallbackEvent::Abort, move || {
+            unsafe {
+                PARADE_INDEX_PENDING.remove(&index_oid);
+            }
+            Self::drop_index_directory(&generation_directory_path).unwrap_or_else(|err| {
+                pgrx::log!(
+                    "error while dropping aborted index generation {generation_directory_path}: {err:?}"
+                )
+            });
+        });
+    }
+
     /// This function must not panic, because it use used by the ParadeServer, which cannot
     /// handle panics.
     fn from_disk(index_directory_path: &str) -> Result<Self, ParadeIndexError> {
         let config_path = &Self::get_field_configs_path(index_directory_path);
         let serialized_data = fs::read_to_string(config_path)?;
-        let new_self = serde_json::from_str(&serialized_data)?;
+        let mut new_self: Self = serde_json::from_str(&serialized_data)?;
+        new_self.index_directory_path = index_directory_path.to_string();
         Ok(new_self)
     }
 
     fn register_commit_callback(&self) -> Result<(), ParadeIndexError> {
         let writer_client = self.writer_client();
         Transaction::call_once_on_precommit(TRANSACTION_CACHE_ID, move || {
+            unsafe {
+                std::mem::take(&mut *PARADE_INDEX_TRANSACTION_WRITES);
+            }
             writer_client
                 .lock()
                 .map_err(ParadeIndexError::from)
                 .and_then(|mut client| {
                     client
-                        .request(WriterRequest::Commit)
+                        .request(WriterRequest::CoalescedCommit)
                         .map_err(ParadeIndexError::from)
                 })
                 .unwrap_or_else(|err| {
@@ -341,12 +840,13 @@ impl ParadeIndex {
 
         let writer_client = self.writer_client();
         Transaction::call_once_on_abort(TRANSACTION_CACHE_ID, move || {
+            let writes = unsafe { std::mem::take(&mut *PARADE_INDEX_TRANSACTION_WRITES) };
             writer_client
                 .lock()
                 .map_err(ParadeIndexError::from)
                 .and_then(|mut client| {
                     client
-                        .request(WriterRequest::Abort)
+                        .request(WriterRequest::Abort { writes })
                         .map_err(ParadeIndexError::from)
                 })
                 .unwrap_or_else(|err| {
@@ -357,11 +857,31 @@ impl ParadeIndex {
         Ok(())
     }
 
+    /// Keeps track of a write of the current transaction, if commits are coalesced.
+    fn track_write(write: impl FnOnce(&mut TransactionWrites)) {
+        if PARADE_GUC.commit_interval_ms.get() > 0 {
+            unsafe { write(&mut PARADE_INDEX_TRANSACTION_WRITES) }
+        }
+    }
+
+    /// Marks the index as one that only the current transaction writes to, because it's
+    /// building or emptying it, so that an abort discards all of its uncommitted writes.
+    pub fn discard_on_abort(&self) {
+        Self::track_write(|writes| writes.discard(&self.index_directory_path));
+    }
+
     pub fn insert(&mut self, index_entries: Vec<IndexEntry>) -> Result<(), ParadeIndexError> {
+        if let Some(IndexValue::U64(ctid)) = index_entries
+            .iter()
+            .find(|entry| entry.key == self.ctid_field)
+            .map(|entry| &entry.value)
+        {
+            Self::track_write(|writes| writes.insert(&self.index_directory_path, *ctid));
+        }
+
         // Send the insert requests to the writer server.
-        let index_directory_path = Self::get_index_directory(&self.name);
         let request = WriterRequest::Insert {
-            index_directory_path,
+            index_directory_path: self.index_directory_path.clone(),
             index_entries,
             key_field: self.key_field,
         };
@@ -378,13 +898,17 @@ impl ParadeIndex {
         &mut self,
         should_delete: impl Fn(*mut ItemPointerData) -> bool,
     ) -> Result<(u32, u32), ParadeIndexError> {
+        // CREATE INDEX CONCURRENTLY calls this to find which rows were indexed by the build,
+        // which committed in an earlier transaction.
+        self.reload()?;
+
         let mut deleted: u32 = 0;
         let mut not_deleted: u32 = 0;
         let mut ctids_to_delete: Vec<u64> = vec![];
 
         for segment_reader in self.searcher().segment_readers() {
             let store_reader = segment_reader
-                .get_store_reader(CACHE_NUM_BLOCKS)
+                .get_store_reader(PARADE_GUC.store_cache_blocks.get() as usize)
                 .expect("Failed to get store reader");
 
             for (delete, ctid) in (0..segment_reader.num_docs())
@@ -412,7 +936,7 @@ impl ParadeIndex {
         let request = WriterRequest::Delete {
             field: self.ctid_field,
             ctids: ctids_to_delete,
-            index_directory_path: Self::get_index_directory(&self.name),
+            index_directory_path: self.index_directory_path.clone(),
         };
         self.writer_client().lock()?.request(request)?;
 
@@ -420,18 +944,51 @@ impl ParadeIndex {
         Ok((deleted, not_deleted))
     }
 
-    pub fn drop_index(index_name: &str) -> Result<(), ParadeIndexError> {
-        let mut writer_client = WriterClient::from_writer_addr();
-        let index_directory_path = Self::get_index_directory(index_name);
-        let paths_to_delete = vec![
+    /// Deletes every document in the index. Like other deletes, it's committed along with
+    /// the current transaction.
+    pub fn delete_all(&mut self) -> Result<(), ParadeIndexError> {
+        self.discard_on_abort();
+        let request = WriterRequest::DeleteAll {
+            index_directory_path: self.index_directory_path.clone(),
+        };
+        self.writer_client().lock()?.request(request)?;
+
+        self.register_commit_callback()?;
+        Ok(())
+    }
+
+    pub fn drop_index(index_oid: pg_sys::Oid) -> Result<(), ParadeIndexError> {
+        Self::drop_index_directory(&Self::get_index_directory(index_oid))
+    }
+
+    fn drop_index_directory(index_directory_path: &str) -> Result<(), ParadeIndexError> {
+        let index_directory_path = index_directory_path.to_string();
+        let mut paths_to_delete = vec![
             index_directory_path.clone(),
             ParadeIndex::get_field_configs_path(&index_directory_path),
             format!("{index_directory_path}/.tantivy-writer.lock"),
             format!("{index_directory_path}/.tantivy-meta.lock"),
         ];
 
+        // An index that's been rebuilt is stored in the directory of its latest generation.
+        if let Ok(data_directory) = Self::current_data_directory(&index_directory_path) {
+            if data_directory != index_directory_path {
+                paths_to_delete.push(data_directory);
+            }
+        }
+
+        Self::delete_paths(&index_directory_path, paths_to_delete)
+    }
+
+    /// Deletes files and directories of an index through the writer server, so that it closes
+    /// any writer it has open on the index first.
+    pub fn delete_paths(
+        index_directory_path: &str,
+        paths_to_delete: Vec<String>,
+    ) -> Result<(), ParadeIndexError> {
+        let mut writer_client = WriterClient::from_writer_addr();
         let request = WriterRequest::DropIndex {
-            index_directory_path,
+            index_directory_path: index_directory_path.to_string(),
             paths_to_delete,
         };
 
@@ -441,21 +998,122 @@ impl ParadeIndex {
     }
 
     pub fn vacuum(&mut self) -> Result<(), ParadeIndexError> {
-        let index_directory_path = Self::get_index_directory(&self.name);
         let request = WriterRequest::Vacuum {
-            index_directory_path,
+            index_directory_path: self.index_directory_path.clone(),
         };
         self.writer_client().lock()?.request(request)?;
         Ok(())
     }
 
-    fn get_index_directory(name: &str) -> String {
+    /// Merges the segments of the index until it has at most `max_segments` of them, and
+    /// deletes the files of the segments that were merged away.
+    pub fn optimize(&mut self, max_segments: usize) -> Result<(), ParadeIndexError> {
+        let request = WriterRequest::Optimize {
+            index_directory_path: self.index_directory_path.clone(),
+            max_segments,
+        };
+        self.writer_client().lock()?.request(request)?;
+        Ok(())
+    }
+
+    /// Indexes are stored under the OIDs of their database and relation, rather than their
+    /// name. This way an index keeps its directory when it's renamed, and REINDEX INDEX
+    /// CONCURRENTLY, which builds a copy of an index under a new OID before swapping their
+    /// names, can build the copy next to the original.
+    pub fn get_index_directory(index_oid: pg_sys::Oid) -> String {
+        let database_oid = unsafe { pg_sys::MyDatabaseId };
         crate::env::paradedb_data_dir_path()
-            .join(name)
+            .join(database_oid.as_u32().to_string())
+            .join(index_oid.as_u32().to_string())
             .display()
             .to_string()
     }
 
+    pub fn get_generation_directory(index_oid: pg_sys::Oid, generation: u64) -> String {
+        format!("{}.{generation}", Self::get_index_directory(index_oid))
+    }
+
+    /// Indexes built by versions that named their directories after the index, directly in
+    /// the paradedb directory, are moved to the directory of their OID when they're first
+    /// used. The index's name has to be looked up in the catalog, so this must not be called
+    /// once the transaction has ended.
+    pub fn migrate_legacy_directory(index_oid: pg_sys::Oid) {
+        let index_directory_path = Self::get_index_directory(index_oid);
+        if Path::new(&Self::get_field_configs_path(&index_directory_path)).exists() {
+            return;
+        }
+
+        let index_name = unsafe { pg_sys::get_rel_name(index_oid) };
+        if index_name.is_null() {
+            return;
+        }
+        let index_name = unsafe { std::ffi::CStr::from_ptr(index_name) }
+            .to_string_lossy()
+            .into_owned();
+        let legacy_directory_path = crate::env::paradedb_data_dir_path()
+            .join(&index_name)
+            .display()
+            .to_string();
+        if !Path::new(&Self::get_field_configs_path(&legacy_directory_path)).exists() {
+            return;
+        }
+
+        // Another backend could be moving the same index, so only one of them gets to.
+        unsafe {
+            pg_sys::LockRelationOid(
+                index_oid,
+                pg_sys::ShareUpdateExclusiveLock as pg_sys::LOCKMODE,
+            );
+        }
+        if Path::new(&Self::get_field_configs_path(&index_directory_path)).exists() {
+            return;
+        }
+
+        Self::move_directory(&legacy_directory_path, &index_directory_path).unwrap_or_else(
+            |err| {
+                panic!(
+                    "error moving index {index_name} from {legacy_directory_path} to {index_directory_path}: {err:?}"
+                )
+            },
+        );
+
+        plog!(
+            "moved bm25 index directory",
+            json!({
+                "name": index_name,
+                "from": legacy_directory_path,
+                "to": index_directory_path
+            })
+        );
+    }
+
+    /// Moves an index to another directory. The configuration is moved last with an atomic
+    /// rename, so the index is only found in its new directory once the move is done.
+    fn move_directory(
+        from_directory_path: &str,
+        to_directory_path: &str,
+    ) -> Result<(), ParadeIndexError> {
+        if let Some(parent_directory) = Path::new(to_directory_path).parent() {
+            fs::create_dir_all(parent_directory)?;
+        }
+        fs::rename(from_directory_path, to_directory_path)?;
+
+        let from_config_path = Self::get_field_configs_path(from_directory_path);
+        let mut config: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&from_config_path)?)?;
+        config["data_directory"] = json!(to_directory_path);
+
+        let temporary_path = format!("{from_config_path}.tmp");
+        fs::write(&temporary_path, serde_json::to_string(&config)?)?;
+        fs::rename(
+            temporary_path,
+            Self::get_field_configs_path(to_directory_path),
+        )?;
+        fs::remove_file(from_config_path)?;
+
+        Ok(())
+    }
+
     pub fn row_to_index_entries(
         &self,
         ctid: pg_sys::ItemPointerData,
@@ -475,16 +1133,16 @@ impl ParadeIndex {
     fn build_index_schema(
         heap_relation: &PgRelation,
         key_field_name: &str,
+        text_fields: &HashMap<String, ParadeTextOptions>,
+        json_fields: &HashMap<String, ParadeJsonOptions>,
         options: &PgBox<ParadeOptions>,
     ) -> Result<(Schema, HashMap<String, Field>), String> {
         let tupdesc = heap_relation.tuple_desc();
         let mut schema_builder = Schema::builder();
         let mut fields: HashMap<String, Field> = HashMap::new();
 
-        let text_fields = options.get_text_fields();
         let numeric_fields = options.get_numeric_fields();
         let boolean_fields = options.get_boolean_fields();
-        let json_fields = options.get_json_fields();
 
         if text_fields.is_empty()
             && numeric_fields.is_empty()
@@ -519,7 +1177,7 @@ impl ParadeIndex {
                             panic!("bm25 key field must be an integer type, received text")
                         } else {
                             text_fields.get(attname).map(|options| {
-                                let text_options: TextOptions = (*options).into();
+                                let text_options: TextOptions = options.clone().into();
                                 schema_builder.add_text_field(attname, text_options)
                             })
                         }
@@ -568,7 +1226,7 @@ impl ParadeIndex {
                             panic!("bm25 id column must be an integer type, received json")
                         } else {
                             json_fields.get(attname).map(|options| {
-                                let json_options: JsonObjectOptions = (*options).into();
+                                let json_options: JsonObjectOptions = options.clone().into();
                                 schema_builder.add_json_field(attname, json_options)
                             })
                         }
@@ -610,6 +1268,10 @@ impl<'de> Deserialize<'de> for ParadeIndex {
             field_configs: ParadeOptionMap,
             key_field_name: String,
             data_directory: String,
+            #[serde(default)]
+            generation: u64,
+            #[serde(default)]
+            merge_policy: ParadeMergePolicy,
         }
 
         // Deserialize into the struct with automatic handling for most fields
@@ -619,12 +1281,15 @@ impl<'de> Deserialize<'de> for ParadeIndex {
             field_configs,
             key_field_name,
             data_directory,
+            generation,
+            merge_policy,
         } = ParadeIndexHelper::deserialize(deserializer)?;
 
         let mut underlying_index =
             Index::open_in_dir(&data_directory).expect("failed to open index");
         // We need to setup tokenizers again after retrieving an index from disk.
         Self::setup_tokenizers(&mut underlying_index, &field_configs);
+        let query_tokenizer_manager = create_query_tokenizer_manager(&field_configs);
 
         let schema = underlying_index.schema();
         let reader = Self::reader(&underlying_index).unwrap_or_else(|_| {
@@ -650,8 +1315,13 @@ impl<'de> Deserialize<'de> for ParadeIndex {
             underlying_index,
             key_field_name,
             data_directory,
+            generation,
+            merge_policy,
+            // Set by from_disk, which knows which path the index was read from.
+            index_directory_path: String::new(),
             key_field,
             ctid_field,
+            query_tokenizer_manager,
         })
     }
 }
@@ -676,6 +1346,9 @@ pub enum ParadeIndexError {
     #[error(transparent)]
     SerdeError(#[from] serde_json::Error),
 
+    #[error("index configuration at {0} is missing its name or data directory")]
+    IncompleteConfig(String),
+
     #[error("mutex lock on writer client failed: {0}")]
     WriterClientRace(String),
 }
@@ -691,18 +1364,19 @@ impl<T> From<PoisonError<T>> for ParadeIndexError {
 mod tests {
 
     use super::ParadeIndex;
+    use crate::operator::get_index_oid;
     use pgrx::*;
     use shared::testing::SETUP_SQL;
 
     #[pg_test]
     fn test_get_field_configs_path() {
-        let name = "thescore";
+        let database_oid = unsafe { pg_sys::MyDatabaseId }.as_u32();
         let current_execution_dir = std::env::current_dir().unwrap();
         let expected = format!(
-            "{}/paradedb/{name}_parade_field_configs.json",
+            "{}/paradedb/{database_oid}/16384_parade_field_configs.json",
             current_execution_dir.to_str().unwrap()
         );
-        let index_directory = ParadeIndex::get_index_directory(name);
+        let index_directory = ParadeIndex::get_index_directory(pg_sys::Oid::from(16384));
         let result = ParadeIndex::get_field_configs_path(index_directory);
         assert_eq!(result, expected);
     }
@@ -715,12 +1389,83 @@ mod tests {
     }
 
     #[pg_test]
-    fn test_from_index_name() {
+    fn test_from_index_oid() {
         crate::setup_background_workers();
         Spi::run(SETUP_SQL).expect("failed to create index");
-        let index_name = "one_republic_songs_bm25_index";
-        let index = ParadeIndex::from_index_name(index_name);
+        let oid = get_index_oid("one_republic_songs_bm25_index", "bm25")
+            .expect("could not find oid for one_republic")
+            .unwrap();
+        let index = ParadeIndex::from_index_oid(oid);
         let fields = &index.fields;
         assert_eq!(fields.len(), 8);
+        assert_eq!(index.name, "one_republic_songs_bm25_index");
+    }
+
+    #[pg_test]
+    fn test_reindex() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index");
+        let oid = get_index_oid("one_republic_songs_bm25_index", "bm25")
+            .expect("could not find oid for one_republic")
+            .unwrap();
+
+        // REINDEX keeps the OID of the index, so it's rebuilt into a new generation that
+        // replaces the current one at commit.
+        Spi::run("REINDEX INDEX one_republic_songs_bm25_index").expect("failed to reindex");
+        let generation =
+            ParadeIndex::from_index_directory(&ParadeIndex::get_generation_directory(oid, 1));
+        assert_eq!(generation.fields.len(), 8);
+
+        // Until then, only this backend uses the new generation.
+        let index_directory = ParadeIndex::get_index_directory(oid);
+        assert_eq!(
+            ParadeIndex::current_data_directory(&index_directory).unwrap(),
+            index_directory
+        );
+        assert_eq!(ParadeIndex::from_index_oid(oid).generation, 1);
+    }
+
+    #[pg_test]
+    fn test_truncate_then_insert() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index");
+
+        // Rows inserted after a TRUNCATE in the same transaction must be in the index.
+        Spi::run("TRUNCATE one_republic_songs").expect("failed to truncate");
+        Spi::run(
+            "INSERT INTO one_republic_songs (title, album, description) VALUES ('Rescue Me', 'Human', 'A new song')",
+        )
+        .expect("failed to insert row");
+
+        let count = Spi::get_one::<i64>(
+            "SELECT count(*) FROM one_republic_songs.search('description:song')",
+        )
+        .expect("failed to search index");
+        assert_eq!(count, Some(1));
+    }
+
+    #[pg_test]
+    fn test_migrate_legacy_directory() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index");
+        let oid = get_index_oid("one_republic_songs_bm25_index", "bm25")
+            .expect("could not find oid for one_republic")
+            .unwrap();
+
+        // Put the index where versions that named directories after indexes kept it.
+        let index_directory = ParadeIndex::get_index_directory(oid);
+        let legacy_directory = crate::env::paradedb_data_dir_path()
+            .join("one_republic_songs_bm25_index")
+            .display()
+            .to_string();
+        ParadeIndex::move_directory(&index_directory, &legacy_directory)
+            .expect("failed to move index");
+
+        assert!(ParadeIndex::exists(oid));
+        assert_eq!(
+            ParadeIndex::current_data_directory(&index_directory).unwrap(),
+            index_directory
+        );
+        assert!(!std::path::Path::new(&legacy_directory).exists());
     }
 }
@@ -0,0 +1,123 @@
+use serde::*;
+use tantivy::merge_policy::{LogMergePolicy, MergePolicy, NoMergePolicy};
+
+// Tantivy's own defaults for LogMergePolicy.
+const DEFAULT_MIN_NUM_SEGMENTS: usize = 8;
+const DEFAULT_MAX_DOCS_BEFORE_MERGE: usize = 10_000_000;
+const DEFAULT_MIN_LAYER_SIZE: u32 = 10_000;
+const DEFAULT_LEVEL_LOG_SIZE: f64 = 0.75;
+const DEFAULT_DEL_DOCS_RATIO_BEFORE_MERGE: f32 = 1.0;
+
+// Merge policies
+// Like tokenizers, serde picks a ParadeMergePolicy variant based on the value of the
+// "type" key. An index with no merge_policy option uses Tantivy's default policy.
+#[derive(Clone, Deserialize, Debug, Serialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum ParadeMergePolicy {
+    /// Merges segments of a similar size together, once there are enough of them.
+    #[serde(rename = "log")]
+    Log {
+        /// How many segments of a similar size there must be before they're merged.
+        #[serde(default = "default_min_num_segments")]
+        min_num_segments: usize,
+        /// Segments with more documents than this are never merged.
+        #[serde(default = "default_max_docs_before_merge")]
+        max_docs_before_merge: usize,
+        /// Segments with fewer documents than this are treated as if they had this many.
+        #[serde(default = "default_min_layer_size")]
+        min_layer_size: u32,
+        /// How far apart, on a log scale, the sizes of segments that are merged together can be.
+        #[serde(default = "default_level_log_size")]
+        level_log_size: f64,
+        /// The ratio of deleted documents that makes a segment be merged, even if it has no
+        /// segments of a similar size to be merged with.
+        #[serde(default = "default_del_docs_ratio_before_merge")]
+        del_docs_ratio_before_merge: f32,
+    },
+    /// Never merges segments in the background. Segments are only merged by optimize_bm25.
+    #[serde(rename = "no_merge")]
+    NoMerge,
+}
+
+impl Default for ParadeMergePolicy {
+    fn default() -> Self {
+        ParadeMergePolicy::Log {
+            min_num_segments: DEFAULT_MIN_NUM_SEGMENTS,
+            max_docs_before_merge: DEFAULT_MAX_DOCS_BEFORE_MERGE,
+            min_layer_size: DEFAULT_MIN_LAYER_SIZE,
+            level_log_size: DEFAULT_LEVEL_LOG_SIZE,
+            del_docs_ratio_before_merge: DEFAULT_DEL_DOCS_RATIO_BEFORE_MERGE,
+        }
+    }
+}
+
+impl ParadeMergePolicy {
+    /// Tantivy asserts on invalid merge policy parameters, which would bring down the
+    /// writer process, so they have to be checked before they're saved.
+    pub fn validate(&self) {
+        if let ParadeMergePolicy::Log {
+            min_num_segments,
+            max_docs_before_merge,
+            level_log_size,
+            del_docs_ratio_before_merge,
+            ..
+        } = self
+        {
+            if *min_num_segments < 2 {
+                panic!("min_num_segments of merge_policy must be at least 2")
+            }
+            if *max_docs_before_merge == 0 {
+                panic!("max_docs_before_merge of merge_policy must be greater than 0")
+            }
+            if !(level_log_size.is_finite() && *level_log_size > 0.0) {
+                panic!("level_log_size of merge_policy must be greater than 0")
+            }
+            if !(*del_docs_ratio_before_merge > 0.0 && *del_docs_ratio_before_merge <= 1.0) {
+                panic!("del_docs_ratio_before_merge of merge_policy must be greater than 0 and at most 1")
+            }
+        }
+    }
+}
+
+impl From<&ParadeMergePolicy> for Box<dyn MergePolicy> {
+    fn from(value: &ParadeMergePolicy) -> Self {
+        match value {
+            ParadeMergePolicy::Log {
+                min_num_segments,
+                max_docs_before_merge,
+                min_layer_size,
+                level_log_size,
+                del_docs_ratio_before_merge,
+            } => {
+                let mut merge_policy = LogMergePolicy::default();
+                merge_policy.set_min_num_segments(*min_num_segments);
+                merge_policy.set_max_docs_before_merge(*max_docs_before_merge);
+                merge_policy.set_min_layer_size(*min_layer_size);
+                merge_policy.set_level_log_size(*level_log_size);
+                merge_policy.set_del_docs_ratio_before_merge(*del_docs_ratio_before_merge);
+                Box::new(merge_policy)
+            }
+            ParadeMergePolicy::NoMerge => Box::new(NoMergePolicy),
+        }
+    }
+}
+
+fn default_min_num_segments() -> usize {
+    DEFAULT_MIN_NUM_SEGMENTS
+}
+
+fn default_max_docs_before_merge() -> usize {
+    DEFAULT_MAX_DOCS_BEFORE_MERGE
+}
+
+fn default_min_layer_size() -> u32 {
+    DEFAULT_MIN_LAYER_SIZE
+}
+
+fn default_level_log_size() -> f64 {
+    DEFAULT_LEVEL_LOG_SIZE
+}
+
+fn default_del_docs_ratio_before_merge() -> f32 {
+    DEFAULT_DEL_DOCS_RATIO_BEFORE_MERGE
+}
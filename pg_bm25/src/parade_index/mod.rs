@@ -1,7 +1,13 @@
 pub mod fields;
+pub mod fuzzy;
+pub mod gc;
 pub mod index;
+pub mod merge_policy;
 pub mod score;
+pub mod sort;
 pub mod state;
+pub mod stats;
+pub mod stream;
 
 #[cfg(any(test, feature = "pg_test"))]
 pub mod tests;
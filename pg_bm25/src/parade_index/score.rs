@@ -1,8 +1,9 @@
 use std::cmp::Ordering;
+use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct ParadeIndexScore {
     pub bm25: f32,
     pub key: i64,
@@ -27,3 +28,38 @@ impl PartialOrd for ParadeIndexScore {
         }
     }
 }
+
+/// A cursor that resumes a search after the result with this score and key, written as
+/// "score,key". Unlike an offset, the results before the cursor are never collected.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct SearchAfter(pub ParadeIndexScore);
+
+impl SearchAfter {
+    /// Whether the cursor comes before a result, in the order results are returned.
+    pub fn precedes(&self, score: &ParadeIndexScore) -> bool {
+        score.partial_cmp(&self.0) == Some(Ordering::Less)
+    }
+}
+
+impl fmt::Display for SearchAfter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Floats are displayed with as many digits as it takes to parse them back exactly.
+        write!(f, "{},{}", self.0.bm25, self.0.key)
+    }
+}
+
+impl TryFrom<String> for SearchAfter {
+    type Error = String;
+
+    fn try_from(cursor: String) -> Result<Self, Self::Error> {
+        let invalid =
+            || format!("search_after must be a score and a key like '1.5,42', received '{cursor}'");
+        let (bm25, key) = cursor.split_once(',').ok_or_else(invalid)?;
+
+        Ok(Self(ParadeIndexScore {
+            bm25: bm25.trim().parse().map_err(|_| invalid())?,
+            key: key.trim().parse().map_err(|_| invalid())?,
+        }))
+    }
+}
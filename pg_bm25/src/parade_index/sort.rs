@@ -0,0 +1,280 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use tantivy::schema::{FieldType, Schema};
+use tantivy::{DocId, SegmentReader};
+
+use super::score::ParadeIndexScore;
+
+/// The name that sorts by BM25 score rather than by a field.
+pub const SCORE: &str = "score";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// One of the keys that search results are sorted by, written like "release_year desc".
+/// Fields are sorted in ascending order by default, and the score in descending order.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SortBy {
+    pub field: String,
+    pub order: SortOrder,
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let field = parts
+            .next()
+            .ok_or_else(|| "sort_by is missing a field name".to_string())?
+            .to_string();
+
+        let order = match parts.next().map(str::to_lowercase).as_deref() {
+            None if field == SCORE => SortOrder::Desc,
+            None | Some("asc") => SortOrder::Asc,
+            Some("desc") => SortOrder::Desc,
+            Some(order) => return Err(format!("invalid sort order {order} for {field}")),
+        };
+
+        if parts.next().is_some() {
+            return Err(format!("invalid sort_by {s}"));
+        }
+
+        Ok(Self { field, order })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortValue {
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+}
+
+impl SortValue {
+    fn compare(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::I64(value), Self::I64(other)) => value.cmp(other),
+            (Self::U64(value), Self::U64(other)) => value.cmp(other),
+            (Self::F64(value), Self::F64(other)) => value.total_cmp(other),
+            (Self::Bool(value), Self::Bool(other)) => value.cmp(other),
+            // Every value of a sort key comes from the same column.
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+type SortValueReader = Box<dyn Fn(DocId) -> Option<SortValue> + Send + Sync>;
+
+#[derive(Debug, Clone)]
+enum SortColumn {
+    Score,
+    I64(String),
+    U64(String),
+    F64(String),
+    Bool(String),
+}
+
+/// The sort keys of a search, checked against the schema of the index.
+#[derive(Debug, Clone)]
+pub struct SortSpec(Vec<(SortColumn, SortOrder)>);
+
+impl SortSpec {
+    pub fn new(sort_by: &[SortBy], schema: &Schema) -> Self {
+        let columns = sort_by
+            .iter()
+            .map(|SortBy { field, order }| {
+                let field_name = field.trim();
+                if field_name == SCORE {
+                    return (SortColumn::Score, *order);
+                }
+
+                let field = schema
+                    .get_field(field_name)
+                    .unwrap_or_else(|_| panic!("sort_by field {field_name} does not exist"));
+                let field_entry = schema.get_field_entry(field);
+                if !field_entry.is_fast() {
+                    panic!("cannot sort by {field_name}, it must be indexed with fast: true")
+                }
+
+                let column = match field_entry.field_type() {
+                    FieldType::I64(_) => SortColumn::I64(field_name.to_string()),
+                    FieldType::U64(_) => SortColumn::U64(field_name.to_string()),
+                    FieldType::F64(_) => SortColumn::F64(field_name.to_string()),
+                    FieldType::Bool(_) => SortColumn::Bool(field_name.to_string()),
+                    _ => panic!(
+                        "cannot sort by {field_name}, only numeric and boolean fields can be sorted by"
+                    ),
+                };
+                (column, *order)
+            })
+            .collect();
+
+        Self(columns)
+    }
+
+    /// Reads the sort keys of the documents in a segment from its fast fields.
+    pub fn segment_sorter(
+        &self,
+        segment_reader: &SegmentReader,
+    ) -> impl Fn(DocId, ParadeIndexScore) -> SortScore {
+        let fast_fields = segment_reader.fast_fields();
+
+        let readers: Vec<(Option<SortValueReader>, SortOrder)> = self
+            .0
+            .iter()
+            .map(|(column, order)| {
+                let reader: Option<SortValueReader> = match column {
+                    SortColumn::Score => None,
+                    SortColumn::I64(name) => {
+                        let column = fast_fields.i64(name).unwrap_or_else(|err| {
+                            panic!("error reading fast field {name}: {err:?}")
+                        });
+                        Some(Box::new(move |doc| column.first(doc).map(SortValue::I64)))
+                    }
+                    SortColumn::U64(name) => {
+                        let column = fast_fields.u64(name).unwrap_or_else(|err| {
+                            panic!("error reading fast field {name}: {err:?}")
+                        });
+                        Some(Box::new(move |doc| column.first(doc).map(SortValue::U64)))
+                    }
+                    SortColumn::F64(name) => {
+                        let column = fast_fields.f64(name).unwrap_or_else(|err| {
+                            panic!("error reading fast field {name}: {err:?}")
+                        });
+                        Some(Box::new(move |doc| column.first(doc).map(SortValue::F64)))
+                    }
+                    SortColumn::Bool(name) => {
+                        let column = fast_fields.bool(name).unwrap_or_else(|err| {
+                            panic!("error reading fast field {name}: {err:?}")
+                        });
+                        Some(Box::new(move |doc| column.first(doc).map(SortValue::Bool)))
+                    }
+                };
+                (reader, *order)
+            })
+            .collect();
+
+        move |doc: DocId, score: ParadeIndexScore| SortScore {
+            keys: readers
+                .iter()
+                .map(|(reader, order)| {
+                    let value = match reader {
+                        Some(reader) => reader(doc),
+                        None => Some(SortValue::F64(score.bm25 as f64)),
+                    };
+                    (value, *order)
+                })
+                .collect(),
+            score,
+        }
+    }
+}
+
+/// A search result's sort keys. Results that come first compare as greater, because that's
+/// the order the top docs collector keeps them in.
+#[derive(Clone, PartialEq)]
+pub struct SortScore {
+    keys: Vec<(Option<SortValue>, SortOrder)>,
+    pub score: ParadeIndexScore,
+}
+
+impl PartialOrd for SortScore {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        for ((value, order), (other_value, _)) in self.keys.iter().zip(&other.keys) {
+            let ordering = match (value, other_value) {
+                (Some(value), Some(other_value)) => match order {
+                    SortOrder::Asc => other_value.compare(value),
+                    SortOrder::Desc => value.compare(other_value),
+                },
+                // Documents without a value for the field come last.
+                (Some(_), None) => Ordering::Greater,
+                (None, Some(_)) => Ordering::Less,
+                (None, None) => Ordering::Equal,
+            };
+            if ordering != Ordering::Equal {
+                return Some(ordering);
+            }
+        }
+
+        // Ties are broken by ascending key, so that pages are stable.
+        other.score.key.partial_cmp(&self.score.key)
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::{SortBy, SortOrder};
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+
+    #[pg_test]
+    fn test_parse_sort_by() {
+        let sort_by: SortBy = "release_year".parse().unwrap();
+        assert_eq!(sort_by.field, "release_year");
+        assert_eq!(sort_by.order, SortOrder::Asc);
+
+        let sort_by: SortBy = " release_year DESC ".parse().unwrap();
+        assert_eq!(sort_by.order, SortOrder::Desc);
+
+        let sort_by: SortBy = "score".parse().unwrap();
+        assert_eq!(sort_by.order, SortOrder::Desc);
+
+        assert!("release_year sideways".parse::<SortBy>().is_err());
+        assert!("release_year desc asc".parse::<SortBy>().is_err());
+    }
+
+    #[pg_test]
+    fn test_sort_by() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CALL paradedb.create_bm25(
+                index_name => 'sorted_songs',
+                table_name => 'one_republic_songs',
+                key_field => 'song_id',
+                text_fields => '{description: {}}',
+                numeric_fields => '{release_year: {fast: true}}'
+            );
+            "#,
+        )
+        .expect("failed to create index");
+
+        let keys = |args: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT string_agg(song_id::text, ',') FROM sorted_songs.rank('description:song', {args})"
+            ))
+            .expect("failed to rank query")
+            .unwrap()
+        };
+
+        // Ties on the release year are broken by key.
+        assert_eq!(keys("sort_by => 'release_year desc'"), "3,6,2,1,5,4,7");
+        assert_eq!(keys("sort_by => 'release_year'"), "4,7,1,5,2,3,6");
+        assert_eq!(
+            keys("sort_by => 'release_year desc', offset_rows => 1, limit_rows => 2"),
+            "6,2"
+        );
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_sort_by_requires_fast_field() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        Spi::run(
+            "SELECT * FROM one_republic_songs.rank('description:song', sort_by => 'release_year')",
+        )
+        .unwrap();
+    }
+}
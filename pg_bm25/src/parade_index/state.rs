@@ -1,23 +1,34 @@
+use crate::guc::PARADE_GUC;
 use crate::index_access::utils::SearchConfig;
+use crate::tokenizers::synonyms::SynonymMap;
+use std::collections::HashMap;
 use tantivy::collector::TopDocs;
-use tantivy::query::{BooleanQuery, RegexQuery};
+use tantivy::query::{
+    BooleanQuery, EmptyQuery, Explanation, PhrasePrefixQuery, PhraseQuery, QueryClone,
+    QueryParserError, RegexQuery, TermQuery,
+};
 use tantivy::query_grammar::Occur;
+use tantivy::tokenizer::TokenStream;
 use tantivy::{
     query::{Query, QueryParser},
     schema::*,
-    DocAddress, Score, Searcher,
+    DocAddress, Score, Searcher, TantivyError,
 };
 use tantivy::{DocId, SegmentReader};
 
+use super::fields::ToString;
+use super::fuzzy::DEFAULT_TRANSPOSE_COST_ONE;
 use super::index::ParadeIndex;
 use super::score::ParadeIndexScore;
+use super::sort::SortSpec;
+use super::stream::ScoreStream;
 
 pub struct TantivyScanState {
     pub schema: Schema,
     pub query: Box<dyn Query>,
     pub parser: QueryParser,
     pub searcher: Searcher,
-    pub iterator: *mut std::vec::IntoIter<(ParadeIndexScore, DocAddress)>,
+    pub iterator: *mut Box<dyn Iterator<Item = (ParadeIndexScore, DocAddress)>>,
     pub config: SearchConfig,
     pub key_field_name: String,
 }
@@ -26,7 +37,12 @@ impl TantivyScanState {
     pub fn new(parade_index: &ParadeIndex, config: &SearchConfig) -> Self {
         let schema = parade_index.schema();
         let mut parser = parade_index.query_parser();
-        let query = Self::query(config, &schema, &mut parser);
+        let query = if config.phrase_fields.is_empty() {
+            Self::query(config, &schema, &mut parser)
+        } else {
+            Self::phrase_query(config, parade_index)
+        };
+        let query = Self::expand_synonyms(query, &parade_index.query_synonyms());
         TantivyScanState {
             schema,
             query,
@@ -38,25 +54,33 @@ impl TantivyScanState {
         }
     }
 
-    pub fn key_field_value(&mut self, doc_address: DocAddress) -> i64 {
-        let retrieved_doc = self.searcher.doc(doc_address).expect("could not find doc");
+    /// The results of the search, best scoring first. If the config opts out of ordering them
+    /// with `order_by_score: false` and doesn't page or sort them, they're streamed in the
+    /// order they're stored in the index instead, rather than collected first.
+    pub fn scan(&mut self) -> Box<dyn Iterator<Item = (ParadeIndexScore, DocAddress)>> {
+        let SearchConfig {
+            limit_rows,
+            offset_rows,
+            sort_by,
+            search_after,
+            order_by_score,
+            ..
+        } = &self.config;
 
-        let key_field = self
-            .schema
-            .get_field(&self.key_field_name)
-            .expect("field '{key_field_name}' not found in schema");
+        let needs_collecting = limit_rows.is_some()
+            || offset_rows.is_some()
+            || !sort_by.is_empty()
+            || search_after.is_some()
+            || order_by_score.unwrap_or(true);
 
-        if let tantivy::schema::Value::I64(key_field_value) =
-            retrieved_doc.get_first(key_field).unwrap_or_else(|| {
-                panic!(
-                    "value for key_field '{}' not found in doc",
-                    &self.key_field_name,
-                )
-            })
-        {
-            *key_field_value
+        if needs_collecting {
+            Box::new(self.search().into_iter())
         } else {
-            panic!("error unwrapping ctid value")
+            Box::new(ScoreStream::new(
+                &self.searcher,
+                self.query.as_ref(),
+                &self.key_field_name,
+            ))
         }
     }
 
@@ -75,6 +99,14 @@ impl TantivyScanState {
         });
 
         let offset = self.config.offset_rows.unwrap_or(0);
+        if !self.config.sort_by.is_empty() {
+            if self.config.search_after.is_some() {
+                panic!("search_after cannot be combined with sort_by");
+            }
+            return self.search_sorted(limit, offset);
+        }
+
+        let search_after = self.config.search_after;
         let key_field_name = self.key_field_name.clone();
         let top_docs_by_custom_score = TopDocs::with_limit(limit).and_offset(offset).tweak_score(
             // tweak_score expects a function that will return a function. A little unusual for
@@ -89,9 +121,15 @@ impl TantivyScanState {
                     })
                     .first_or_default_col(0);
 
-                move |doc: DocId, original_score: Score| ParadeIndexScore {
-                    bm25: original_score,
-                    key: key_field_reader.get_val(doc),
+                move |doc: DocId, original_score: Score| {
+                    let score = ParadeIndexScore {
+                        bm25: original_score,
+                        key: key_field_reader.get_val(doc),
+                    };
+                    // Results up to the cursor are ranked below every result after it, so
+                    // they only take up the slots that nothing after the cursor could fill.
+                    let after_cursor = search_after.map_or(true, |cursor| cursor.precedes(&score));
+                    (after_cursor, score)
                 }
             },
         );
@@ -99,6 +137,77 @@ impl TantivyScanState {
         self.searcher
             .search(&self.query, &top_docs_by_custom_score)
             .expect("failed to search")
+            .into_iter()
+            .filter(|((after_cursor, _), _)| *after_cursor)
+            .map(|((_, score), doc_address)| (score, doc_address))
+            .collect()
+    }
+
+    /// Like `search`, but orders the results by the fast fields in the config's sort_by,
+    /// rather than by score. The offset and limit are still applied by the collector.
+    fn search_sorted(
+        &mut self,
+        limit: usize,
+        offset: usize,
+    ) -> Vec<(ParadeIndexScore, DocAddress)> {
+        let sort_spec = SortSpec::new(&self.config.sort_by, &self.schema);
+        let key_field_name = self.key_field_name.clone();
+        let top_docs_by_sort_keys = TopDocs::with_limit(limit).and_offset(offset).tweak_score(
+            move |segment_reader: &SegmentReader| {
+                let key_field_reader = segment_reader
+                    .fast_fields()
+                    .i64(&key_field_name)
+                    .unwrap_or_else(|err| {
+                        panic!("key field {} is not a u64: {err:?}", &key_field_name)
+                    })
+                    .first_or_default_col(0);
+                let sorter = sort_spec.segment_sorter(segment_reader);
+
+                move |doc: DocId, original_score: Score| {
+                    sorter(
+                        doc,
+                        ParadeIndexScore {
+                            bm25: original_score,
+                            key: key_field_reader.get_val(doc),
+                        },
+                    )
+                }
+            },
+        );
+
+        self.searcher
+            .search(&self.query, &top_docs_by_sort_keys)
+            .expect("failed to search")
+            .into_iter()
+            .map(|(sort_score, doc_address)| (sort_score.score, doc_address))
+            .collect()
+    }
+
+    /// Explains how the query scored the document with the given key. Returns `None` if
+    /// there's no document with that key, or if the document doesn't match the query.
+    pub fn explain(&self, key: i64) -> Option<Explanation> {
+        let key_field = self
+            .schema
+            .get_field(&self.key_field_name)
+            .unwrap_or_else(|_| panic!("field '{}' not found in schema", self.key_field_name));
+        let key_query = TermQuery::new(
+            Term::from_field_i64(key_field, key),
+            IndexRecordOption::Basic,
+        );
+
+        let (_, doc_address) = self
+            .searcher
+            .search(&key_query, &TopDocs::with_limit(1))
+            .expect("failed to search")
+            .into_iter()
+            .next()?;
+
+        match self.query.explain(&self.searcher, doc_address) {
+            Ok(explanation) => Some(explanation),
+            // Tantivy refuses to explain documents that don't match the query.
+            Err(TantivyError::InvalidArgument(_)) => None,
+            Err(err) => panic!("error explaining document with key {key}: {err:?}"),
+        }
     }
 
     pub fn doc(&self, doc_address: DocAddress) -> tantivy::Result<Document> {
@@ -145,8 +254,12 @@ impl TantivyScanState {
             }
         } else {
             let require_prefix = query_config.prefix.unwrap_or(true);
-            let transpose_cost_one = query_config.transpose_cost_one.unwrap_or(true);
-            let max_distance = query_config.distance.unwrap_or(2);
+            let transpose_cost_one = query_config
+                .transpose_cost_one
+                .unwrap_or(DEFAULT_TRANSPOSE_COST_ONE);
+            let max_distance = query_config
+                .distance
+                .unwrap_or(PARADE_GUC.default_fuzzy_distance.get() as u8);
 
             for field_name in &mut fuzzy_fields.iter() {
                 if let Ok(field) = schema.get_field(field_name) {
@@ -155,9 +268,361 @@ impl TantivyScanState {
             }
 
             // Construct the query using the lenient parser to tolerate minor errors in the input.
-            parser.parse_query_lenient(&query_config.query).0
+            // Phrases on fields without positions are left out of a leniently parsed query,
+            // which would silently match nothing, so they're reported instead.
+            let (query, errors) = parser.parse_query_lenient(&query_config.query);
+            for error in errors {
+                if let QueryParserError::FieldDoesNotHavePositionsIndexed(field_name) = error {
+                    Self::require_positions(schema, &field_name);
+                }
+            }
+            query
         };
 
         tantivy_query
     }
+
+    /// Searches for the query text as a phrase in each of the phrase fields. With a slop,
+    /// the terms of the phrase can be up to that many positions apart, but must still be
+    /// in order. With phrase_prefix, the last term of the phrase is matched as a prefix.
+    fn phrase_query(query_config: &SearchConfig, parade_index: &ParadeIndex) -> Box<dyn Query> {
+        if !query_config.fuzzy_fields.is_empty() || !query_config.regex_fields.is_empty() {
+            panic!("cannot search with phrase_fields and fuzzy_fields or regex_fields")
+        }
+
+        let schema = parade_index.schema();
+        let slop = query_config.slop.unwrap_or(0);
+        let phrase_prefix = query_config.phrase_prefix.unwrap_or(false);
+        let mut queries: Vec<Box<dyn Query>> = Vec::new();
+
+        for field_name in &query_config.phrase_fields {
+            let field = schema
+                .get_field(field_name)
+                .unwrap_or_else(|_| panic!("phrase field {field_name} does not exist"));
+            let mut analyzer = parade_index.query_analyzer(field_name).unwrap_or_else(|| {
+                panic!("phrase queries are only supported on text fields, {field_name} is not a text field")
+            });
+            Self::require_positions(&schema, field_name);
+
+            let mut terms: Vec<(usize, Term)> = vec![];
+            let mut token_stream = analyzer.token_stream(&query_config.query);
+            while token_stream.advance() {
+                let token = token_stream.token();
+                terms.push((token.position, Term::from_field_text(field, &token.text)));
+            }
+
+            let query: Box<dyn Query> = match terms.len() {
+                0 => continue,
+                _ if phrase_prefix => Box::new(PhrasePrefixQuery::new_with_offset(terms)),
+                1 => Box::new(TermQuery::new(
+                    terms.remove(0).1,
+                    IndexRecordOption::WithFreqs,
+                )),
+                _ => Box::new(PhraseQuery::new_with_offset_and_slop(terms, slop)),
+            };
+            queries.push(query);
+        }
+
+        match queries.len() {
+            0 => Box::new(EmptyQuery),
+            1 => queries.remove(0),
+            _ => Box::new(BooleanQuery::new(
+                queries.into_iter().map(|q| (Occur::Should, q)).collect(),
+            )),
+        }
+    }
+
+    /// Panics with an explanation if a field wasn't indexed with the positions that
+    /// phrase queries need.
+    fn require_positions(schema: &Schema, field_name: &str) {
+        let Ok(field) = schema.get_field(field_name) else {
+            return;
+        };
+
+        let indexing_options = match schema.get_field_entry(field).field_type() {
+            FieldType::Str(text_options) => text_options.get_indexing_options(),
+            FieldType::JsonObject(json_options) => json_options.get_text_indexing_options(),
+            _ => None,
+        };
+
+        if let Some(indexing_options) = indexing_options {
+            let record = indexing_options.index_option();
+            if !record.has_positions() {
+                panic!(
+                    "phrase queries on field {field_name} require positions, but it was indexed with record set to \"{}\", recreate the index with record set to \"position\" to search it for phrases",
+                    record.to_string()
+                )
+            }
+        }
+    }
+
+    /// Rewrites term queries on fields with query time synonyms into a disjunction of the
+    /// term and its synonyms. Boolean queries are rewritten recursively, and every other
+    /// kind of query is left as it is, so phrase queries don't match synonyms.
+    fn expand_synonyms(
+        query: Box<dyn Query>,
+        synonyms: &HashMap<Field, SynonymMap>,
+    ) -> Box<dyn Query> {
+        if synonyms.is_empty() {
+            return query;
+        }
+
+        if let Some(term_query) = query.downcast_ref::<TermQuery>() {
+            let term = term_query.term();
+            let expansions = term
+                .as_str()
+                .and_then(|text| synonyms.get(&term.field())?.get(text));
+
+            if let Some(expansions) = expansions {
+                let terms = std::iter::once(term.clone()).chain(
+                    expansions
+                        .iter()
+                        .map(|synonym| Term::from_field_text(term.field(), synonym)),
+                );
+                let clauses: Vec<(Occur, Box<dyn Query>)> = terms
+                    .map(|term| {
+                        let term_query = TermQuery::new(term, IndexRecordOption::WithFreqs);
+                        (Occur::Should, Box::new(term_query) as Box<dyn Query>)
+                    })
+                    .collect();
+                return Box::new(BooleanQuery::new(clauses));
+            }
+        } else if let Some(boolean_query) = query.downcast_ref::<BooleanQuery>() {
+            let clauses = boolean_query
+                .clauses()
+                .iter()
+                .map(|(occur, subquery)| {
+                    (
+                        *occur,
+                        Self::expand_synonyms(subquery.box_clone(), synonyms),
+                    )
+                })
+                .collect();
+            return Box::new(BooleanQuery::new(clauses));
+        }
+
+        query
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use crate::index_access::utils::{get_parade_index, SearchConfig};
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+
+    fn count(query: &str) -> Option<i64> {
+        Spi::get_one::<i64>(&format!("SELECT COUNT(*) FROM {query}")).unwrap()
+    }
+
+    #[pg_test]
+    fn test_phrase_query() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        assert_eq!(
+            count("one_republic_songs.search('right places', phrase_fields => 'lyrics')"),
+            Some(1)
+        );
+        assert_eq!(
+            count("one_republic_songs.search('places right', phrase_fields => 'lyrics')"),
+            Some(0)
+        );
+
+        // "all" and "moves" are two positions apart in "All the right moves".
+        assert_eq!(
+            count("one_republic_songs.search('all moves', phrase_fields => 'lyrics')"),
+            Some(0)
+        );
+        assert_eq!(
+            count("one_republic_songs.search('all moves', phrase_fields => 'lyrics', slop => 2)"),
+            Some(1)
+        );
+
+        assert_eq!(
+            count("one_republic_songs.search('right pla', phrase_fields => 'lyrics', phrase_prefix => true)"),
+            Some(1)
+        );
+        assert_eq!(
+            count("one_republic_songs.search('counting', phrase_fields => 'title,lyrics')"),
+            Some(1)
+        );
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "record set to \"basic\"")]
+    fn test_phrase_query_without_positions() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CALL paradedb.create_bm25(
+                index_name => 'basic_songs',
+                table_name => 'one_republic_songs',
+                key_field => 'song_id',
+                text_fields => '{lyrics: {record: "basic"}}'
+            );
+            "#,
+        )
+        .expect("failed to create index");
+
+        count("basic_songs.search('lyrics:\"right places\"')");
+    }
+
+    #[pg_test]
+    fn test_search_after() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let keys = |args: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT string_agg(song_id::text, ',') FROM one_republic_songs.rank('description:song', {args})"
+            ))
+            .expect("failed to rank query")
+            .unwrap()
+        };
+
+        // The cursor of the last result on the first page.
+        let cursor = Spi::get_one::<String>(
+            r#"
+            SELECT cursor
+            FROM one_republic_songs.rank('description:song', limit_rows => 3)
+            ORDER BY rank_bm25 ASC, song_id DESC
+            LIMIT 1
+            "#,
+        )
+        .expect("failed to rank query")
+        .unwrap();
+
+        assert_eq!(
+            keys(&format!("search_after => '{cursor}', limit_rows => 3")),
+            keys("offset_rows => 3, limit_rows => 3")
+        );
+        assert_eq!(
+            keys(&format!("search_after => '{cursor}'")),
+            keys("offset_rows => 3")
+        );
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_search_after_invalid_cursor() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        Spi::run("SELECT * FROM one_republic_songs.rank('description:song', search_after => '42')")
+            .unwrap();
+    }
+
+    #[pg_test]
+    fn test_scan_streams_results() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        let parade_index = get_parade_index("one_republic_songs_bm25_index");
+        let mut config = SearchConfig {
+            query: "description:song".to_string(),
+            index_name: "one_republic_songs_bm25_index".to_string(),
+            ..Default::default()
+        };
+
+        // Results are ordered by score unless the config opts out.
+        let scores: Vec<f32> = parade_index
+            .scan_state(&config)
+            .unwrap()
+            .scan()
+            .map(|(score, _)| score.bm25)
+            .collect();
+        assert_eq!(scores.len(), 7);
+        assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        config.order_by_score = Some(false);
+        let mut streamed: Vec<i64> = parade_index
+            .scan_state(&config)
+            .unwrap()
+            .scan()
+            .map(|(score, _)| score.key)
+            .collect();
+        streamed.sort();
+        assert_eq!(streamed, vec![1, 2, 3, 4, 5, 6, 7]);
+
+        config.limit_rows = Some(3);
+        assert_eq!(parade_index.scan_state(&config).unwrap().scan().count(), 3);
+
+        // A query on the index can stop reading results as soon as it has enough.
+        Spi::run("SET enable_seqscan = off").expect("failed to disable sequential scans");
+        let exists = Spi::get_one::<bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM one_republic_songs
+                WHERE one_republic_songs @@@ jsonb_build_object(
+                    'query', 'description:song',
+                    'schema_name', current_schema(),
+                    'index_name', 'one_republic_songs_bm25_index',
+                    'table_name', 'one_republic_songs',
+                    'key_field', 'song_id',
+                    'order_by_score', false
+                )
+            )
+            "#,
+        )
+        .expect("failed to search index")
+        .unwrap();
+        assert!(exists);
+    }
+
+    #[pg_test]
+    fn test_default_limit_rows() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        Spi::run("SET paradedb.pg_bm25.default_limit_rows = 2").expect("failed to set limit");
+        assert_eq!(
+            count("one_republic_songs.search('description:song')"),
+            Some(2)
+        );
+
+        // A limit_rows given to the search takes precedence over the default.
+        assert_eq!(
+            count("one_republic_songs.search('description:song', limit_rows => 5)"),
+            Some(5)
+        );
+        assert_eq!(
+            count("one_republic_songs.rank('description:song')"),
+            Some(2)
+        );
+
+        // The default only applies to the search functions, and not to the operator.
+        assert_eq!(
+            count(
+                r#"
+                one_republic_songs
+                WHERE one_republic_songs @@@ jsonb_build_object(
+                    'query', 'description:song',
+                    'schema_name', current_schema(),
+                    'index_name', 'one_republic_songs_bm25_index',
+                    'table_name', 'one_republic_songs',
+                    'key_field', 'song_id'
+                )
+                "#
+            ),
+            Some(7)
+        );
+    }
+
+    #[pg_test]
+    fn test_default_fuzzy_distance() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+
+        // "sng" is one edit away from "song".
+        let fuzzy_search =
+            "one_republic_songs.search('description:sng', fuzzy_fields => 'description')";
+        assert_eq!(count(fuzzy_search), Some(7));
+
+        Spi::run("SET paradedb.pg_bm25.default_fuzzy_distance = 0")
+            .expect("failed to set distance");
+        assert_eq!(count(fuzzy_search), Some(0));
+    }
 }
@@ -0,0 +1,100 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tantivy::{Opstamp, Searcher};
+
+use super::index::ParadeIndexError;
+
+/// The health of an index: how many documents it has, how they're split into segments, and
+/// how much space each part of the index takes up on disk.
+#[derive(Debug, Serialize)]
+pub struct IndexStats {
+    pub num_docs: u64,
+    pub num_deleted_docs: u64,
+    pub num_segments: usize,
+    /// The operation stamp of the last commit, which goes up with every document that's
+    /// added or deleted.
+    pub opstamp: Opstamp,
+    pub total_bytes: u64,
+    pub components: ComponentBytes,
+    pub fields: BTreeMap<String, FieldStats>,
+    pub segments: Vec<SegmentStats>,
+}
+
+/// The bytes on disk of each kind of file in an index.
+#[derive(Debug, Default, Serialize)]
+pub struct ComponentBytes {
+    pub postings: u64,
+    pub positions: u64,
+    pub term_dictionary: u64,
+    pub store: u64,
+    pub fast_fields: u64,
+    pub fieldnorms: u64,
+    pub deletes: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct FieldStats {
+    /// The number of terms in the field, summed over every segment. A term that's in more
+    /// than one segment is counted once for each of them.
+    pub num_terms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SegmentStats {
+    pub id: String,
+    pub num_docs: u32,
+    pub num_deleted_docs: u32,
+    pub bytes: u64,
+}
+
+impl IndexStats {
+    pub fn new(searcher: &Searcher, opstamp: Opstamp) -> Result<Self, ParadeIndexError> {
+        let schema = searcher.schema();
+        let mut components = ComponentBytes::default();
+        let mut fields: BTreeMap<String, FieldStats> = BTreeMap::new();
+        let mut segments = vec![];
+
+        for segment_reader in searcher.segment_readers() {
+            let space_usage = segment_reader.space_usage()?;
+            components.postings += space_usage.postings().total().get_bytes();
+            components.positions += space_usage.positions().total().get_bytes();
+            components.term_dictionary += space_usage.termdict().total().get_bytes();
+            components.store += space_usage.store().total().get_bytes();
+            components.fast_fields += space_usage.fast_fields().total().get_bytes();
+            components.fieldnorms += space_usage.fieldnorms().total().get_bytes();
+            components.deletes += space_usage.deletes().get_bytes();
+
+            for (field, field_entry) in schema.fields() {
+                if !field_entry.is_indexed() {
+                    continue;
+                }
+                let inverted_index = segment_reader.inverted_index(field)?;
+                fields
+                    .entry(field_entry.name().to_string())
+                    .or_default()
+                    .num_terms += inverted_index.terms().num_terms() as u64;
+            }
+
+            segments.push(SegmentStats {
+                id: segment_reader.segment_id().uuid_string(),
+                num_docs: segment_reader.num_docs(),
+                num_deleted_docs: segment_reader.num_deleted_docs(),
+                bytes: space_usage.total().get_bytes(),
+            });
+        }
+
+        Ok(Self {
+            num_docs: searcher.num_docs(),
+            num_deleted_docs: segments
+                .iter()
+                .map(|segment| segment.num_deleted_docs as u64)
+                .sum(),
+            num_segments: segments.len(),
+            opstamp,
+            total_bytes: segments.iter().map(|segment| segment.bytes).sum(),
+            components,
+            fields,
+            segments,
+        })
+    }
+}
@@ -0,0 +1,101 @@
+use tantivy::fastfield::AliveBitSet;
+use tantivy::query::{EnableScoring, Query, Scorer, Weight};
+use tantivy::{DocAddress, DocId, DocSet, Searcher, SegmentOrdinal, SegmentReader, TERMINATED};
+
+use super::score::ParadeIndexScore;
+
+/// Iterates over the documents that match a query one segment at a time, in the order
+/// they're stored in. Nothing is collected up front, so a scan that stops early, like one
+/// under a LIMIT or an EXISTS, only pays for the documents it actually reads.
+pub struct ScoreStream {
+    weight: Box<dyn Weight>,
+    key_field_name: String,
+    segment_readers: std::vec::IntoIter<(SegmentOrdinal, SegmentReader)>,
+    segment: Option<SegmentStream>,
+}
+
+struct SegmentStream {
+    ordinal: SegmentOrdinal,
+    scorer: Box<dyn Scorer>,
+    alive_bitset: Option<AliveBitSet>,
+    key: Box<dyn Fn(DocId) -> i64>,
+}
+
+impl ScoreStream {
+    pub fn new(searcher: &Searcher, query: &dyn Query, key_field_name: &str) -> Self {
+        let weight = query
+            .weight(EnableScoring::enabled_from_searcher(searcher))
+            .unwrap_or_else(|err| panic!("failed to prepare query for search: {err:?}"));
+
+        let segment_readers: Vec<(SegmentOrdinal, SegmentReader)> = searcher
+            .segment_readers()
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(ordinal, segment_reader)| (ordinal as SegmentOrdinal, segment_reader))
+            .collect();
+
+        Self {
+            weight,
+            key_field_name: key_field_name.to_string(),
+            segment_readers: segment_readers.into_iter(),
+            segment: None,
+        }
+    }
+
+    fn open_segment(
+        &self,
+        ordinal: SegmentOrdinal,
+        segment_reader: &SegmentReader,
+    ) -> SegmentStream {
+        let key_field_name = &self.key_field_name;
+        let key_field_reader = segment_reader
+            .fast_fields()
+            .i64(key_field_name)
+            .unwrap_or_else(|err| panic!("key field {key_field_name} is not a u64: {err:?}"))
+            .first_or_default_col(0);
+
+        SegmentStream {
+            ordinal,
+            scorer: self
+                .weight
+                .scorer(segment_reader, 1.0)
+                .unwrap_or_else(|err| panic!("failed to search segment: {err:?}")),
+            alive_bitset: segment_reader.alive_bitset().cloned(),
+            key: Box::new(move |doc| key_field_reader.get_val(doc)),
+        }
+    }
+}
+
+impl Iterator for ScoreStream {
+    type Item = (ParadeIndexScore, DocAddress);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(segment) = &mut self.segment {
+                let doc = segment.scorer.doc();
+                if doc != TERMINATED {
+                    let bm25 = segment.scorer.score();
+                    segment.scorer.advance();
+
+                    // Deleted documents stay in their segment until it's merged.
+                    let is_alive = segment
+                        .alive_bitset
+                        .as_ref()
+                        .map_or(true, |alive_bitset| alive_bitset.is_alive(doc));
+                    if is_alive {
+                        let score = ParadeIndexScore {
+                            bm25,
+                            key: (segment.key)(doc),
+                        };
+                        return Some((score, DocAddress::new(segment.ordinal, doc)));
+                    }
+                    continue;
+                }
+            }
+
+            let (ordinal, segment_reader) = self.segment_readers.next()?;
+            self.segment = Some(self.open_segment(ordinal, &segment_reader));
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use pgrx::pg_sys::AsPgCStr;
+use pgrx::*;
+use rustc_hash::FxHashSet;
+use std::ffi::CStr;
+use tantivy::DocAddress;
+
+use crate::index_access::utils::SearchConfig;
+use crate::parade_index::index::ParadeIndex;
+use crate::parade_index::score::ParadeIndexScore;
+use crate::parade_index::state::TantivyScanState;
+
+/// The search functions read documents straight out of Tantivy, which knows nothing about
+/// Postgres privileges or row-level security. Before returning anything to the caller, they
+/// need to make sure that the current user would have been able to read the same rows and
+/// columns by querying the table directly.
+pub struct HeapAccess {
+    relid: pg_sys::Oid,
+    qualified_table_name: String,
+    key_field: String,
+    rls_enabled: bool,
+}
+
+impl HeapAccess {
+    /// Looks up the table behind a search config and checks that the current user has SELECT
+    /// privileges on every column that the bm25 index was built over. Panics if they don't.
+    pub fn new(config: &SearchConfig, parade_index: &ParadeIndex) -> Self {
+        let SearchConfig {
+            schema_name,
+            table_name,
+            ..
+        } = config;
+
+        let relid = unsafe {
+            let namespace_oid = pg_sys::get_namespace_oid(schema_name.as_pg_cstr(), false);
+            pg_sys::get_relname_relid(table_name.as_pg_cstr(), namespace_oid)
+        };
+
+        if relid == pg_sys::InvalidOid {
+            panic!("could not find table {schema_name}.{table_name} for bm25 index");
+        }
+
+        Self::from_relid(relid, parade_index)
+    }
+
+    /// Like `new`, but for functions that only know the bm25 index, and so have to find the
+    /// indexed table through the catalog.
+    pub fn from_index(index_oid: pg_sys::Oid, parade_index: &ParadeIndex) -> Self {
+        let relid = unsafe { pg_sys::IndexGetRelation(index_oid, false) };
+        Self::from_relid(relid, parade_index)
+    }
+
+    fn from_relid(relid: pg_sys::Oid, parade_index: &ParadeIndex) -> Self {
+        let (schema_name, table_name) = unsafe {
+            let schema_name = pg_sys::get_namespace_name(pg_sys::get_rel_namespace(relid));
+            let table_name = pg_sys::get_rel_name(relid);
+            (
+                CStr::from_ptr(schema_name).to_string_lossy().into_owned(),
+                CStr::from_ptr(table_name).to_string_lossy().into_owned(),
+            )
+        };
+
+        let new_self = Self {
+            relid,
+            qualified_table_name: spi::quote_qualified_identifier(schema_name, table_name),
+            key_field: parade_index.key_field_name.clone(),
+            // Superusers, table owners and roles with BYPASSRLS are not subject to policies,
+            // in which case Postgres tells us RLS is not enabled for this user.
+            rls_enabled: unsafe { pg_sys::check_enable_rls(relid, pg_sys::InvalidOid, false) }
+                == pg_sys::CheckEnableRlsResult_RLS_ENABLED as i32,
+        };
+
+        new_self.check_select_privilege(parade_index.fields.keys());
+        new_self
+    }
+
+    /// Some functions return data aggregated over many rows, like terms from the index
+    /// vocabulary, which can't be rechecked against row-level security policies.
+    /// Those functions must refuse to run when policies apply to the current user.
+    pub fn deny_row_security(&self, function_name: &str) {
+        if self.rls_enabled {
+            panic!(
+                "{function_name} is not available on table {} because it has row level security enabled",
+                self.qualified_table_name
+            );
+        }
+    }
+
+    fn check_select_privilege<'a>(&self, column_names: impl Iterator<Item = &'a String>) {
+        let user_id = unsafe { pg_sys::GetUserId() };
+        let mode = pg_sys::ACL_SELECT as pg_sys::AclMode;
+
+        // SELECT on the table implies SELECT on all of its columns.
+        if unsafe { pg_sys::pg_class_aclcheck(self.relid, user_id, mode) }
+            == pg_sys::AclResult_ACLCHECK_OK
+        {
+            return;
+        }
+
+        let qualified_table_name = &self.qualified_table_name;
+        for column_name in column_names {
+            // Skip index fields that aren't user columns, like ctid.
+            let attnum = unsafe { pg_sys::get_attnum(self.relid, column_name.as_pg_cstr()) };
+            if attnum <= 0 {
+                continue;
+            }
+
+            if unsafe { pg_sys::pg_attribute_aclcheck(self.relid, attnum, user_id, mode) }
+                != pg_sys::AclResult_ACLCHECK_OK
+            {
+                panic!(
+                    "permission denied for column {column_name} of table {qualified_table_name}"
+                );
+            }
+        }
+    }
+
+    /// Returns the subset of keys that belong to rows the current user can see, or `None` if
+    /// every key is visible. Keys are only rechecked against the heap, which applies the
+    /// table's policies, if the table has row-level security enabled for this user. Rows that
+    /// have been deleted but not yet vacuumed out of the index are dropped by the recheck, but
+    /// are returned as-is when there's no recheck.
+    pub fn visible_keys(&self, keys: Vec<i64>) -> Option<FxHashSet<i64>> {
+        if !self.rls_enabled {
+            return None;
+        }
+
+        let query = format!(
+            "SELECT {key_field}::bigint FROM {table} WHERE {key_field} = ANY($1)",
+            key_field = spi::quote_identifier(&self.key_field),
+            table = self.qualified_table_name,
+        );
+
+        let visible = Spi::connect(|client| {
+            let tup_table = client.select(
+                &query,
+                None,
+                Some(vec![(PgBuiltInOids::INT8ARRAYOID.oid(), keys.into_datum())]),
+            )?;
+
+            let mut visible = FxHashSet::default();
+            for row in tup_table {
+                if let Some(key) = row[1].value::<i64>()? {
+                    visible.insert(key);
+                }
+            }
+            Ok::<_, spi::Error>(visible)
+        })
+        .unwrap_or_else(|err| panic!("error checking row visibility for bm25 search: {err:?}"));
+
+        Some(visible)
+    }
+
+    /// Runs the search in the scan state, and returns the results for the rows that the
+    /// current user is allowed to see.
+    ///
+    /// When results are rechecked, the ones that are filtered out would leave the search
+    /// short of its limit_rows. So the search asks Tantivy for more results than it needs,
+    /// and keeps asking for more until it has enough visible rows or runs out of matches.
+    /// The offset is applied after the recheck, so it only counts visible rows.
+    pub fn search(&self, scan_state: &mut TantivyScanState) -> Vec<(ParadeIndexScore, DocAddress)> {
+        if !self.rls_enabled {
+            return scan_state.search();
+        }
+
+        let SearchConfig {
+            limit_rows,
+            offset_rows,
+            ..
+        } = scan_state.config.clone();
+        let offset = offset_rows.unwrap_or(0);
+        let num_docs = (scan_state.searcher.num_docs() as usize).max(1);
+        let wanted = limit_rows.map(|limit| limit.saturating_add(offset));
+
+        // Without a limit, the first search returns every match.
+        let mut fetch = wanted.map_or(num_docs, |wanted| wanted.saturating_mul(2).min(num_docs));
+        scan_state.config.offset_rows = None;
+        let visible = loop {
+            scan_state.config.limit_rows = Some(fetch.max(1));
+            let results = scan_state.search();
+            let exhausted = results.len() < fetch || fetch >= num_docs;
+            let visible = self.filter(results, |(score, _)| score.key);
+
+            if exhausted || wanted.map_or(true, |wanted| visible.len() >= wanted) {
+                break visible;
+            }
+            fetch = fetch.saturating_mul(2).min(num_docs);
+        };
+        scan_state.config.limit_rows = limit_rows;
+        scan_state.config.offset_rows = offset_rows;
+
+        visible
+            .into_iter()
+            .skip(offset)
+            .take(limit_rows.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// Filters search results down to the rows that the current user is allowed to see.
+    pub fn filter<T>(&self, rows: Vec<T>, key: impl Fn(&T) -> i64) -> Vec<T> {
+        match self.visible_keys(rows.iter().map(&key).collect()) {
+            Some(visible) => rows
+                .into_iter()
+                .filter(|row| visible.contains(&key(row)))
+                .collect(),
+            None => rows,
+        }
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+
+    #[pg_test]
+    fn test_rank_respects_row_level_security() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CREATE ROLE bm25_rls_reader;
+            GRANT USAGE ON SCHEMA one_republic_songs TO bm25_rls_reader;
+            GRANT SELECT ON one_republic_songs TO bm25_rls_reader;
+            ALTER TABLE one_republic_songs ENABLE ROW LEVEL SECURITY;
+            CREATE POLICY native_only ON one_republic_songs FOR SELECT USING (album = 'Native');
+            SET ROLE bm25_rls_reader;
+            "#,
+        )
+        .expect("failed to set up row level security");
+
+        let count =
+            Spi::get_one::<i64>("SELECT COUNT(*) FROM one_republic_songs.rank('description:song')")
+                .expect("failed to rank query")
+                .unwrap();
+
+        Spi::run("RESET ROLE").expect("failed to reset role");
+        assert_eq!(count, 2);
+    }
+
+    #[pg_test]
+    fn test_rank_fills_limit_under_row_level_security() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CREATE ROLE bm25_rls_limit_reader;
+            GRANT USAGE ON SCHEMA one_republic_songs TO bm25_rls_limit_reader;
+            GRANT SELECT ON one_republic_songs TO bm25_rls_limit_reader;
+            ALTER TABLE one_republic_songs ENABLE ROW LEVEL SECURITY;
+            CREATE POLICY dreaming_only ON one_republic_songs FOR SELECT
+                USING (album = 'Dreaming Out Loud');
+            SET ROLE bm25_rls_limit_reader;
+            "#,
+        )
+        .expect("failed to set up row level security");
+
+        // Every song matches, but only two are visible, so the search has to keep going past
+        // the rows it filtered out to fill the limit.
+        let first = Spi::get_one::<i64>(
+            "SELECT COUNT(*) FROM one_republic_songs.rank('description:song', limit_rows => 2)",
+        )
+        .expect("failed to rank query")
+        .unwrap();
+        let second = Spi::get_one::<i32>(
+            "SELECT song_id::int FROM one_republic_songs.rank('description:song', limit_rows => 1, offset_rows => 1)",
+        )
+        .expect("failed to rank query")
+        .unwrap();
+
+        Spi::run("RESET ROLE").expect("failed to reset role");
+        assert_eq!(first, 2);
+        assert!(second == 4 || second == 7);
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_highlight_requires_column_privilege() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CREATE ROLE bm25_column_reader;
+            GRANT USAGE ON SCHEMA one_republic_songs TO bm25_column_reader;
+            GRANT SELECT (song_id, title) ON one_republic_songs TO bm25_column_reader;
+            SET ROLE bm25_column_reader;
+            "#,
+        )
+        .expect("failed to set up column privileges");
+
+        Spi::run(
+            "SELECT * FROM one_republic_songs.highlight('lyrics:im', highlight_field => 'lyrics')",
+        )
+        .unwrap();
+    }
+}
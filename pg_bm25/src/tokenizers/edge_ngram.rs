@@ -0,0 +1,219 @@
+use std::str::CharIndices;
+
+use tantivy::tokenizer::{Token, TokenStream, Tokenizer};
+use tantivy::TantivyError;
+
+/// Splits text into words on non-alphanumeric characters, like Tantivy's `SimpleTokenizer`,
+/// and then emits the leading `min_gram..=max_gram` characters of every word as tokens.
+/// All the prefixes of a word share the word's position, so phrase queries keep working.
+///
+/// Unlike the `ngram` tokenizer with `prefix_only`, which only produces prefixes of the
+/// whole input text, this produces prefixes of every word. This makes it useful for
+/// search-as-you-type, where the last word in the search box is usually incomplete.
+#[derive(Clone)]
+pub struct EdgeNgramTokenizer {
+    min_gram: usize,
+    max_gram: usize,
+    token: Token,
+}
+
+impl EdgeNgramTokenizer {
+    pub fn new(min_gram: usize, max_gram: usize) -> tantivy::Result<Self> {
+        if min_gram == 0 {
+            return Err(TantivyError::InvalidArgument(
+                "min_gram must be greater than 0".to_string(),
+            ));
+        }
+        if min_gram > max_gram {
+            return Err(TantivyError::InvalidArgument(
+                "min_gram must not be greater than max_gram".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            min_gram,
+            max_gram,
+            token: Token::default(),
+        })
+    }
+}
+
+impl Tokenizer for EdgeNgramTokenizer {
+    type TokenStream<'a> = EdgeNgramTokenStream<'a>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        self.token.reset();
+        EdgeNgramTokenStream {
+            chars: text.char_indices(),
+            min_gram: self.min_gram,
+            max_gram: self.max_gram,
+            word_start: 0,
+            gram_ends: Vec::new(),
+            next_gram: 0,
+            word_position: None,
+            text,
+            token: &mut self.token,
+        }
+    }
+}
+
+pub struct EdgeNgramTokenStream<'a> {
+    text: &'a str,
+    chars: CharIndices<'a>,
+    min_gram: usize,
+    max_gram: usize,
+    /// Byte offset of the start of the current word.
+    word_start: usize,
+    /// Byte offsets of the end of each gram of the current word, shortest first.
+    gram_ends: Vec<usize>,
+    /// Index into `gram_ends` of the next gram to emit.
+    next_gram: usize,
+    word_position: Option<usize>,
+    token: &'a mut Token,
+}
+
+impl<'a> EdgeNgramTokenStream<'a> {
+    /// Moves on to the next word that is long enough to produce at least one gram.
+    /// Returns false once the text is exhausted.
+    fn next_word(&mut self) -> bool {
+        loop {
+            // Skip ahead to the first character of the next word.
+            let (word_start, first_char) =
+                match self.chars.by_ref().find(|(_, c)| c.is_alphanumeric()) {
+                    Some(next) => next,
+                    None => return false,
+                };
+
+            self.word_start = word_start;
+            self.next_gram = 0;
+            self.gram_ends.clear();
+
+            let mut num_chars = 1;
+            let mut word_end = word_start + first_char.len_utf8();
+            loop {
+                if num_chars >= self.min_gram && num_chars <= self.max_gram {
+                    self.gram_ends.push(word_end);
+                }
+                match self.chars.next() {
+                    Some((offset, c)) if c.is_alphanumeric() => {
+                        num_chars += 1;
+                        word_end = offset + c.len_utf8();
+                    }
+                    _ => break,
+                }
+            }
+
+            // Words that are too short to produce any grams still take up a position.
+            self.word_position = Some(self.word_position.map_or(0, |pos| pos + 1));
+
+            if !self.gram_ends.is_empty() {
+                return true;
+            }
+        }
+    }
+}
+
+impl<'a> TokenStream for EdgeNgramTokenStream<'a> {
+    fn advance(&mut self) -> bool {
+        if self.next_gram >= self.gram_ends.len() && !self.next_word() {
+            return false;
+        }
+
+        let gram_end = self.gram_ends[self.next_gram];
+        self.next_gram += 1;
+
+        self.token.text.clear();
+        self.token
+            .text
+            .push_str(&self.text[self.word_start..gram_end]);
+        self.token.offset_from = self.word_start;
+        self.token.offset_to = gram_end;
+        self.token.position = self.word_position.unwrap_or(0);
+        self.token.position_length = 1;
+
+        true
+    }
+
+    fn token(&self) -> &Token {
+        self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        self.token
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::*;
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+    use tantivy::tokenizer::{Token, TokenStream, Tokenizer};
+
+    fn test_helper<T: Tokenizer>(tokenizer: &mut T, text: &str) -> Vec<Token> {
+        let mut token_stream = tokenizer.token_stream(text);
+        let mut tokens: Vec<Token> = vec![];
+        while token_stream.advance() {
+            tokens.push(token_stream.token().clone());
+        }
+        tokens
+    }
+
+    #[pg_test]
+    fn test_edge_ngram_tokenizer() {
+        let mut tokenizer = EdgeNgramTokenizer::new(2, 4).unwrap();
+        let tokens = test_helper(&mut tokenizer, "Hello, a wörld");
+        let texts: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+        assert_eq!(texts, vec!["He", "Hel", "Hell", "wö", "wör", "wörl"]);
+
+        let token = &tokens[4];
+        assert_eq!(token.offset_from, 9);
+        assert_eq!(token.offset_to, 13);
+        // "a" is too short to produce a gram, but still takes up position 1.
+        assert_eq!(token.position, 2);
+        assert_eq!(tokens[0].position, 0);
+    }
+
+    #[pg_test]
+    fn test_edge_ngram_tokenizer_with_empty_string() {
+        let mut tokenizer = EdgeNgramTokenizer::new(1, 3).unwrap();
+        assert_eq!(test_helper(&mut tokenizer, "").len(), 0);
+        assert_eq!(test_helper(&mut tokenizer, "  ,. ").len(), 0);
+    }
+
+    #[pg_test]
+    fn test_edge_ngram_tokenizer_with_invalid_grams() {
+        assert!(EdgeNgramTokenizer::new(0, 3).is_err());
+        assert!(EdgeNgramTokenizer::new(4, 3).is_err());
+    }
+
+    #[pg_test]
+    fn test_edge_ngram_search() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CALL paradedb.create_bm25(
+                index_name => 'edge_ngram_songs',
+                table_name => 'one_republic_songs',
+                key_field => 'song_id',
+                text_fields => '{title: {tokenizer: {type: "edge_ngram", min_gram: 2, max_gram: 10}}}'
+            );
+            "#,
+        )
+        .expect("failed to create index");
+
+        // Queries aren't split into prefixes, so they only match the words they're a
+        // prefix of, rather than every word that starts with the same letters.
+        let count = |query: &str| {
+            Spi::get_one::<i64>(&format!(
+                "SELECT COUNT(*) FROM edge_ngram_songs.search('{query}')"
+            ))
+            .expect("failed to search index")
+        };
+        assert_eq!(count("title:sta"), Some(2));
+        assert_eq!(count("title:stop"), Some(1));
+        assert_eq!(count("title:stops"), Some(0));
+    }
+}
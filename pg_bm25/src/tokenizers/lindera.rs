@@ -8,56 +8,299 @@
  *
  */
 
-use lindera_core::mode::Mode;
-use lindera_dictionary::{load_dictionary_from_config, DictionaryConfig, DictionaryKind};
+use crate::env::postgres_data_dir_path;
+use crate::parade_index::fields::{content_hash, LinderaMode, LinderaUserDictionary};
+use lindera_core::mode::{Mode, Penalty};
+use lindera_dictionary::{
+    load_dictionary_from_config, load_user_dictionary_from_config, DictionaryConfig,
+    DictionaryKind, UserDictionaryConfig,
+};
 use lindera_tokenizer::token::Token as LinderaToken;
 use lindera_tokenizer::tokenizer::Tokenizer as LinderaTokenizer;
 use once_cell::sync::Lazy;
+use pgrx::pg_sys::AsPgCStr;
+use pgrx::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tantivy::tokenizer::{Token, TokenStream, Tokenizer};
 
-static CMN_TOKENIZER: Lazy<LinderaTokenizer> = Lazy::new(|| {
-    let dictionary_config = DictionaryConfig {
-        kind: Some(DictionaryKind::CcCedict),
-        path: None,
-    };
-    let dictionary = load_dictionary_from_config(dictionary_config)
-        .expect("Lindera `CcCedict` dictionary must be present");
-    LinderaTokenizer::new(dictionary, None, Mode::Normal)
+static CMN_TOKENIZER: Lazy<Arc<LinderaTokenizer>> = Lazy::new(|| {
+    Arc::new(load_tokenizer(
+        DictionaryKind::CcCedict,
+        LinderaMode::Normal,
+        None,
+    ))
 });
 
-static JPN_TOKENIZER: Lazy<LinderaTokenizer> = Lazy::new(|| {
-    let dictionary_config = DictionaryConfig {
-        kind: Some(DictionaryKind::IPADIC),
-        path: None,
-    };
-    let dictionary = load_dictionary_from_config(dictionary_config)
-        .expect("Lindera `IPADIC` dictionary must be present");
-    LinderaTokenizer::new(dictionary, None, Mode::Normal)
+static JPN_TOKENIZER: Lazy<Arc<LinderaTokenizer>> = Lazy::new(|| {
+    Arc::new(load_tokenizer(
+        DictionaryKind::IPADIC,
+        LinderaMode::Normal,
+        None,
+    ))
+});
+
+static KOR_TOKENIZER: Lazy<Arc<LinderaTokenizer>> = Lazy::new(|| {
+    Arc::new(load_tokenizer(
+        DictionaryKind::KoDic,
+        LinderaMode::Normal,
+        None,
+    ))
 });
 
-static KOR_TOKENIZER: Lazy<LinderaTokenizer> = Lazy::new(|| {
+/// Tokenizers with a user dictionary or a non default mode, keyed by their options.
+/// Loading a dictionary is expensive, so every analyzer with the same options shares one.
+#[allow(clippy::type_complexity)]
+static CUSTOM_TOKENIZERS: Lazy<
+    Mutex<HashMap<(String, LinderaMode, Option<String>), Arc<LinderaTokenizer>>>,
+> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The directory, under the Postgres data directory, that user dictionaries read from
+/// tables are written to.
+pub const USER_DICTIONARY_DIRECTORY: &str = "paradedb/lindera";
+
+fn load_tokenizer(
+    kind: DictionaryKind,
+    mode: LinderaMode,
+    user_dictionary_path: Option<&Path>,
+) -> LinderaTokenizer {
     let dictionary_config = DictionaryConfig {
-        kind: Some(DictionaryKind::KoDic),
+        kind: Some(kind.clone()),
         path: None,
     };
     let dictionary = load_dictionary_from_config(dictionary_config)
-        .expect("Lindera `KoDic` dictionary must be present");
-    LinderaTokenizer::new(dictionary, None, Mode::Normal)
-});
+        .unwrap_or_else(|err| panic!("Lindera `{kind:?}` dictionary must be present: {err}"));
+
+    let user_dictionary = user_dictionary_path.map(|path| {
+        let user_dictionary_config = UserDictionaryConfig {
+            kind: Some(kind.clone()),
+            path: path.to_path_buf(),
+        };
+        load_user_dictionary_from_config(user_dictionary_config).unwrap_or_else(|err| {
+            panic!(
+                "could not load Lindera user dictionary {}: {err}",
+                path.display()
+            )
+        })
+    });
+
+    let mode = match mode {
+        LinderaMode::Normal => Mode::Normal,
+        LinderaMode::Decompose => Mode::Decompose(Penalty::default()),
+    };
+
+    LinderaTokenizer::new(dictionary, user_dictionary, mode)
+}
+
+fn custom_tokenizer(
+    kind: DictionaryKind,
+    mode: LinderaMode,
+    user_dictionary: Option<&LinderaUserDictionary>,
+) -> Arc<LinderaTokenizer> {
+    let user_dictionary_path = user_dictionary.map(|user_dictionary| match user_dictionary {
+        LinderaUserDictionary::Path(path) => user_dictionary_path(path),
+        LinderaUserDictionary::Table(table) => {
+            panic!("user dictionary table {table} must be resolved before it can be loaded")
+        }
+    });
+
+    let key = (
+        format!("{kind:?}"),
+        mode,
+        user_dictionary_path
+            .as_ref()
+            .map(|path| path.display().to_string()),
+    );
+
+    let mut tokenizers = CUSTOM_TOKENIZERS
+        .lock()
+        .expect("failed to lock Lindera tokenizers");
+    tokenizers
+        .entry(key)
+        .or_insert_with(|| Arc::new(load_tokenizer(kind, mode, user_dictionary_path.as_deref())))
+        .clone()
+}
+
+/// Reading a user dictionary from a file is like running COPY FROM a file, so it's limited to
+/// the same roles: superusers and members of pg_read_server_files. Dictionaries stored in a
+/// table are read with the privileges of the user creating the index instead.
+pub fn check_user_dictionary_privilege(path: &str) {
+    let allowed = unsafe {
+        let read_server_files = pg_sys::get_role_oid("pg_read_server_files".as_pg_cstr(), false);
+        pg_sys::superuser() || pg_sys::has_privs_of_role(pg_sys::GetUserId(), read_server_files)
+    };
+
+    if !allowed {
+        panic!(
+            "permission denied to read user dictionary file {path}, only superusers and members of pg_read_server_files can use a user dictionary file"
+        )
+    }
+}
+
+/// Resolves the path of a user dictionary against the Postgres data directory. Panics if the
+/// path points outside of it. This only keeps paths pointing where dictionaries are expected
+/// to be, it doesn't stop the files in the data directory from being read, which is why
+/// user dictionary files also require `check_user_dictionary_privilege`.
+pub fn user_dictionary_path(path: &str) -> PathBuf {
+    let data_dir = postgres_data_dir_path();
+    let resolved = data_dir.join(path);
+
+    let canonical_data_dir = fs::canonicalize(&data_dir).unwrap_or(data_dir);
+    let canonical = fs::canonicalize(&resolved).unwrap_or_else(|_| resolved.clone());
+    if !canonical.starts_with(&canonical_data_dir) {
+        panic!("user dictionary {path} must be inside the Postgres data directory")
+    }
+
+    resolved
+}
+
+/// Writes the entries of a user dictionary table to a CSV file under the data directory, and
+/// returns its path relative to the data directory. Files are named after their contents, so
+/// indexes with the same dictionary share a file.
+pub fn resolve_user_dictionary(table: &str) -> String {
+    let entries = Spi::connect(|client| {
+        // Casting to regclass checks that the table exists and quotes its name.
+        let relation = client
+            .select(
+                "SELECT $1::regclass::text",
+                None,
+                Some(vec![(PgBuiltInOids::TEXTOID.oid(), table.into_datum())]),
+            )?
+            .first()
+            .get_one::<String>()?
+            .unwrap_or_else(|| panic!("user dictionary table {table} does not exist"));
+
+        let tup_table = client.select(
+            &format!("SELECT surface, part_of_speech, reading FROM {relation}"),
+            None,
+            None,
+        )?;
+
+        let mut entries = vec![];
+        for row in tup_table {
+            let surface = row[1].value::<String>()?.unwrap_or_default();
+            let part_of_speech = row[2].value::<String>()?.unwrap_or_default();
+            let reading = row[3].value::<String>()?.unwrap_or_default();
+            if !surface.is_empty() {
+                entries.push([surface, part_of_speech, reading]);
+            }
+        }
+        Ok::<_, spi::Error>(entries)
+    })
+    .unwrap_or_else(|err| panic!("error reading user dictionary table {table}: {err:?}"));
+
+    let mut csv = String::new();
+    for entry in entries {
+        let fields: Vec<String> = entry.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+
+    let relative_path = format!(
+        "{USER_DICTIONARY_DIRECTORY}/{}.csv",
+        content_hash(csv.as_bytes())
+    );
+
+    let path = postgres_data_dir_path().join(&relative_path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .unwrap_or_else(|err| panic!("could not create directory {}: {err}", parent.display()));
+    }
+    fs::write(&path, csv)
+        .unwrap_or_else(|err| panic!("could not write user dictionary {}: {err}", path.display()));
+
+    relative_path
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct LinderaChineseTokenizer {
+    tokenizer: Arc<LinderaTokenizer>,
     token: Token,
 }
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct LinderaJapaneseTokenizer {
+    tokenizer: Arc<LinderaTokenizer>,
     token: Token,
 }
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct LinderaKoreanTokenizer {
+    tokenizer: Arc<LinderaTokenizer>,
     token: Token,
 }
 
+impl LinderaChineseTokenizer {
+    pub fn new(mode: LinderaMode, user_dictionary: Option<&LinderaUserDictionary>) -> Self {
+        match (mode, user_dictionary) {
+            (LinderaMode::Normal, None) => Self::default(),
+            _ => Self {
+                tokenizer: custom_tokenizer(DictionaryKind::CcCedict, mode, user_dictionary),
+                token: Token::default(),
+            },
+        }
+    }
+}
+
+impl Default for LinderaChineseTokenizer {
+    fn default() -> Self {
+        Self {
+            tokenizer: CMN_TOKENIZER.clone(),
+            token: Token::default(),
+        }
+    }
+}
+
+impl LinderaJapaneseTokenizer {
+    pub fn new(mode: LinderaMode, user_dictionary: Option<&LinderaUserDictionary>) -> Self {
+        match (mode, user_dictionary) {
+            (LinderaMode::Normal, None) => Self::default(),
+            _ => Self {
+                tokenizer: custom_tokenizer(DictionaryKind::IPADIC, mode, user_dictionary),
+                token: Token::default(),
+            },
+        }
+    }
+}
+
+impl Default for LinderaJapaneseTokenizer {
+    fn default() -> Self {
+        Self {
+            tokenizer: JPN_TOKENIZER.clone(),
+            token: Token::default(),
+        }
+    }
+}
+
+impl LinderaKoreanTokenizer {
+    pub fn new(mode: LinderaMode, user_dictionary: Option<&LinderaUserDictionary>) -> Self {
+        match (mode, user_dictionary) {
+            (LinderaMode::Normal, None) => Self::default(),
+            _ => Self {
+                tokenizer: custom_tokenizer(DictionaryKind::KoDic, mode, user_dictionary),
+                token: Token::default(),
+            },
+        }
+    }
+}
+
+impl Default for LinderaKoreanTokenizer {
+    fn default() -> Self {
+        Self {
+            tokenizer: KOR_TOKENIZER.clone(),
+            token: Token::default(),
+        }
+    }
+}
+
 impl Tokenizer for LinderaChineseTokenizer {
     type TokenStream<'a> = MultiLanguageTokenStream<'a>;
 
@@ -67,7 +310,8 @@ impl Tokenizer for LinderaChineseTokenizer {
         }
 
         let lindera_token_stream = LinderaTokenStream {
-            tokens: CMN_TOKENIZER
+            tokens: self
+                .tokenizer
                 .tokenize(text)
                 .expect("Lindera Chinese tokenizer failed"),
             token: &mut self.token,
@@ -86,7 +330,8 @@ impl Tokenizer for LinderaJapaneseTokenizer {
         }
 
         let lindera_token_stream = LinderaTokenStream {
-            tokens: JPN_TOKENIZER
+            tokens: self
+                .tokenizer
                 .tokenize(text)
                 .expect("Lindera Japanese tokenizer failed"),
             token: &mut self.token,
@@ -105,7 +350,8 @@ impl Tokenizer for LinderaKoreanTokenizer {
         }
 
         let lindera_token_stream = LinderaTokenStream {
-            tokens: KOR_TOKENIZER
+            tokens: self
+                .tokenizer
                 .tokenize(text)
                 .expect("Lindera Korean tokenizer failed"),
             token: &mut self.token,
@@ -244,6 +490,58 @@ mod tests {
         }
     }
 
+    #[pg_test]
+    fn test_japanese_tokenizer_with_user_dictionary() {
+        let text = "東京スカイツリーの最寄り駅はとうきょうスカイツリー駅です";
+
+        let mut tokenizer = LinderaJapaneseTokenizer::default();
+        let tokens = test_helper(&mut tokenizer, text);
+        assert_eq!(tokens[0].text, "東京");
+
+        Spi::run(
+            "CREATE TABLE product_terms (surface TEXT, part_of_speech TEXT, reading TEXT);
+            INSERT INTO product_terms VALUES ('東京スカイツリー', 'カスタム名詞', 'トウキョウスカイツリー');",
+        )
+        .expect("failed to create user dictionary table");
+
+        let path = resolve_user_dictionary("product_terms");
+        assert!(path.starts_with("paradedb/lindera/"));
+        assert!(user_dictionary_path(&path).is_file());
+
+        let mut tokenizer = LinderaJapaneseTokenizer::new(
+            LinderaMode::Normal,
+            Some(&LinderaUserDictionary::Path(path)),
+        );
+        let tokens = test_helper(&mut tokenizer, text);
+        assert_eq!(tokens[0].text, "東京スカイツリー");
+        assert_eq!(tokens[0].offset_to, 24);
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "permission denied to read user dictionary file")]
+    fn test_user_dictionary_file_requires_privilege() {
+        Spi::run(
+            "CREATE ROLE bm25_dictionary_reader;
+            SET ROLE bm25_dictionary_reader;",
+        )
+        .expect("failed to set role");
+
+        check_user_dictionary_privilege("postgresql.conf");
+    }
+
+    #[pg_test]
+    #[should_panic]
+    fn test_user_dictionary_outside_data_directory() {
+        user_dictionary_path("../../etc/passwd");
+    }
+
+    #[pg_test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
     #[pg_test]
     fn test_lindera_chinese_tokenizer_with_empty_string() {
         let mut tokenizer = LinderaChineseTokenizer::default();
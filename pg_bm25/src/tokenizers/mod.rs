@@ -1,28 +1,44 @@
 pub(crate) mod cjk;
 pub(crate) mod code;
+pub(crate) mod edge_ngram;
 #[cfg(feature = "icu")]
 pub(crate) mod icu;
 pub(crate) mod lindera;
+pub(crate) mod phonetic;
+pub(crate) mod synonyms;
 
-use crate::parade_index::fields::{ParadeOption, ParadeOptionMap, ParadeTokenizer};
-use crate::tokenizers::cjk::ChineseTokenizer;
-use crate::tokenizers::code::CodeTokenizer;
-use crate::tokenizers::lindera::{
-    LinderaChineseTokenizer, LinderaJapaneseTokenizer, LinderaKoreanTokenizer,
+use crate::parade_index::fields::{
+    ParadeOption, ParadeOptionMap, ParadeTextOptions, ParadeTokenizer,
 };
 use serde_json::json;
 use shared::plog;
 use tantivy::tokenizer::{
-    AsciiFoldingFilter, LowerCaser, NgramTokenizer, RawTokenizer, RemoveLongFilter, TextAnalyzer,
-    TokenizerManager,
+    LowerCaser, RawTokenizer, RemoveLongFilter, TextAnalyzer, TokenizerManager,
 };
 
-#[cfg(feature = "icu")]
-use crate::tokenizers::icu::ICUTokenizer;
-
 pub const DEFAULT_REMOVE_TOKEN_LENGTH: usize = 255;
 
 pub fn create_tokenizer_manager(option_map: &ParadeOptionMap) -> TokenizerManager {
+    build_tokenizer_manager(option_map, ParadeTextOptions::analyzer, |tokenizer| {
+        TextAnalyzer::from(tokenizer.clone())
+    })
+}
+
+/// The tokenizers used to parse queries, which only differ from the ones fields are indexed
+/// with for fields that need a different analyzer at query time.
+pub fn create_query_tokenizer_manager(option_map: &ParadeOptionMap) -> TokenizerManager {
+    build_tokenizer_manager(
+        option_map,
+        ParadeTextOptions::query_analyzer,
+        ParadeTokenizer::query_analyzer,
+    )
+}
+
+fn build_tokenizer_manager(
+    option_map: &ParadeOptionMap,
+    text_analyzer_for: fn(&ParadeTextOptions) -> TextAnalyzer,
+    json_analyzer_for: fn(&ParadeTokenizer) -> TextAnalyzer,
+) -> TokenizerManager {
     let tokenizer_manager = TokenizerManager::default();
 
     for (field_name, field_options) in option_map.iter() {
@@ -31,81 +47,26 @@ pub fn create_tokenizer_manager(option_map: &ParadeOptionMap) -> TokenizerManage
             json!({ "field_name": field_name, "field_options": field_options })
         );
 
-        let parade_tokenizer = match field_options {
-            ParadeOption::Text(text_options) => text_options.tokenizer,
-            ParadeOption::Json(json_options) => json_options.tokenizer,
-            _ => continue,
-        };
-
-        let tokenizer_option = match parade_tokenizer {
-            ParadeTokenizer::Raw => Some(
-                TextAnalyzer::builder(RawTokenizer::default())
-                    .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                    .build(),
+        let (tokenizer_name, text_analyzer) = match field_options {
+            ParadeOption::Text(text_options) => (
+                text_options.tokenizer_name(),
+                text_analyzer_for(text_options),
             ),
-            ParadeTokenizer::ChineseCompatible => Some(
-                TextAnalyzer::builder(ChineseTokenizer)
-                    .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                    .filter(LowerCaser)
-                    .build(),
+            ParadeOption::Json(json_options) => (
+                json_options.tokenizer.name(),
+                json_analyzer_for(&json_options.tokenizer),
             ),
-            ParadeTokenizer::SourceCode => Some(
-                TextAnalyzer::builder(CodeTokenizer::default())
-                    .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                    .filter(LowerCaser)
-                    .filter(AsciiFoldingFilter)
-                    .build(),
-            ),
-            ParadeTokenizer::Ngram {
-                min_gram,
-                max_gram,
-                prefix_only,
-            } => Some(
-                TextAnalyzer::builder(
-                    NgramTokenizer::new(min_gram, max_gram, prefix_only).unwrap(),
-                )
-                .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                .filter(LowerCaser)
-                .build(),
-            ),
-            ParadeTokenizer::ChineseLindera => Some(
-                TextAnalyzer::builder(LinderaChineseTokenizer::default())
-                    .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                    .filter(LowerCaser)
-                    .build(),
-            ),
-            ParadeTokenizer::JapaneseLindera => Some(
-                TextAnalyzer::builder(LinderaJapaneseTokenizer::default())
-                    .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                    .filter(LowerCaser)
-                    .build(),
-            ),
-            ParadeTokenizer::KoreanLindera => Some(
-                TextAnalyzer::builder(LinderaKoreanTokenizer::default())
-                    .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                    .filter(LowerCaser)
-                    .build(),
-            ),
-            #[cfg(feature = "icu")]
-            ParadeTokenizer::ICUTokenizer => Some(
-                TextAnalyzer::builder(ICUTokenizer)
-                    .filter(RemoveLongFilter::limit(DEFAULT_REMOVE_TOKEN_LENGTH))
-                    .filter(LowerCaser)
-                    .build(),
-            ),
-            _ => None,
+            _ => continue,
         };
 
-        if let Some(text_analyzer) = tokenizer_option {
-            plog!(
-                "registering tokenizer",
-                json!({
-                    "field_name": field_name,
-                    "tokenizer_name": &parade_tokenizer.name()
-                })
-            );
-            tokenizer_manager.register(&parade_tokenizer.name(), text_analyzer);
-        }
+        plog!(
+            "registering tokenizer",
+            json!({
+                "field_name": field_name,
+                "tokenizer_name": &tokenizer_name
+            })
+        );
+        tokenizer_manager.register(&tokenizer_name, text_analyzer);
     }
 
     tokenizer_manager
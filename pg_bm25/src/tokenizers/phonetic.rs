@@ -0,0 +1,1104 @@
+use serde::{Deserialize, Serialize};
+use tantivy::tokenizer::{Token, TokenFilter, TokenStream, Tokenizer};
+
+#[derive(Default, Copy, Clone, Deserialize, Debug, Serialize, PartialEq, Eq, Hash)]
+pub enum PhoneticAlgorithm {
+    /// American Soundex, which keeps the first letter of a word followed by three digits.
+    /// "Robert" and "Rupert" are both encoded as R163.
+    #[serde(rename = "soundex")]
+    Soundex,
+    /// The original Metaphone algorithm, which is more accurate than Soundex for English
+    /// names. "Catherine" and "Kathryn" are both encoded as K0RN.
+    #[serde(rename = "metaphone")]
+    #[default]
+    Metaphone,
+    /// Double Metaphone, which also accounts for words of Slavic, Germanic, Romance and
+    /// other origins. Words can have an alternate code besides their primary one, for the
+    /// other way they're commonly pronounced. "Smith" is encoded as SM0 and XMT, and
+    /// "Schmidt" as XMT and SMT.
+    #[serde(rename = "double_metaphone")]
+    DoubleMetaphone,
+}
+
+impl PhoneticAlgorithm {
+    pub fn name(&self) -> &str {
+        match self {
+            PhoneticAlgorithm::Soundex => "soundex",
+            PhoneticAlgorithm::Metaphone => "metaphone",
+            PhoneticAlgorithm::DoubleMetaphone => "double_metaphone",
+        }
+    }
+
+    /// Encodes a word, ignoring anything that isn't an ASCII letter. Returns the primary code
+    /// of the word followed by its alternate code, if it has a different one, or no codes if
+    /// the word doesn't contain any letters.
+    pub fn encode(&self, word: &str) -> Vec<String> {
+        let letters: Vec<u8> = word
+            .bytes()
+            .filter(u8::is_ascii_alphabetic)
+            .map(|byte| byte.to_ascii_uppercase())
+            .collect();
+
+        if letters.is_empty() {
+            return vec![];
+        }
+
+        let mut codes = match self {
+            PhoneticAlgorithm::Soundex => vec![soundex(&letters)],
+            PhoneticAlgorithm::Metaphone => vec![metaphone(&letters)],
+            PhoneticAlgorithm::DoubleMetaphone => {
+                let (primary, alternate) = DoubleMetaphone::encode(&letters);
+                vec![primary, alternate]
+            }
+        };
+        codes.retain(|code| !code.is_empty());
+        codes.dedup();
+        codes
+    }
+}
+
+fn soundex_digit(letter: u8) -> Option<u8> {
+    match letter {
+        b'B' | b'F' | b'P' | b'V' => Some(b'1'),
+        b'C' | b'G' | b'J' | b'K' | b'Q' | b'S' | b'X' | b'Z' => Some(b'2'),
+        b'D' | b'T' => Some(b'3'),
+        b'L' => Some(b'4'),
+        b'M' | b'N' => Some(b'5'),
+        b'R' => Some(b'6'),
+        _ => None,
+    }
+}
+
+fn soundex(letters: &[u8]) -> String {
+    let mut code = vec![letters[0]];
+    let mut last_digit = soundex_digit(letters[0]);
+
+    for &letter in &letters[1..] {
+        if code.len() == 4 {
+            break;
+        }
+        match letter {
+            // H and W don't separate letters with the same digit.
+            b'H' | b'W' => continue,
+            // Vowels do, so the same digit can appear again after one.
+            b'A' | b'E' | b'I' | b'O' | b'U' | b'Y' => last_digit = None,
+            _ => {
+                let digit = soundex_digit(letter);
+                if digit != last_digit {
+                    if let Some(digit) = digit {
+                        code.push(digit);
+                    }
+                }
+                last_digit = digit;
+            }
+        }
+    }
+
+    code.resize(4, b'0');
+    String::from_utf8(code).expect("soundex codes are ASCII")
+}
+
+fn is_vowel(letter: Option<&u8>) -> bool {
+    matches!(letter, Some(b'A' | b'E' | b'I' | b'O' | b'U'))
+}
+
+fn is_front_vowel(letter: Option<&u8>) -> bool {
+    matches!(letter, Some(b'E' | b'I' | b'Y'))
+}
+
+fn metaphone(letters: &[u8]) -> String {
+    // Some letter combinations at the start of a word have a silent first letter.
+    let mut word: Vec<u8> = match letters {
+        [b'A', b'E', ..] | [b'G' | b'K' | b'P', b'N', ..] | [b'W', b'R', ..] => {
+            letters[1..].to_vec()
+        }
+        [b'X', ..] => [b"S", &letters[1..]].concat(),
+        [b'W', b'H', ..] => [b"W", &letters[2..]].concat(),
+        _ => letters.to_vec(),
+    };
+    if word.is_empty() {
+        word = letters.to_vec();
+    }
+
+    let mut code = String::new();
+    let mut index = 0;
+    while index < word.len() {
+        let letter = word[index];
+        let prev = index.checked_sub(1).map(|prev| &word[prev]);
+        let next = word.get(index + 1);
+        let after_next = word.get(index + 2);
+        let is_last = index + 1 == word.len();
+
+        // Doubled letters are encoded once, except for C.
+        if prev == Some(&letter) && letter != b'C' {
+            index += 1;
+            continue;
+        }
+
+        match letter {
+            b'A' | b'E' | b'I' | b'O' | b'U' => {
+                if index == 0 {
+                    code.push(letter as char);
+                }
+            }
+            b'B' => {
+                // B is silent in a final MB, like "dumb".
+                if !(is_last && prev == Some(&b'M')) {
+                    code.push('B');
+                }
+            }
+            b'C' => {
+                if prev == Some(&b'S') && is_front_vowel(next) {
+                    // Silent in SCI, SCE and SCY.
+                } else if next == Some(&b'I') && after_next == Some(&b'A') {
+                    code.push('X');
+                } else if is_front_vowel(next) {
+                    code.push('S');
+                } else if next == Some(&b'H') {
+                    code.push(if prev == Some(&b'S') { 'K' } else { 'X' });
+                    index += 1;
+                } else {
+                    code.push('K');
+                }
+            }
+            b'D' => {
+                if next == Some(&b'G') && is_front_vowel(after_next) {
+                    code.push('J');
+                    index += 1;
+                } else {
+                    code.push('T');
+                }
+            }
+            b'G' => {
+                if next == Some(&b'H') && !(index + 2 == word.len() || is_vowel(after_next)) {
+                    // Silent in GH when it's not at the end or before a vowel, like "night".
+                } else if next == Some(&b'N')
+                    && (index + 2 == word.len() || word[index + 1..].starts_with(b"NED"))
+                {
+                    // Silent in a final GN or GNED, like "sign".
+                } else if is_front_vowel(next) && prev != Some(&b'G') {
+                    code.push('J');
+                } else {
+                    code.push('K');
+                }
+            }
+            b'H' => {
+                let after_consonant = matches!(prev, Some(b'C' | b'S' | b'P' | b'T' | b'G'));
+                if !after_consonant && is_vowel(next) {
+                    code.push('H');
+                }
+            }
+            b'K' => {
+                if prev != Some(&b'C') {
+                    code.push('K');
+                }
+            }
+            b'P' => code.push(if next == Some(&b'H') { 'F' } else { 'P' }),
+            b'Q' => code.push('K'),
+            b'S' => {
+                if next == Some(&b'H') {
+                    code.push('X');
+                    index += 1;
+                } else if next == Some(&b'I') && matches!(after_next, Some(b'O' | b'A')) {
+                    code.push('X');
+                } else {
+                    code.push('S');
+                }
+            }
+            b'T' => {
+                if next == Some(&b'I') && matches!(after_next, Some(b'O' | b'A')) {
+                    code.push('X');
+                } else if next == Some(&b'H') {
+                    code.push('0');
+                    index += 1;
+                } else if !(next == Some(&b'C') && after_next == Some(&b'H')) {
+                    code.push('T');
+                }
+            }
+            b'V' => code.push('F'),
+            b'W' | b'Y' => {
+                if is_vowel(next) {
+                    code.push(letter as char);
+                }
+            }
+            b'X' => code.push_str("KS"),
+            b'Z' => code.push('S'),
+            // F, J, L, M, N and R are encoded as themselves.
+            _ => code.push(letter as char),
+        }
+
+        index += 1;
+    }
+
+    code
+}
+
+/// The length that Double Metaphone codes are cut off at.
+const DOUBLE_METAPHONE_MAX_LENGTH: usize = 4;
+
+/// Lawrence Philips' Double Metaphone, as described in "The Double Metaphone Search
+/// Algorithm" (C/C++ Users Journal, June 2000). The rules that only apply to phrases,
+/// like "San Jacinto", are left out, because tokens are single words.
+struct DoubleMetaphone<'a> {
+    word: &'a [u8],
+    slavo_germanic: bool,
+    primary: String,
+    alternate: String,
+}
+
+impl<'a> DoubleMetaphone<'a> {
+    fn encode(word: &'a [u8]) -> (String, String) {
+        let mut encoder = DoubleMetaphone {
+            word,
+            slavo_germanic: word.contains(&b'W')
+                || word.contains(&b'K')
+                || word.windows(2).any(|window| window == b"CZ"),
+            primary: String::new(),
+            alternate: String::new(),
+        };
+
+        // The first letter is silent in these, like the K in "knight".
+        let mut index: isize = if encoder.contains(0, &["GN", "KN", "PN", "WR", "PS"]) {
+            1
+        } else {
+            0
+        };
+
+        while !encoder.is_complete() && index < encoder.len() {
+            index = match encoder.at(index) {
+                b'A' | b'E' | b'I' | b'O' | b'U' | b'Y' => {
+                    if index == 0 {
+                        encoder.push('A');
+                    }
+                    index + 1
+                }
+                b'B' => {
+                    encoder.push('P');
+                    encoder.skip_double(index, b'B')
+                }
+                b'C' => encoder.c(index),
+                b'D' => encoder.d(index),
+                b'F' => {
+                    encoder.push('F');
+                    encoder.skip_double(index, b'F')
+                }
+                b'G' => encoder.g(index),
+                b'H' => encoder.h(index),
+                b'J' => encoder.j(index),
+                b'K' => {
+                    encoder.push('K');
+                    encoder.skip_double(index, b'K')
+                }
+                b'L' => encoder.l(index),
+                b'M' => {
+                    encoder.push('M');
+                    if encoder.at(index + 1) == b'M'
+                        || (encoder.contains(index - 1, &["UMB"])
+                            && (index + 1 == encoder.len() - 1
+                                || encoder.contains(index + 2, &["ER"])))
+                    {
+                        index + 2
+                    } else {
+                        index + 1
+                    }
+                }
+                b'N' => {
+                    encoder.push('N');
+                    encoder.skip_double(index, b'N')
+                }
+                b'P' => encoder.p(index),
+                b'Q' => {
+                    encoder.push('K');
+                    encoder.skip_double(index, b'Q')
+                }
+                b'R' => encoder.r(index),
+                b'S' => encoder.s(index),
+                b'T' => encoder.t(index),
+                b'V' => {
+                    encoder.push('F');
+                    encoder.skip_double(index, b'V')
+                }
+                b'W' => encoder.w(index),
+                b'X' => encoder.x(index),
+                b'Z' => encoder.z(index),
+                _ => index + 1,
+            };
+        }
+
+        (encoder.primary, encoder.alternate)
+    }
+
+    fn len(&self) -> isize {
+        self.word.len() as isize
+    }
+
+    /// The letter at `index`, or 0 if it's outside of the word.
+    fn at(&self, index: isize) -> u8 {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.word.get(index))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn is_vowel_at(&self, index: isize) -> bool {
+        matches!(self.at(index), b'A' | b'E' | b'I' | b'O' | b'U' | b'Y')
+    }
+
+    /// Whether the word has one of `options` starting at `index`. The options must all have
+    /// the same length.
+    fn contains(&self, index: isize, options: &[&str]) -> bool {
+        let Ok(start) = usize::try_from(index) else {
+            return false;
+        };
+        options.iter().any(|option| {
+            self.word
+                .get(start..start + option.len())
+                .map_or(false, |letters| letters == option.as_bytes())
+        })
+    }
+
+    fn skip_double(&self, index: isize, letter: u8) -> isize {
+        if self.at(index + 1) == letter {
+            index + 2
+        } else {
+            index + 1
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.primary.len() >= DOUBLE_METAPHONE_MAX_LENGTH
+            && self.alternate.len() >= DOUBLE_METAPHONE_MAX_LENGTH
+    }
+
+    fn push(&mut self, sound: char) {
+        self.push_both(&sound.to_string(), &sound.to_string());
+    }
+
+    fn push_str(&mut self, sounds: &str) {
+        self.push_both(sounds, sounds);
+    }
+
+    fn push_both(&mut self, primary: &str, alternate: &str) {
+        self.push_primary(primary);
+        self.push_alternate(alternate);
+    }
+
+    fn push_primary(&mut self, sounds: &str) {
+        let room = DOUBLE_METAPHONE_MAX_LENGTH.saturating_sub(self.primary.len());
+        self.primary.extend(sounds.chars().take(room));
+    }
+
+    fn push_alternate(&mut self, sounds: &str) {
+        let room = DOUBLE_METAPHONE_MAX_LENGTH.saturating_sub(self.alternate.len());
+        self.alternate.extend(sounds.chars().take(room));
+    }
+
+    fn is_germanic(&self) -> bool {
+        self.contains(0, &["SCH"])
+    }
+
+    fn c(&mut self, index: isize) -> isize {
+        if self.c_is_k(index) {
+            // Various Germanic spellings, like "bacher" and "macher".
+            self.push('K');
+            index + 2
+        } else if index == 0 && self.contains(index, &["CAESAR"]) {
+            self.push('S');
+            index + 2
+        } else if self.contains(index, &["CH"]) {
+            self.ch(index)
+        } else if self.contains(index, &["CZ"]) && !self.contains(index - 2, &["WICZ"]) {
+            // "Czerny"
+            self.push_both("S", "X");
+            index + 2
+        } else if self.contains(index + 1, &["CIA"]) {
+            // "Focaccia"
+            self.push('X');
+            index + 3
+        } else if self.contains(index, &["CC"]) && !(index == 1 && self.at(0) == b'M') {
+            // A double C, but not "McClelland".
+            self.cc(index)
+        } else if self.contains(index, &["CK", "CG", "CQ"]) {
+            self.push('K');
+            index + 2
+        } else if self.contains(index, &["CI", "CE", "CY"]) {
+            // Italian and English pronunciations.
+            if self.contains(index, &["CIO", "CIE", "CIA"]) {
+                self.push_both("S", "X");
+            } else {
+                self.push('S');
+            }
+            index + 2
+        } else {
+            self.push('K');
+            if self.contains(index + 1, &["C", "K", "Q"])
+                && !self.contains(index + 1, &["CE", "CI"])
+            {
+                index + 2
+            } else {
+                index + 1
+            }
+        }
+    }
+
+    fn c_is_k(&self, index: isize) -> bool {
+        if self.contains(index, &["CHIA"]) {
+            true
+        } else if index <= 1 || self.is_vowel_at(index - 2) || !self.contains(index - 1, &["ACH"]) {
+            false
+        } else {
+            let after = self.at(index + 2);
+            (after != b'I' && after != b'E') || self.contains(index - 2, &["BACHER", "MACHER"])
+        }
+    }
+
+    fn cc(&mut self, index: isize) -> isize {
+        if self.contains(index + 2, &["I", "E", "H"]) && !self.contains(index + 2, &["HU"]) {
+            // "Bellocchio", but not "bacchus".
+            if (index == 1 && self.at(index - 1) == b'A')
+                || self.contains(index - 1, &["UCCEE", "UCCES"])
+            {
+                // "Accident", "accede", "succeed".
+                self.push_str("KS");
+            } else {
+                // "Bacci", "bertucci" and other Italian words.
+                self.push('X');
+            }
+            index + 3
+        } else {
+            self.push('K');
+            index + 2
+        }
+    }
+
+    fn ch(&mut self, index: isize) -> isize {
+        if index > 0 && self.contains(index, &["CHAE"]) {
+            // "Michael"
+            self.push_both("K", "X");
+        } else if index == 0
+            && (self.contains(index + 1, &["HARAC", "HARIS"])
+                || self.contains(index + 1, &["HOR", "HYM", "HIA", "HEM"]))
+            && !self.contains(0, &["CHORE"])
+        {
+            // Greek roots, like "chemistry" and "chorus".
+            self.push('K');
+        } else if self.is_germanic()
+            || self.contains(index - 2, &["ORCHES", "ARCHIT", "ORCHID"])
+            || self.contains(index + 2, &["T", "S"])
+            || ((index == 0 || self.contains(index - 1, &["A", "O", "U", "E"]))
+                && (self.contains(index + 2, &["L", "R", "N", "M", "B", "H", "F", "V", "W"])
+                    || index + 1 == self.len() - 1))
+        {
+            // Germanic, Greek and other words where CH sounds like KH.
+            self.push('K');
+        } else if index > 0 {
+            if self.contains(0, &["MC"]) {
+                self.push('K');
+            } else {
+                self.push_both("X", "K");
+            }
+        } else {
+            self.push('X');
+        }
+        index + 2
+    }
+
+    fn d(&mut self, index: isize) -> isize {
+        if self.contains(index, &["DG"]) {
+            if self.contains(index + 2, &["I", "E", "Y"]) {
+                // "Edge"
+                self.push('J');
+                index + 3
+            } else {
+                // "Edgar"
+                self.push_str("TK");
+                index + 2
+            }
+        } else if self.contains(index, &["DT", "DD"]) {
+            self.push('T');
+            index + 2
+        } else {
+            self.push('T');
+            index + 1
+        }
+    }
+
+    fn g(&mut self, index: isize) -> isize {
+        let next = self.at(index + 1);
+        if next == b'H' {
+            self.gh(index)
+        } else if next == b'N' {
+            if index == 1 && self.is_vowel_at(0) && !self.slavo_germanic {
+                self.push_both("KN", "N");
+            } else if !self.contains(index + 2, &["EY"])
+                && self.at(index + 1) != b'Y'
+                && !self.slavo_germanic
+            {
+                self.push_both("N", "KN");
+            } else {
+                self.push_str("KN");
+            }
+            index + 2
+        } else if self.contains(index + 1, &["LI"]) && !self.slavo_germanic {
+            // "Tagliaro"
+            self.push_both("KL", "L");
+            index + 2
+        } else if index == 0
+            && (next == b'Y'
+                || self.contains(
+                    index + 1,
+                    &[
+                        "ES", "EP", "EB", "EL", "EY", "IB", "IL", "IN", "IE", "EI", "ER",
+                    ],
+                ))
+        {
+            // -ges-, -gep-, -gel- and -gie- at the start of a word.
+            self.push_both("K", "J");
+            index + 2
+        } else if (self.contains(index + 1, &["ER"]) || next == b'Y')
+            && !self.contains(0, &["DANGER", "RANGER", "MANGER"])
+            && !self.contains(index - 1, &["E", "I"])
+            && !self.contains(index - 1, &["RGY", "OGY"])
+        {
+            // -ger- and -gy-
+            self.push_both("K", "J");
+            index + 2
+        } else if self.contains(index + 1, &["E", "I", "Y"])
+            || self.contains(index - 1, &["AGGI", "OGGI"])
+        {
+            // Italian, like "biaggi".
+            if self.is_germanic() || self.contains(index + 1, &["ET"]) {
+                self.push('K');
+            } else if self.contains(index + 1, &["IER"]) {
+                self.push('J');
+            } else {
+                self.push_both("J", "K");
+            }
+            index + 2
+        } else {
+            self.push('K');
+            self.skip_double(index, b'G')
+        }
+    }
+
+    fn gh(&mut self, index: isize) -> isize {
+        if index > 0 && !self.is_vowel_at(index - 1) {
+            self.push('K');
+        } else if index == 0 {
+            // "Ghislane" and "ghiradelli"
+            if self.at(index + 2) == b'I' {
+                self.push('J');
+            } else {
+                self.push('K');
+            }
+        } else if (index > 1 && self.contains(index - 2, &["B", "H", "D"]))
+            || (index > 2 && self.contains(index - 3, &["B", "H", "D"]))
+            || (index > 3 && self.contains(index - 4, &["B", "H"]))
+        {
+            // Parker's rule, like "hugh", "bough" and "broughton".
+        } else if index > 2
+            && self.at(index - 1) == b'U'
+            && self.contains(index - 3, &["C", "G", "L", "R", "T"])
+        {
+            // "Laugh", "McLaughlin", "cough", "gough", "rough" and "tough".
+            self.push('F');
+        } else if index > 0 && self.at(index - 1) != b'I' {
+            self.push('K');
+        }
+        index + 2
+    }
+
+    fn h(&mut self, index: isize) -> isize {
+        // H is only kept at the start of a word or between vowels, and before a vowel.
+        if (index == 0 || self.is_vowel_at(index - 1)) && self.is_vowel_at(index + 1) {
+            self.push('H');
+            index + 2
+        } else {
+            index + 1
+        }
+    }
+
+    fn j(&mut self, index: isize) -> isize {
+        if self.contains(index, &["JOSE"]) {
+            // Spanish, like "Jose".
+            if self.len() == 4 {
+                self.push('H');
+            } else {
+                self.push_both("J", "H");
+            }
+            return index + 1;
+        }
+
+        if index == 0 {
+            self.push_both("J", "A");
+        } else if self.is_vowel_at(index - 1)
+            && !self.slavo_germanic
+            && matches!(self.at(index + 1), b'A' | b'O')
+        {
+            self.push_both("J", "H");
+        } else if index == self.len() - 1 {
+            self.push_primary("J");
+        } else if !self.contains(index + 1, &["L", "T", "K", "S", "N", "M", "B", "Z"])
+            && !self.contains(index - 1, &["S", "K", "L"])
+        {
+            self.push('J');
+        }
+        self.skip_double(index, b'J')
+    }
+
+    fn l(&mut self, index: isize) -> isize {
+        if self.at(index + 1) != b'L' {
+            self.push('L');
+            return index + 1;
+        }
+
+        // Spanish, like "cabrillo" and "gallegos", where LL sounds like Y.
+        let len = self.len();
+        let spanish = (index == len - 3 && self.contains(index - 1, &["ILLO", "ILLA", "ALLE"]))
+            || ((self.contains(len - 2, &["AS", "OS"]) || self.contains(len - 1, &["A", "O"]))
+                && self.contains(index - 1, &["ALLE"]));
+        if spanish {
+            self.push_primary("L");
+        } else {
+            self.push('L');
+        }
+        index + 2
+    }
+
+    fn p(&mut self, index: isize) -> isize {
+        if self.at(index + 1) == b'H' {
+            self.push('F');
+            index + 2
+        } else {
+            self.push('P');
+            if self.contains(index + 1, &["P", "B"]) {
+                index + 2
+            } else {
+                index + 1
+            }
+        }
+    }
+
+    fn r(&mut self, index: isize) -> isize {
+        // French, like "rogier", where the final R is silent.
+        if index == self.len() - 1
+            && !self.slavo_germanic
+            && self.contains(index - 2, &["IE"])
+            && !self.contains(index - 4, &["ME", "MA"])
+        {
+            self.push_alternate("R");
+        } else {
+            self.push('R');
+        }
+        self.skip_double(index, b'R')
+    }
+
+    fn s(&mut self, index: isize) -> isize {
+        if self.contains(index - 1, &["ISL", "YSL"]) {
+            // "Island", "isle", "carlisle" and "carlysle".
+            index + 1
+        } else if index == 0 && self.contains(index, &["SUGAR"]) {
+            self.push_both("X", "S");
+            index + 1
+        } else if self.contains(index, &["SH"]) {
+            // Germanic, like "holm" and "holz".
+            if self.contains(index + 1, &["HEIM", "HOEK", "HOLM", "HOLZ"]) {
+                self.push('S');
+            } else {
+                self.push('X');
+            }
+            index + 2
+        } else if self.contains(index, &["SIO", "SIA"]) {
+            // Italian and Armenian.
+            if self.slavo_germanic {
+                self.push('S');
+            } else {
+                self.push_both("S", "X");
+            }
+            index + 3
+        } else if (index == 0 && self.contains(index + 1, &["M", "N", "L", "W"]))
+            || self.contains(index + 1, &["Z"])
+        {
+            // German and anglicized spellings, so "Smith" matches "Schmidt" and "Snider"
+            // matches "Schneider". Also -sz- in Slavic languages.
+            self.push_both("S", "X");
+            self.skip_double(index, b'Z')
+        } else if self.contains(index, &["SC"]) {
+            self.sc(index)
+        } else {
+            // French, like "resnais" and "artois".
+            if index == self.len() - 1 && self.contains(index - 2, &["AI", "OI"]) {
+                self.push_alternate("S");
+            } else {
+                self.push('S');
+            }
+            if self.contains(index + 1, &["S", "Z"]) {
+                index + 2
+            } else {
+                index + 1
+            }
+        }
+    }
+
+    fn sc(&mut self, index: isize) -> isize {
+        if self.at(index + 2) == b'H' {
+            if self.contains(index + 3, &["OO", "ER", "EN", "UY", "ED", "EM"]) {
+                // Dutch, like "school" and "schooner".
+                if self.contains(index + 3, &["ER", "EN"]) {
+                    // "Schermerhorn" and "schenker".
+                    self.push_both("X", "SK");
+                } else {
+                    self.push_str("SK");
+                }
+            } else if index == 0 && !self.is_vowel_at(3) && self.at(3) != b'W' {
+                self.push_both("X", "S");
+            } else {
+                self.push('X');
+            }
+        } else if self.contains(index + 2, &["I", "E", "Y"]) {
+            self.push('S');
+        } else {
+            self.push_str("SK");
+        }
+        index + 3
+    }
+
+    fn t(&mut self, index: isize) -> isize {
+        if self.contains(index, &["TION"]) || self.contains(index, &["TIA", "TCH"]) {
+            self.push('X');
+            index + 3
+        } else if self.contains(index, &["TH"]) || self.contains(index, &["TTH"]) {
+            // "Thomas", "thames" and Germanic words.
+            if self.contains(index + 2, &["OM", "AM"]) || self.is_germanic() {
+                self.push('T');
+            } else {
+                self.push_both("0", "T");
+            }
+            index + 2
+        } else {
+            self.push('T');
+            if self.contains(index + 1, &["T", "D"]) {
+                index + 2
+            } else {
+                index + 1
+            }
+        }
+    }
+
+    fn w(&mut self, index: isize) -> isize {
+        if self.contains(index, &["WR"]) {
+            self.push('R');
+            index + 2
+        } else if index == 0 && (self.is_vowel_at(index + 1) || self.contains(index, &["WH"])) {
+            // "Wasserman" should match "Vasserman", and "Uomo" should match "Womo".
+            if self.is_vowel_at(index + 1) {
+                self.push_both("A", "F");
+            } else {
+                self.push('A');
+            }
+            index + 1
+        } else if (index == self.len() - 1 && self.is_vowel_at(index - 1))
+            || self.contains(index - 1, &["EWSKI", "EWSKY", "OWSKI", "OWSKY"])
+            || self.is_germanic()
+        {
+            // "Arnow" should match "Arnoff".
+            self.push_alternate("F");
+            index + 1
+        } else if self.contains(index, &["WICZ", "WITZ"]) {
+            // Polish, like "filipowicz".
+            self.push_both("TS", "FX");
+            index + 4
+        } else {
+            index + 1
+        }
+    }
+
+    fn x(&mut self, index: isize) -> isize {
+        if index == 0 {
+            self.push('S');
+            return index + 1;
+        }
+
+        // French, like "breaux".
+        let silent = index == self.len() - 1
+            && (self.contains(index - 3, &["IAU", "EAU"])
+                || self.contains(index - 2, &["AU", "OU"]));
+        if !silent {
+            self.push_str("KS");
+        }
+        if self.contains(index + 1, &["C", "X"]) {
+            index + 2
+        } else {
+            index + 1
+        }
+    }
+
+    fn z(&mut self, index: isize) -> isize {
+        if self.at(index + 1) == b'H' {
+            // Chinese pinyin, like "Zhao".
+            self.push('J');
+            return index + 2;
+        }
+
+        if self.contains(index + 1, &["ZO", "ZI", "ZA"])
+            || (self.slavo_germanic && index > 0 && self.at(index - 1) != b'T')
+        {
+            self.push_both("S", "TS");
+        } else {
+            self.push('S');
+        }
+        self.skip_double(index, b'Z')
+    }
+}
+
+/// A token filter that replaces tokens with their phonetic codes, or adds the codes at the
+/// same position as the token if `keep_original` is set. Tokens without any letters to
+/// encode, like numbers, are left as they are.
+#[derive(Clone)]
+pub struct PhoneticFilter {
+    algorithm: PhoneticAlgorithm,
+    keep_original: bool,
+    primary_only: bool,
+}
+
+impl PhoneticFilter {
+    pub fn new(algorithm: PhoneticAlgorithm, keep_original: bool) -> Self {
+        Self {
+            algorithm,
+            keep_original,
+            primary_only: false,
+        }
+    }
+
+    /// The filter for query terms, which are replaced by their primary code only. Query
+    /// tokens at the same position would have to all match, so an alternate code would
+    /// only narrow the search. The primary code already matches every word that has it as
+    /// either of its codes.
+    pub fn for_query(algorithm: PhoneticAlgorithm) -> Self {
+        Self {
+            algorithm,
+            keep_original: false,
+            primary_only: true,
+        }
+    }
+}
+
+impl TokenFilter for PhoneticFilter {
+    type Tokenizer<T: Tokenizer> = PhoneticFilterWrapper<T>;
+
+    fn transform<T: Tokenizer>(self, tokenizer: T) -> Self::Tokenizer<T> {
+        PhoneticFilterWrapper {
+            tokenizer,
+            algorithm: self.algorithm,
+            keep_original: self.keep_original,
+            primary_only: self.primary_only,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PhoneticFilterWrapper<T> {
+    tokenizer: T,
+    algorithm: PhoneticAlgorithm,
+    keep_original: bool,
+    primary_only: bool,
+}
+
+impl<T: Tokenizer> Tokenizer for PhoneticFilterWrapper<T> {
+    type TokenStream<'a> = PhoneticTokenStream<T::TokenStream<'a>>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        PhoneticTokenStream {
+            tail: self.tokenizer.token_stream(text),
+            algorithm: self.algorithm,
+            keep_original: self.keep_original,
+            primary_only: self.primary_only,
+            pending: vec![],
+            token: Token::default(),
+        }
+    }
+}
+
+pub struct PhoneticTokenStream<T> {
+    tail: T,
+    algorithm: PhoneticAlgorithm,
+    keep_original: bool,
+    primary_only: bool,
+    /// The codes of the current token that are still to be emitted after it, in reverse.
+    pending: Vec<String>,
+    token: Token,
+}
+
+impl<T: TokenStream> TokenStream for PhoneticTokenStream<T> {
+    fn advance(&mut self) -> bool {
+        if let Some(code) = self.pending.pop() {
+            self.token.text = code;
+            return true;
+        }
+
+        if !self.tail.advance() {
+            return false;
+        }
+
+        self.token = self.tail.token().clone();
+        let mut codes = self.algorithm.encode(&self.token.text);
+        if self.primary_only {
+            codes.truncate(1);
+        }
+        if self.keep_original {
+            codes.retain(|code| *code != self.token.text);
+        } else if !codes.is_empty() {
+            self.token.text = codes.remove(0);
+        }
+        codes.reverse();
+        self.pending = codes;
+        true
+    }
+
+    fn token(&self) -> &Token {
+        &self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::*;
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+    use tantivy::tokenizer::{LowerCaser, SimpleTokenizer, TextAnalyzer};
+
+    #[pg_test]
+    fn test_soundex() {
+        let soundex = PhoneticAlgorithm::Soundex;
+        assert_eq!(soundex.encode("Robert"), vec!["R163"]);
+        assert_eq!(soundex.encode("Rupert"), vec!["R163"]);
+        assert_eq!(soundex.encode("Ashcraft"), vec!["A261"]);
+        assert_eq!(soundex.encode("Tymczak"), vec!["T522"]);
+        assert_eq!(soundex.encode("Lee"), vec!["L000"]);
+        assert_eq!(soundex.encode("Smith"), soundex.encode("Smyth"));
+        assert!(soundex.encode("1234").is_empty());
+    }
+
+    #[pg_test]
+    fn test_metaphone() {
+        let metaphone = PhoneticAlgorithm::Metaphone;
+        assert_eq!(metaphone.encode("Catherine"), vec!["K0RN"]);
+        assert_eq!(metaphone.encode("Kathryn"), vec!["K0RN"]);
+        assert_eq!(metaphone.encode("Smith"), vec!["SM0"]);
+        assert_eq!(metaphone.encode("Smyth"), vec!["SM0"]);
+        assert_eq!(metaphone.encode("Knight"), vec!["NT"]);
+        assert_eq!(metaphone.encode("Philip"), vec!["FLP"]);
+        assert_eq!(metaphone.encode("Xavier"), vec!["SFR"]);
+        assert!(metaphone.encode("1234").is_empty());
+    }
+
+    #[pg_test]
+    fn test_double_metaphone() {
+        let double_metaphone = PhoneticAlgorithm::DoubleMetaphone;
+        assert_eq!(double_metaphone.encode("Smith"), vec!["SM0", "XMT"]);
+        assert_eq!(double_metaphone.encode("Schmidt"), vec!["XMT", "SMT"]);
+        assert_eq!(double_metaphone.encode("Catherine"), vec!["K0RN", "KTRN"]);
+        assert_eq!(double_metaphone.encode("Kathryn"), vec!["K0RN", "KTRN"]);
+        assert_eq!(double_metaphone.encode("Knight"), vec!["NT"]);
+        assert_eq!(double_metaphone.encode("Jose"), vec!["HS"]);
+        assert!(double_metaphone.encode("1234").is_empty());
+    }
+
+    #[pg_test]
+    fn test_double_metaphone_filter() {
+        let mut analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(PhoneticFilter::new(
+                PhoneticAlgorithm::DoubleMetaphone,
+                false,
+            ))
+            .build();
+
+        let mut tokens: Vec<(String, usize)> = vec![];
+        let mut token_stream = analyzer.token_stream("Smith 42");
+        while token_stream.advance() {
+            let token = token_stream.token();
+            tokens.push((token.text.clone(), token.position));
+        }
+        assert_eq!(
+            tokens,
+            vec![
+                ("SM0".to_string(), 0),
+                ("XMT".to_string(), 0),
+                ("42".to_string(), 1),
+            ]
+        );
+
+        let mut analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(PhoneticFilter::for_query(
+                PhoneticAlgorithm::DoubleMetaphone,
+            ))
+            .build();
+        let mut token_stream = analyzer.token_stream("Schmidt");
+        let mut tokens = vec![];
+        while token_stream.advance() {
+            tokens.push(token_stream.token().text.clone());
+        }
+        assert_eq!(tokens, vec!["XMT".to_string()]);
+    }
+
+    #[pg_test]
+    fn test_phonetic_filter() {
+        let mut analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(LowerCaser)
+            .filter(PhoneticFilter::new(PhoneticAlgorithm::Metaphone, true))
+            .build();
+
+        let mut tokens: Vec<(String, usize)> = vec![];
+        let mut token_stream = analyzer.token_stream("Jane Smyth 42");
+        while token_stream.advance() {
+            let token = token_stream.token();
+            tokens.push((token.text.clone(), token.position));
+        }
+
+        assert_eq!(
+            tokens,
+            vec![
+                ("jane".to_string(), 0),
+                ("JN".to_string(), 0),
+                ("smyth".to_string(), 1),
+                ("SM0".to_string(), 1),
+                ("42".to_string(), 2),
+            ]
+        );
+    }
+
+    #[pg_test]
+    fn test_phonetic_search() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CALL paradedb.create_bm25(
+                index_name => 'phonetic_songs',
+                table_name => 'one_republic_songs',
+                key_field => 'song_id',
+                text_fields => '{title: {filters: [{type: "lowercase"}, {type: "phonetic", algorithm: "metaphone", keep_original: true}]}}'
+            );
+            "#,
+        )
+        .expect("failed to create index with phonetic filter");
+
+        let count =
+            Spi::get_one::<i64>("SELECT COUNT(*) FROM phonetic_songs.search('title:apologise')")
+                .unwrap();
+        assert_eq!(count, Some(1));
+
+        // The original tokens are kept, so they can still be looked up as is.
+        let count = Spi::get_one::<i64>(
+            "SELECT COUNT(*) FROM paradedb.autocomplete_bm25('phonetic_songs', 'title', 'apolog')",
+        )
+        .unwrap();
+        assert_eq!(count, Some(1));
+    }
+}
@@ -0,0 +1,272 @@
+use pgrx::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tantivy::tokenizer::{TextAnalyzer, Token, TokenFilter, TokenStream, Tokenizer};
+
+/// Maps every term in a synonym set to the other terms in the same set.
+/// Terms are stored the way the field's tokenizer produces them, so that they can be
+/// compared directly against tokens and index terms.
+#[derive(Clone, Debug, Default)]
+pub struct SynonymMap {
+    synonyms: HashMap<String, Vec<String>>,
+}
+
+impl SynonymMap {
+    /// Builds a map from synonym sets, running each entry through the field's analyzer.
+    /// Entries that don't tokenize to exactly one term, like multi-word phrases, are skipped.
+    pub fn new(sets: &[Vec<String>], analyzer: &mut TextAnalyzer) -> Self {
+        let mut synonyms: HashMap<String, Vec<String>> = HashMap::new();
+
+        for set in sets {
+            let mut terms: Vec<String> = set
+                .iter()
+                .filter_map(|entry| {
+                    let mut token_stream = analyzer.token_stream(entry);
+                    let mut tokens = vec![];
+                    while token_stream.advance() {
+                        tokens.push(token_stream.token().text.clone());
+                    }
+                    match tokens.len() {
+                        1 => tokens.pop(),
+                        _ => None,
+                    }
+                })
+                .collect();
+            terms.sort();
+            terms.dedup();
+
+            for term in &terms {
+                let entry = synonyms.entry(term.clone()).or_default();
+                for synonym in &terms {
+                    if synonym != term && !entry.contains(synonym) {
+                        entry.push(synonym.clone());
+                    }
+                }
+            }
+        }
+
+        Self { synonyms }
+    }
+
+    pub fn get(&self, term: &str) -> Option<&[String]> {
+        self.synonyms
+            .get(term)
+            .map(|synonyms| synonyms.as_slice())
+            .filter(|synonyms| !synonyms.is_empty())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.synonyms.is_empty()
+    }
+}
+
+/// Reads the synonym sets of a group from the `paradedb.synonyms` table.
+pub fn load_synonym_group(group_name: &str) -> Vec<Vec<String>> {
+    Spi::connect(|client| {
+        let tup_table = client.select(
+            "SELECT terms FROM paradedb.synonyms WHERE group_name = $1",
+            None,
+            Some(vec![(
+                PgBuiltInOids::TEXTOID.oid(),
+                group_name.into_datum(),
+            )]),
+        )?;
+
+        let mut sets = vec![];
+        for row in tup_table {
+            if let Some(terms) = row[1].value::<Vec<Option<String>>>()? {
+                sets.push(terms.into_iter().flatten().collect());
+            }
+        }
+        Ok::<_, spi::Error>(sets)
+    })
+    .unwrap_or_else(|err| panic!("error reading synonym group {group_name}: {err:?}"))
+}
+
+/// A token filter that emits the synonyms of a token right after it, at the same
+/// position, so that a document containing any term of a set is indexed under all of them.
+#[derive(Clone)]
+pub struct SynonymFilter {
+    synonyms: Arc<SynonymMap>,
+}
+
+impl SynonymFilter {
+    pub fn new(synonyms: SynonymMap) -> Self {
+        Self {
+            synonyms: Arc::new(synonyms),
+        }
+    }
+}
+
+impl TokenFilter for SynonymFilter {
+    type Tokenizer<T: Tokenizer> = SynonymFilterWrapper<T>;
+
+    fn transform<T: Tokenizer>(self, tokenizer: T) -> Self::Tokenizer<T> {
+        SynonymFilterWrapper {
+            tokenizer,
+            synonyms: self.synonyms,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SynonymFilterWrapper<T> {
+    tokenizer: T,
+    synonyms: Arc<SynonymMap>,
+}
+
+impl<T: Tokenizer> Tokenizer for SynonymFilterWrapper<T> {
+    type TokenStream<'a> = SynonymTokenStream<'a, T::TokenStream<'a>>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        SynonymTokenStream {
+            tail: self.tokenizer.token_stream(text),
+            synonyms: &self.synonyms,
+            pending: vec![],
+            token: Token::default(),
+        }
+    }
+}
+
+pub struct SynonymTokenStream<'a, T> {
+    tail: T,
+    synonyms: &'a SynonymMap,
+    /// Synonyms of the current token that have yet to be emitted.
+    pending: Vec<String>,
+    token: Token,
+}
+
+impl<'a, T: TokenStream> TokenStream for SynonymTokenStream<'a, T> {
+    fn advance(&mut self) -> bool {
+        // Synonyms keep the position and offsets of the token they were expanded from.
+        if let Some(synonym) = self.pending.pop() {
+            self.token.text = synonym;
+            return true;
+        }
+
+        if !self.tail.advance() {
+            return false;
+        }
+
+        self.token = self.tail.token().clone();
+        if let Some(synonyms) = self.synonyms.get(&self.token.text) {
+            self.pending.extend(synonyms.iter().rev().cloned());
+        }
+        true
+    }
+
+    fn token(&self) -> &Token {
+        &self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::*;
+    use pgrx::*;
+    use shared::testing::SETUP_SQL;
+    use tantivy::tokenizer::{LowerCaser, SimpleTokenizer, TextAnalyzer};
+
+    fn synonym_sets() -> Vec<Vec<String>> {
+        vec![
+            vec!["Laptop".into(), "notebook".into()],
+            vec!["tv".into(), "television".into(), "flat screen".into()],
+        ]
+    }
+
+    #[pg_test]
+    fn test_synonym_map() {
+        let mut analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(LowerCaser)
+            .build();
+        let synonyms = SynonymMap::new(&synonym_sets(), &mut analyzer);
+
+        assert_eq!(synonyms.get("laptop"), Some(&["notebook".to_string()][..]));
+        assert_eq!(synonyms.get("tv"), Some(&["television".to_string()][..]));
+        // Multi-word entries are skipped.
+        assert_eq!(synonyms.get("flat"), None);
+        assert_eq!(synonyms.get("phone"), None);
+    }
+
+    #[pg_test]
+    fn test_synonym_filter() {
+        let mut base = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(LowerCaser)
+            .build();
+        let synonyms = SynonymMap::new(&synonym_sets(), &mut base);
+        let mut analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(LowerCaser)
+            .filter(SynonymFilter::new(synonyms))
+            .build();
+
+        let mut tokens: Vec<(String, usize)> = vec![];
+        let mut token_stream = analyzer.token_stream("new Laptop bag");
+        while token_stream.advance() {
+            let token = token_stream.token();
+            tokens.push((token.text.clone(), token.position));
+        }
+
+        assert_eq!(
+            tokens,
+            vec![
+                ("new".to_string(), 0),
+                ("laptop".to_string(), 1),
+                ("notebook".to_string(), 1),
+                ("bag".to_string(), 2),
+            ]
+        );
+    }
+
+    #[pg_test]
+    fn test_query_time_synonyms() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CALL paradedb.create_bm25(
+                index_name => 'synonym_songs',
+                table_name => 'one_republic_songs',
+                key_field => 'song_id',
+                text_fields => '{lyrics: {synonyms: {group: "songs"}}}'
+            );
+            "#,
+        )
+        .expect("failed to create index with synonyms");
+
+        let query = "SELECT COUNT(*) FROM synonym_songs.search('lyrics:tale')";
+        let count = Spi::get_one::<i64>(query).unwrap();
+        assert_eq!(count, Some(0));
+
+        // Query time synonyms are picked up without rebuilding the index.
+        Spi::run("INSERT INTO paradedb.synonyms (group_name, terms) VALUES ('songs', ARRAY['tale', 'story'])")
+            .expect("failed to insert synonyms");
+        let count = Spi::get_one::<i64>(query).unwrap();
+        assert_eq!(count, Some(1));
+    }
+
+    #[pg_test]
+    fn test_index_time_synonyms() {
+        crate::setup_background_workers();
+        Spi::run(SETUP_SQL).expect("failed to create index and table");
+        Spi::run(
+            r#"
+            CALL paradedb.create_bm25(
+                index_name => 'synonym_songs',
+                table_name => 'one_republic_songs',
+                key_field => 'song_id',
+                text_fields => '{lyrics: {synonyms: {expand: "index", sets: [["tale", "story"]]}}}'
+            );
+            "#,
+        )
+        .expect("failed to create index with synonyms");
+
+        let count = Spi::get_one::<i64>("SELECT COUNT(*) FROM synonym_songs.search('lyrics:tale')")
+            .unwrap();
+        assert_eq!(count, Some(1));
+    }
+}
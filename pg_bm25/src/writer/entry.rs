@@ -17,6 +17,9 @@ pub enum IndexError {
     #[error("couldn't get writer for index {0}: {1}")]
     GetWriterFailed(String, String),
 
+    #[error("couldn't promote index generation {0}: {1}")]
+    PromoteFailed(String, String),
+
     #[error("{0} has a type oid of InvalidOid")]
     InvalidOid(String),
 
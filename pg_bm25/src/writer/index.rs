@@ -1,8 +1,10 @@
 use super::{
     entry::{IndexEntry, IndexKey},
-    Handler, IndexError, ServerError, WriterRequest,
+    Handler, IndexError, ServerError, TransactionWrites, WriterRequest,
 };
+use crate::guc::PARADE_GUC;
 use crate::parade_index::index::ParadeIndex;
+use crate::parade_index::merge_policy::ParadeMergePolicy;
 use std::{
     collections::{
         hash_map::Entry::{Occupied, Vacant},
@@ -10,6 +12,7 @@ use std::{
     },
     fs,
     path::Path,
+    time::{Duration, Instant},
 };
 use tantivy::{
     schema::{Field, Value},
@@ -20,12 +23,18 @@ use tantivy::{
 pub struct Writer {
     /// Map of index directory path to Tantivy writer instance.
     tantivy_writers: HashMap<String, tantivy::IndexWriter>,
+    /// When a commit that's being coalesced with the commits of other transactions is due.
+    commit_deadline: Option<Instant>,
+    /// The outcome of the last coalesced commit, for the transactions that waited on it.
+    coalesced_commit_result: Option<Result<(), String>>,
 }
 
 impl Writer {
     pub fn new() -> Self {
         Self {
             tantivy_writers: HashMap::new(),
+            commit_deadline: None,
+            coalesced_commit_result: None,
         }
     }
 
@@ -90,15 +99,80 @@ impl Writer {
         Ok(())
     }
 
+    fn delete_all(&mut self, index_directory_path: &str) -> Result<(), IndexError> {
+        let writer = self.get_writer(index_directory_path)?;
+        writer.delete_all_documents()?;
+        Ok(())
+    }
+
     fn commit(&mut self) -> Result<(), IndexError> {
+        // Every commit covers the writes of the transactions waiting for a coalesced commit.
+        let coalesced = self.commit_deadline.take().is_some();
+        let result = self.commit_writers();
+        if coalesced {
+            self.coalesced_commit_result =
+                Some(result.as_ref().map_err(|err| err.to_string()).copied());
+        }
+        result
+    }
+
+    fn commit_writers(&mut self) -> Result<(), IndexError> {
+        let started_at = Instant::now();
         for writer in self.tantivy_writers.values_mut() {
             writer.prepare_commit()?;
             writer.commit()?;
         }
+
+        if PARADE_GUC.log_writer_commits.get() {
+            pgrx::log!(
+                "pg_bm25 writer committed {} indexes in {:?}",
+                self.tantivy_writers.len(),
+                started_at.elapsed()
+            );
+        }
         Ok(())
     }
 
-    fn abort(&mut self) -> Result<(), IndexError> {
+    /// Commit the writes of a committing transaction once the commit interval has passed
+    /// since the first of the transactions whose commits are being coalesced. The committing
+    /// transactions wait until then, so their writes are as durable as with their own commits.
+    fn coalesced_commit(&mut self) -> Result<(), IndexError> {
+        let commit_interval = PARADE_GUC.commit_interval_ms.get() as u64;
+        if commit_interval == 0 {
+            return self.commit();
+        }
+
+        let now = Instant::now();
+        let commit_deadline = *self
+            .commit_deadline
+            .get_or_insert(now + Duration::from_millis(commit_interval));
+        if commit_deadline <= now {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    fn abort(&mut self, writes: TransactionWrites) -> Result<(), IndexError> {
+        // Transactions that are waiting for a coalesced commit share the writers with the
+        // aborted transaction, so only its own writes are rolled back. No other transaction
+        // can have written to the indexes it built or emptied, and until VACUUM deletes them,
+        // no other row can have the ctid of a row it inserted.
+        if self.commit_deadline.is_some() {
+            for index_directory_path in &writes.discarded {
+                self.tantivy_writers.remove(index_directory_path);
+            }
+            for (index_directory_path, ctids) in writes.inserted {
+                let Some(writer) = self.tantivy_writers.get_mut(&index_directory_path) else {
+                    continue;
+                };
+                let ctid_field = writer.index().schema().get_field("ctid")?;
+                for ctid in ctids {
+                    writer.delete_term(tantivy::Term::from_field_u64(ctid_field, ctid));
+                }
+            }
+            return Ok(());
+        }
+
         // If the transaction was aborted, we should clear all the writers from the cache.
         // Otherwise, partialy written data could stick around for the next transaction.
         self.tantivy_writers.drain();
@@ -111,6 +185,45 @@ impl Writer {
         Ok(())
     }
 
+    /// Merge the smallest segments of an index together, so that it's left with at most
+    /// `max_segments` segments. This is done regardless of the index's merge policy.
+    /// Only committed segments are merged. The writer isn't committed first, because it can
+    /// hold the writes of transactions that are still in progress and might abort.
+    fn optimize(
+        &mut self,
+        index_directory_path: &str,
+        max_segments: usize,
+    ) -> Result<(), IndexError> {
+        let writer = self.get_writer(index_directory_path)?;
+        let mut segment_metas = writer.index().searchable_segment_metas()?;
+        if segment_metas.len() > max_segments {
+            segment_metas.sort_by_key(|segment_meta| segment_meta.num_docs());
+            let segment_ids: Vec<_> = segment_metas
+                .iter()
+                .take(segment_metas.len() - max_segments + 1)
+                .map(|segment_meta| segment_meta.id())
+                .collect();
+            writer.merge(&segment_ids).wait()?;
+        }
+
+        // The merged segments' files are only deleted once nothing uses them anymore.
+        writer.garbage_collect_files().wait()?;
+        Ok(())
+    }
+
+    /// Switch an index's writer over to a new merge policy. A writer that isn't open yet
+    /// reads the merge policy from the index's configuration when it's opened.
+    fn set_merge_policy(
+        &mut self,
+        index_directory_path: &str,
+        merge_policy: &ParadeMergePolicy,
+    ) -> Result<(), IndexError> {
+        if let Some(writer) = self.tantivy_writers.get(index_directory_path) {
+            writer.set_merge_policy(merge_policy.into());
+        }
+        Ok(())
+    }
+
     fn drop_index<T: AsRef<str>>(
         &mut self,
         index_directory_path: &str,
@@ -129,24 +242,50 @@ impl Writer {
                 std::mem::drop(writer);
             };
 
-            // Filter out non-existent paths and sort: files first, then directories.
-            let mut paths_to_delete: Vec<&str> =
-                paths_to_delete.iter().map(|p| p.as_ref()).collect();
-            paths_to_delete.retain(|path| Path::new(path).exists());
-            paths_to_delete.sort_by_key(|path| !Path::new(path).is_file());
-
-            // Iterate through the sorted list and delete each path.
-            for path in paths_to_delete {
-                // Even though we've filtered out the files that supposedly don't exist above,
-                // we can still see errors around files existing/not existing unexpectedly.
-                // we'll just check again here to be safe.
-                let path_ref = Path::new(&path);
-                if path_ref.try_exists()? {
-                    if path_ref.is_file() {
-                        fs::remove_file(path_ref)?;
-                    } else {
-                        fs::remove_dir_all(path_ref)?;
-                    }
+            Self::delete_paths(paths_to_delete)?;
+        }
+
+        Ok(())
+    }
+
+    /// Swap in a generation of an index that was built next to the one it replaces.
+    fn promote<T: AsRef<str>>(
+        &mut self,
+        index_directory_path: &str,
+        generation_directory_path: &str,
+        paths_to_delete: &[T],
+    ) -> Result<(), IndexError> {
+        self.commit()?;
+
+        // Both writers must be dropped, so that the lockfile of the new generation is
+        // released, and so that the next write to the index opens the new generation.
+        self.tantivy_writers.remove(index_directory_path);
+        self.tantivy_writers.remove(generation_directory_path);
+
+        ParadeIndex::promote(index_directory_path, generation_directory_path).map_err(|err| {
+            IndexError::PromoteFailed(generation_directory_path.to_string(), err.to_string())
+        })?;
+
+        Self::delete_paths(paths_to_delete)
+    }
+
+    fn delete_paths<T: AsRef<str>>(paths_to_delete: &[T]) -> Result<(), IndexError> {
+        // Filter out non-existent paths and sort: files first, then directories.
+        let mut paths_to_delete: Vec<&str> = paths_to_delete.iter().map(|p| p.as_ref()).collect();
+        paths_to_delete.retain(|path| Path::new(path).exists());
+        paths_to_delete.sort_by_key(|path| !Path::new(path).is_file());
+
+        // Iterate through the sorted list and delete each path.
+        for path in paths_to_delete {
+            // Even though we've filtered out the files that supposedly don't exist above,
+            // we can still see errors around files existing/not existing unexpectedly.
+            // we'll just check again here to be safe.
+            let path_ref = Path::new(&path);
+            if path_ref.try_exists()? {
+                if path_ref.is_file() {
+                    fs::remove_file(path_ref)?;
+                } else {
+                    fs::remove_dir_all(path_ref)?;
                 }
             }
         }
@@ -172,19 +311,64 @@ impl Handler<WriterRequest> for Writer {
             } => self
                 .delete(&index_directory_path, &field, &ctids)
                 .map_err(ServerError::from),
+            WriterRequest::DeleteAll {
+                index_directory_path,
+            } => self
+                .delete_all(&index_directory_path)
+                .map_err(ServerError::from),
             WriterRequest::DropIndex {
                 index_directory_path,
                 paths_to_delete,
             } => self
                 .drop_index(&index_directory_path, &paths_to_delete)
                 .map_err(ServerError::from),
+            WriterRequest::Promote {
+                index_directory_path,
+                generation_directory_path,
+                paths_to_delete,
+            } => self
+                .promote(
+                    &index_directory_path,
+                    &generation_directory_path,
+                    &paths_to_delete,
+                )
+                .map_err(ServerError::from),
             WriterRequest::Commit => self.commit().map_err(ServerError::from),
-            WriterRequest::Abort => self.abort().map_err(ServerError::from),
+            WriterRequest::CoalescedCommit => self.coalesced_commit().map_err(ServerError::from),
+            WriterRequest::Abort { writes } => self.abort(writes).map_err(ServerError::from),
             WriterRequest::Vacuum {
                 index_directory_path,
             } => self
                 .vacuum(&index_directory_path)
                 .map_err(ServerError::from),
+            WriterRequest::Optimize {
+                index_directory_path,
+                max_segments,
+            } => self
+                .optimize(&index_directory_path, max_segments)
+                .map_err(ServerError::from),
+            WriterRequest::SetMergePolicy {
+                index_directory_path,
+                merge_policy,
+            } => self
+                .set_merge_policy(&index_directory_path, &merge_policy)
+                .map_err(ServerError::from),
         }
     }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.commit_deadline
+    }
+
+    fn tick(&mut self) -> Result<(), ServerError> {
+        self.commit().map_err(ServerError::from)
+    }
+
+    fn waits(&self, request: &WriterRequest) -> bool {
+        matches!(request, WriterRequest::CoalescedCommit) && PARADE_GUC.commit_interval_ms.get() > 0
+    }
+
+    fn take_waited(&mut self) -> Option<Result<(), String>> {
+        self.coalesced_commit_result.take()
+    }
 }
@@ -5,10 +5,13 @@ mod server;
 mod transfer;
 
 pub use self::entry::{IndexEntry, IndexError, IndexKey, IndexValue};
+use crate::parade_index::merge_policy::ParadeMergePolicy;
 pub use client::{Client, ClientError};
 pub use index::Writer;
 use serde::{Deserialize, Serialize};
 pub use server::{Server, ServerError};
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 use tantivy::schema::Field;
 
 // A layer of the client-server request structure that handles
@@ -25,15 +28,70 @@ pub enum WriterRequest {
         field: Field,
         ctids: Vec<u64>,
     },
+    /// Deletes every document of an index, as one of the writes of the current transaction.
+    DeleteAll {
+        index_directory_path: String,
+    },
     DropIndex {
         index_directory_path: String,
         paths_to_delete: Vec<String>,
     },
-    Abort,
+    Promote {
+        index_directory_path: String,
+        generation_directory_path: String,
+        paths_to_delete: Vec<String>,
+    },
+    /// Rolls back the writes of a transaction that aborted.
+    Abort {
+        writes: TransactionWrites,
+    },
     Commit,
+    /// Commits the writes of a transaction that's committing. The commit can wait for up to
+    /// the commit interval, so that transactions that commit in the meantime share it. The
+    /// transaction isn't answered until its writes are committed.
+    CoalescedCommit,
     Vacuum {
         index_directory_path: String,
     },
+    Optimize {
+        index_directory_path: String,
+        max_segments: usize,
+    },
+    SetMergePolicy {
+        index_directory_path: String,
+        merge_policy: ParadeMergePolicy,
+    },
+}
+
+/// The writes of a transaction, so that they can be rolled back on their own if it aborts
+/// while other transactions are waiting for a coalesced commit.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TransactionWrites {
+    /// The ctids of the rows the transaction inserted, by index directory.
+    pub inserted: HashMap<String, Vec<u64>>,
+    /// The directories of the indexes that only the transaction wrote to, because it built or
+    /// emptied them, so all their uncommitted writes can be discarded.
+    pub discarded: HashSet<String>,
+}
+
+impl TransactionWrites {
+    pub fn insert(&mut self, index_directory_path: &str, ctid: u64) {
+        if self.discarded.contains(index_directory_path) {
+            return;
+        }
+        match self.inserted.get_mut(index_directory_path) {
+            Some(ctids) => ctids.push(ctid),
+            None => {
+                self.inserted
+                    .insert(index_directory_path.to_string(), vec![ctid]);
+            }
+        }
+    }
+
+    pub fn discard(&mut self, index_directory_path: &str) {
+        self.inserted.remove(index_directory_path);
+        self.discarded.insert(index_directory_path.to_string());
+    }
 }
 
 // A layer of the client-server request structure that handles
@@ -54,4 +112,27 @@ enum ServerRequest<R: Serialize> {
 /// and re-used independently.
 pub trait Handler<T: Serialize> {
     fn handle(&mut self, request: T) -> Result<(), ServerError>;
+
+    /// When the handler has work that it put off, like a commit it's waiting to coalesce,
+    /// the time by which the server must call `tick` to have it done.
+    fn deadline(&self) -> Option<Instant> {
+        None
+    }
+
+    fn tick(&mut self) -> Result<(), ServerError> {
+        Ok(())
+    }
+
+    /// Whether the client that sent a request is only answered once the work the handler
+    /// put off is done, like a transaction that waits for the commit its writes are coalesced
+    /// into.
+    fn waits(&self, _request: &T) -> bool {
+        false
+    }
+
+    /// The outcome of the work the handler put off, once it's done, for the clients that
+    /// have been waiting on it.
+    fn take_waited(&mut self) -> Option<Result<(), String>> {
+        None
+    }
 }
@@ -5,6 +5,7 @@ use serde::{de::DeserializeOwned, Serialize};
 use std::cell::RefCell;
 use std::marker::PhantomData;
 use std::path::Path;
+use std::time::Instant;
 use thiserror::Error;
 
 /// A generic server for receiving requests and transfers from a client.
@@ -12,6 +13,8 @@ pub struct Server<'a, T: Serialize + DeserializeOwned + 'a, H: Handler<T>> {
     addr: std::net::SocketAddr,
     http: tiny_http::Server,
     handler: RefCell<H>,
+    /// Requests whose clients are waiting for work the handler put off.
+    waiting: Vec<tiny_http::Request>,
     marker: PhantomData<&'a T>,
 }
 
@@ -34,6 +37,7 @@ impl<'a, T: Serialize + DeserializeOwned + 'a, H: Handler<T>> Server<'a, T, H> {
             addr,
             http,
             handler: RefCell::new(handler),
+            waiting: vec![],
             marker: PhantomData,
         })
     }
@@ -56,7 +60,27 @@ impl<'a, T: Serialize + DeserializeOwned + 'a, H: Handler<T>> Server<'a, T, H> {
 
     fn listen_request(&mut self) -> Result<(), ServerError> {
         pgrx::log!("listening to incoming requests at {:?}", self.addr);
-        for mut incoming in self.http.incoming_requests() {
+        loop {
+            // Work the handler put off is done once it's due, even while requests keep coming.
+            let deadline = self.handler.borrow().deadline();
+            if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+                if let Err(err) = self.handler.borrow_mut().tick() {
+                    pgrx::log!("writer server failed to do deferred work: {err:?}");
+                }
+                self.answer_waiting();
+                continue;
+            }
+
+            let incoming = match deadline {
+                Some(deadline) => self
+                    .http
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now()))?,
+                None => Some(self.http.recv()?),
+            };
+            let Some(mut incoming) = incoming else {
+                continue;
+            };
+
             let reader = incoming.as_reader();
             let request: Result<ServerRequest<T>, ServerError> =
                 serde_json::from_reader(reader).map_err(ServerError::SerdeJsonError);
@@ -73,12 +97,24 @@ impl<'a, T: Serialize + DeserializeOwned + 'a, H: Handler<T>> Server<'a, T, H> {
                         Ok(()) // We must respond with OK before initiating the transfer.
                     }
                     ServerRequest::Request(writer_request) => {
-                        self.handler.borrow_mut().handle(writer_request)
+                        let waits = self.handler.borrow().waits(&writer_request);
+                        let response = self.handler.borrow_mut().handle(writer_request);
+                        if waits && response.is_ok() {
+                            // The client is answered once the work it's waiting on is done,
+                            // which might be right away.
+                            self.waiting.push(incoming);
+                            self.answer_waiting();
+                            continue;
+                        }
+                        response
                     }
                 },
                 Err(err) => Err(err),
             };
 
+            // The request might have done the work that other clients are waiting on.
+            self.answer_waiting();
+
             // Try to respond to the client. This could fail if the client has disconnected.
             if let Err(err) = match response {
                 Ok(()) => incoming.respond(tiny_http::Response::empty(200)),
@@ -95,8 +131,23 @@ impl<'a, T: Serialize + DeserializeOwned + 'a, H: Handler<T>> Server<'a, T, H> {
                 self.listen_transfer(pipe_path)?
             }
         }
+    }
 
-        unreachable!("server should never stop listening");
+    /// Answers the clients that are waiting for work the handler put off, if it's done.
+    fn answer_waiting(&mut self) {
+        let Some(result) = self.handler.borrow_mut().take_waited() else {
+            return;
+        };
+
+        for incoming in self.waiting.drain(..) {
+            if let Err(err) = match &result {
+                Ok(()) => incoming.respond(tiny_http::Response::empty(200)),
+                Err(err) => incoming
+                    .respond(tiny_http::Response::from_string(err.clone()).with_status_code(500)),
+            } {
+                pgrx::log!("writer server failed to respond to waiting client: {err:?}");
+            }
+        }
     }
 }
 
@@ -8,6 +8,7 @@ CALL paradedb.create_bm25_test_table(table_name => 'search_config', schema_name
 CALL paradedb.create_bm25_test_table(table_name => 'tokenizer_config', schema_name => 'paradedb');
 CALL paradedb.create_bm25_test_table(table_name => 'bm25_search', schema_name => 'paradedb');
 CALL paradedb.create_bm25_test_table(table_name => 'aggregations', schema_name => 'paradedb');
+CALL paradedb.create_bm25_test_table(table_name => 'bm25_functions', schema_name => 'paradedb');
 
 CALL paradedb.create_bm25(
 	index_name => 'index_config',
@@ -53,3 +54,13 @@ CALL paradedb.create_bm25(
 	json_fields => '{"metadata": {}}'
 );
 
+CALL paradedb.create_bm25(
+	index_name => 'bm25_functions',
+	table_name => 'bm25_functions',
+	schema_name => 'paradedb',
+    key_field => 'id',
+    text_fields => '{"description": {}, "category": {}}',
+	numeric_fields => '{"rating": {}}',
+	boolean_fields => '{"in_stock": {}}',
+	json_fields => '{"metadata": {}}'
+);
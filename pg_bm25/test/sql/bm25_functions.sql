@@ -0,0 +1,59 @@
+-- Tokenize text with a tokenizer
+SELECT * FROM paradedb.tokenize('{"type": "en_stem"}', 'Running Shoes');
+-- Tokenize text with a tokenizer and filters
+SELECT * FROM paradedb.tokenize('{"type": "whitespace", "filters": [{"type": "lowercase"}, {"type": "stopwords", "language": "english"}]}', 'The Running Shoes');
+-- Tokenize text the way a field is indexed and queried
+SELECT * FROM paradedb.tokenize_field('bm25_functions', 'description', 'Ergonomic metal keyboard');
+SELECT * FROM paradedb.tokenize_field('bm25_functions', 'description', 'Ergonomic metal keyboard', query => true);
+-- Fuse a BM25 search with a similarity ranking
+SELECT id FROM paradedb.rank_hybrid(
+	jsonb_build_object(
+		'query', 'description:keyboard',
+		'index_name', 'bm25_functions_bm25_index',
+		'schema_name', 'paradedb',
+		'table_name', 'bm25_functions',
+		'key_field', 'id'
+	),
+	similarity_keys => ARRAY[2, 5]::bigint[]
+) ORDER BY rank_hybrid DESC, id;
+-- Similarity keys are ranked by ascending distance
+SELECT id FROM paradedb.rank_hybrid(
+	jsonb_build_object(
+		'query', 'description:keyboard',
+		'index_name', 'bm25_functions_bm25_index',
+		'schema_name', 'paradedb',
+		'table_name', 'bm25_functions',
+		'key_field', 'id'
+	),
+	similarity_keys => ARRAY[2, 5]::bigint[],
+	similarity_scores => ARRAY[0.5, 0.1]::real[],
+	strategy => 'minmax',
+	bm25_weight => 0.4,
+	similarity_weight => 0.6
+) ORDER BY rank_hybrid DESC, id;
+-- Explain the score of a row
+WITH config AS (
+	SELECT jsonb_build_object(
+		'query', 'description:keyboard',
+		'index_name', 'bm25_functions_bm25_index',
+		'schema_name', 'paradedb',
+		'table_name', 'bm25_functions',
+		'key_field', 'id'
+	) AS config
+)
+SELECT
+	paradedb.explain_bm25(config, 2)::text LIKE '%idf%' AS explained,
+	paradedb.explain_bm25(config, 3) IS NULL AS unmatched
+FROM config;
+-- Index statistics
+SELECT stats->'num_docs' AS num_docs, stats->'num_deleted_docs' AS num_deleted_docs
+FROM paradedb.index_stats_bm25('bm25_functions') AS stats;
+SELECT jsonb_object_keys(paradedb.index_stats_bm25('bm25_functions')->'fields') AS field ORDER BY field;
+-- Optimize the index into a single segment
+SELECT paradedb.optimize_bm25('bm25_functions');
+SELECT stats->'num_docs' AS num_docs, stats->'num_segments' AS num_segments
+FROM paradedb.index_stats_bm25('bm25_functions') AS stats;
+-- Garbage collection leaves live indexes alone
+SELECT count(*) FROM paradedb.gc_bm25()
+WHERE path ~ ('/' || 'bm25_functions_bm25_index'::regclass::oid || '(\.[0-9]+)?$');
+SELECT id FROM bm25_functions.search('description:keyboard') ORDER BY id;